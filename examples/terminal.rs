@@ -8,9 +8,9 @@ extern crate relaunch;
 use std::{io::Write, process::ExitCode};
 
 fn main() {
-    relaunch::Trampoline::new("re-Terminal", "com.github.maaku.relauncher.Terminal")
-        .version("1.0.0")
-        .run_once(relaunch::InstallDir::Temp, |app| {
+    relaunch::trampoline!("re-Terminal", "com.github.maaku.relauncher.Terminal").run_once(
+        relaunch::InstallDir::Temp,
+        |app| {
             // Vefify we are now running as a bundled application.
             assert!(relaunch::Trampoline::is_bundled());
 
@@ -36,7 +36,8 @@ fn main() {
             }
             println!("Hello, {}!", name);
             ExitCode::SUCCESS
-        })
+        },
+    )
 }
 
 // End of File
@@ -0,0 +1,201 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Build-script helpers, enabled by the `build` feature, for configuring a
+//! [`Trampoline`] from the `[package.metadata.relaunch]` table of the
+//! application's `Cargo.toml`:
+//!
+//! ```toml
+//! [package.metadata.relaunch]
+//! name = "My App"
+//! identifier = "org.example.MyApp"
+//! category = "public.app-category.developer-tools"
+//! info-plist = "macos/Info.plist"
+//! icon = ["macos/icon-1024.png", "macos/icon-32.png"]
+//!
+//! [package.metadata.relaunch.plist]
+//! NSHumanReadableCopyright = "Copyright (c) 2024 Example Corp."
+//! ```
+//!
+//! Add `relaunch` as a build dependency with the `build` feature, and call
+//! [`embed_metadata()`] from `build.rs`:
+//!
+//! ```no_run
+//! // build.rs
+//! relaunch::build::embed_metadata().unwrap();
+//! ```
+//!
+//! The application then creates its trampoline with
+//! `Trampoline::from_cargo_metadata(relaunch::cargo_metadata!())`.
+//!
+//! Supported keys are `name` and `version`, which default to those of the
+//! package; `identifier`, which is required; `category`, which sets
+//! `LSApplicationCategoryType`; `info-plist`, a path to an `Info.plist`
+//! template; `icon`, the path of a PNG icon image or an array of them; and
+//! `plist`, a table of custom `Info.plist` keys.  Paths are relative to the
+//! manifest.  Unknown keys are rejected, to catch typos.
+//!
+//! The template and icon files are read by the build script and their
+//! contents embedded in the executable, so the application does not depend
+//! on the source tree being present when it runs.  Cargo is asked to rerun
+//! the build script whenever the manifest or one of these files changes.
+//!
+//! [`Trampoline`]: crate::Trampoline
+
+use crate::{
+    package::{self, CargoMetadata},
+    plist::{self, Dictionary, Value},
+    Error, PackageInfo,
+};
+use std::path::{Path, PathBuf};
+
+/// The environment variable, set for the application crate by
+/// [`embed_metadata()`], which holds the path of the embedded metadata.
+pub const METADATA_ENV: &str = "RELAUNCH_METADATA";
+
+/// Read `[package.metadata.relaunch]` from the manifest of the package being
+/// built, and write it to `OUT_DIR` for [`cargo_metadata!`] to embed.  Must
+/// be called from a build script.
+///
+/// [`cargo_metadata!`]: crate::cargo_metadata
+pub fn embed_metadata() -> Result<PathBuf, Error> {
+    let env = |var: &str| {
+        std::env::var_os(var).ok_or_else(|| Error::CargoMetadata {
+            path: None,
+            message: format!(
+                "{} is not set; embed_metadata() must be called from a build script",
+                var
+            ),
+        })
+    };
+    let manifest = PathBuf::from(env("CARGO_MANIFEST_DIR")?).join("Cargo.toml");
+    let out = PathBuf::from(env("OUT_DIR")?).join("relaunch-metadata.plist");
+
+    // Cargo resolves workspace inheritance for these, so prefer them over
+    // the raw manifest.
+    let var = |var: &str| std::env::var(var).unwrap_or_default();
+    let package = PackageInfo::new(
+        &var("CARGO_PKG_NAME"),
+        &var("CARGO_PKG_VERSION"),
+        &var("CARGO_PKG_AUTHORS"),
+        &var("CARGO_PKG_DESCRIPTION"),
+    );
+    let (metadata, files) = read(&manifest, package)?;
+    for path in std::iter::once(&manifest).chain(&files) {
+        println!("cargo:rerun-if-changed={}", path.display());
+    }
+
+    let text =
+        plist::xml::to_string(&Value::Dictionary(metadata)).map_err(|err| Error::io(&out, err))?;
+    std::fs::write(&out, text).map_err(|err| Error::io(&out, err))?;
+    println!("cargo:rustc-env={}={}", METADATA_ENV, out.display());
+    Ok(out)
+}
+
+/// Read and check `[package.metadata.relaunch]` from the manifest at
+/// `manifest`, returning the dictionary which [`embed_metadata()`] embeds.
+/// Relative paths are resolved against the manifest's directory, and the
+/// `info-plist` and `icon` files they name are read into the dictionary as
+/// data.
+pub fn read_metadata(manifest: &Path, package: PackageInfo) -> Result<Dictionary, Error> {
+    read(manifest, package).map(|(metadata, _)| metadata)
+}
+
+/// Implementation of [`read_metadata()`], which also returns the paths of
+/// the files whose contents were embedded.
+fn read(manifest: &Path, package: PackageInfo) -> Result<(Dictionary, Vec<PathBuf>), Error> {
+    let error = |message: String| Error::CargoMetadata {
+        path: Some(manifest.to_path_buf()),
+        message,
+    };
+    let text = std::fs::read_to_string(manifest).map_err(|err| Error::io(manifest, err))?;
+    let document: toml::Table = text
+        .parse()
+        .map_err(|err: toml::de::Error| error(err.to_string()))?;
+    let table = document
+        .get("package")
+        .and_then(|package| package.get("metadata"))
+        .and_then(|metadata| metadata.get("relaunch"))
+        .ok_or_else(|| error("no [package.metadata.relaunch] table".to_string()))?
+        .as_table()
+        .ok_or_else(|| error("package.metadata.relaunch is not a table".to_string()))?;
+    let base = manifest.parent().unwrap_or(Path::new("."));
+    let mut files = Vec::new();
+    let mut read_file = |path: &str| {
+        let path = base.join(path);
+        let bytes = std::fs::read(&path).map_err(|err| Error::io(&path, err))?;
+        files.push(path);
+        Ok::<_, Error>(Value::Data(bytes))
+    };
+
+    let mut relaunch = Dictionary::new();
+    for (key, value) in table {
+        let string = || {
+            value
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| error(format!("`{}` must be a string", key)))
+        };
+        let value: Value = match key.as_str() {
+            package::NAME | package::IDENTIFIER | package::VERSION | package::CATEGORY => {
+                string()?.into()
+            }
+            package::INFO_PLIST => read_file(&string()?)?,
+            package::ICON => {
+                let paths = match value {
+                    toml::Value::Array(paths) => paths.iter().collect(),
+                    path => vec![path],
+                };
+                let icons = paths
+                    .into_iter()
+                    .map(|path| match path.as_str() {
+                        Some(path) => read_file(path),
+                        None => Err(error(
+                            "`icon` must be a path or an array of paths".to_string(),
+                        )),
+                    })
+                    .collect::<Result<_, _>>()?;
+                Value::Array(icons)
+            }
+            package::PLIST => {
+                match from_toml(value).map_err(|message| error(format!("`plist`: {}", message)))? {
+                    value @ Value::Dictionary(_) => value,
+                    _ => return Err(error("`plist` must be a table".to_string())),
+                }
+            }
+            _ => return Err(error(format!("unknown key `{}`", key))),
+        };
+        relaunch.insert(key.clone(), value);
+    }
+
+    let metadata = CargoMetadata::to_dictionary(&package, base, relaunch);
+    // Decode what we are about to embed, so mistakes surface at build time.
+    CargoMetadata::from_dictionary(metadata.clone()).map_err(|err| error(err.to_string()))?;
+    Ok((metadata, files))
+}
+
+/// Convert a TOML value into the equivalent property list value.
+fn from_toml(value: &toml::Value) -> Result<Value, String> {
+    Ok(match value {
+        toml::Value::String(s) => s.as_str().into(),
+        toml::Value::Integer(i) => (*i).into(),
+        toml::Value::Float(f) => (*f).into(),
+        toml::Value::Boolean(b) => (*b).into(),
+        toml::Value::Datetime(d) => {
+            return Err(format!("dates are not supported, found {}", d));
+        }
+        toml::Value::Array(array) => {
+            Value::Array(array.iter().map(from_toml).collect::<Result<_, _>>()?)
+        }
+        toml::Value::Table(table) => Value::Dictionary(
+            table
+                .iter()
+                .map(|(key, value)| Ok((key.clone(), from_toml(value)?)))
+                .collect::<Result<_, String>>()?,
+        ),
+    })
+}
+
+// End of File
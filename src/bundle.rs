@@ -0,0 +1,366 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Platform-neutral generation of macOS app bundles.
+//!
+//! The layout of an app bundle is just a directory tree and a property list,
+//! so there is nothing about generating one that requires running on macOS.
+//! The [`BundleBuilder`] writes the complete `.app` directory for a
+//! [`Trampoline`] without launching anything, which allows bundles to be
+//! generated and inspected from any host.
+
+use crate::{
+    macho::{self, MachO},
+    manifest::{self, Digest, Manifest, Source, Stamp},
+    plist::Format,
+    strings, Error, Trampoline, Warning,
+};
+use std::path::{Path, PathBuf};
+
+/// Writes the `.app` directory tree for a [`Trampoline`] into an install
+/// directory:
+///
+/// ```text
+/// <install_path>/<name>.app/
+///     Contents/
+///         Info.plist
+///         MacOS/
+///             <executable>
+///         Frameworks/
+///             <dynamic libraries>
+///         Resources/
+///             AppIcon.icns
+///             <locale>.lproj/InfoPlist.strings
+///             <resources>
+/// ```
+///
+/// `AppIcon.icns` is only written if the trampoline has an icon, and
+/// `Frameworks` only if [`Trampoline::embed_dylibs()`] is set and the
+/// executable links against non-system libraries.  If the trampoline has
+/// [`Trampoline::entitlements()`], they are written next to the bundle as
+/// `<install_path>/<name>.entitlements`, for the signing step.
+///
+/// A manifest of what went into the bundle is kept in
+/// `Contents/_CodeSignature`, which code signing does not seal, so that
+/// building again over an existing bundle only writes the pieces whose
+/// inputs have changed: the executable and `Frameworks`, `Resources`, and
+/// `Info.plist`, each of which is replaced as a whole.  An existing bundle
+/// without a manifest, whose executable has changed since the manifest was
+/// written, or any existing bundle if [`Trampoline::force_rebuild()`] is
+/// set, is removed first.
+pub struct BundleBuilder<'a> {
+    /// The application metadata to write into the bundle.
+    trampoline: &'a Trampoline,
+    /// The executable to copy into `Contents/MacOS`.
+    executable: PathBuf,
+    /// The directory in which the `.app` bundle will be created.
+    install_path: PathBuf,
+    /// The encoding to use for `Info.plist`.
+    plist_format: Format,
+}
+
+/// The paths of a bundle written by [`BundleBuilder::build()`].
+#[derive(Clone, Debug)]
+pub struct Bundle {
+    /// The path to the `.app` bundle directory.
+    pub path: PathBuf,
+    /// The path to the copied executable within `Contents/MacOS`.
+    pub executable: PathBuf,
+    /// The path of the `.entitlements` file written next to the bundle, to
+    /// pass to `codesign --entitlements`, if the trampoline has any.
+    pub entitlements: Option<PathBuf>,
+    /// Likely mistakes in the configuration, as found by
+    /// [`Trampoline::warnings()`].
+    pub warnings: Vec<Warning>,
+}
+
+impl<'a> BundleBuilder<'a> {
+    /// Create a builder which will bundle `executable` as the application
+    /// described by `trampoline`, placing the bundle in `install_path`.
+    pub fn new<P, Q>(trampoline: &'a Trampoline, executable: P, install_path: Q) -> Self
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        BundleBuilder {
+            trampoline,
+            executable: executable.as_ref().to_path_buf(),
+            install_path: install_path.as_ref().to_path_buf(),
+            plist_format: Format::Xml,
+        }
+    }
+
+    /// Set the encoding of the generated `Info.plist`.  Defaults to XML, but
+    /// the binary format is smaller and is what Xcode emits for release
+    /// builds.
+    pub fn plist_format(&mut self, format: Format) -> &mut Self {
+        self.plist_format = format;
+        self
+    }
+
+    /// The path of the `.app` bundle directory which will be generated.
+    pub fn bundle_path(&self) -> PathBuf {
+        self.install_path
+            .join(format!("{}.app", self.trampoline.name))
+    }
+
+    /// The file name of the executable, as it will appear within
+    /// `Contents/MacOS` and in the `CFBundleExecutable` key.
+    pub fn executable_name(&self) -> Result<String, Error> {
+        self.executable
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(|name| name.to_string())
+            .ok_or_else(|| Error::ExecutableName {
+                path: self.executable.clone(),
+            })
+    }
+
+    /// The executables which make up the bundled executable: the
+    /// per-architecture builds given to [`Trampoline::universal_binary()`],
+    /// or else the executable itself.
+    fn sources(&self) -> Vec<&Path> {
+        match self.trampoline.universal.is_empty() {
+            true => vec![self.executable.as_path()],
+            false => self
+                .trampoline
+                .universal
+                .iter()
+                .map(PathBuf::as_path)
+                .collect(),
+        }
+    }
+
+    /// Combine the per-architecture builds into a universal binary, or
+    /// return `None` if there are none.
+    fn universal_binary(&self) -> Result<Option<Vec<u8>>, Error> {
+        let paths = &self.trampoline.universal;
+        if paths.is_empty() {
+            return Ok(None);
+        }
+        let files = paths
+            .iter()
+            .map(|path| std::fs::read(path).map_err(|err| Error::io(path, err)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let files: Vec<&[u8]> = files.iter().map(Vec::as_slice).collect();
+        macho::build_fat(&files, self.trampoline.fat64)
+            .map(Some)
+            .map_err(|source| Error::UniversalBinary {
+                paths: paths.clone(),
+                source,
+            })
+    }
+
+    /// Generate the bundle on the filesystem.  Nothing is launched.  The
+    /// trampoline is validated, the universal binary assembled, the icon
+    /// encoded, the resources checked, and the dynamic libraries to embed
+    /// found first, so an invalid name, identifier, architecture, icon image,
+    /// missing resource, or missing library fails without touching the
+    /// filesystem.  The pieces of an existing bundle whose inputs are
+    /// unchanged are left as they are.
+    pub fn build(&self) -> Result<Bundle, Error> {
+        self.trampoline.validate()?;
+        let bundle_path = self.bundle_path();
+        let contents_path = bundle_path.join("Contents");
+        let macos_path = contents_path.join("MacOS");
+        let resources_path = contents_path.join("Resources");
+        let plist = contents_path.join("Info.plist");
+
+        let exe_name = self.executable_name()?;
+        let dst_exe = macos_path.join(&exe_name);
+        let universal = self.universal_binary()?;
+        let binary = match (self.trampoline.inspect_executable, &universal) {
+            (false, _) => None,
+            (true, Some(bytes)) => Some(MachO::parse(bytes).map_err(|source| Error::MachO {
+                path: self.executable.clone(),
+                source,
+            })?),
+            (true, None) => Some(MachO::read(&self.executable)?),
+        };
+        let info_plist = self.trampoline.info_plist_for(&exe_name, binary.as_ref())?;
+        let warnings = self.trampoline.warnings_for(&info_plist, binary.as_ref());
+        let icns = self.trampoline.icns()?;
+        let localizations = self.trampoline.localized_strings()?;
+        let entitlements_path = self
+            .install_path
+            .join(format!("{}.entitlements", self.trampoline.name));
+        let entitlements_xml = match &self.trampoline.entitlements {
+            Some(entitlements) => {
+                entitlements.validate().map_err(Error::Entitlements)?;
+                let xml = entitlements.to_xml();
+                Some(xml.map_err(|err| Error::io(&entitlements_path, err))?)
+            }
+            None => None,
+        };
+        for resource in &self.trampoline.resources {
+            resource.check()?;
+        }
+        let dylibs = match self.trampoline.embed_dylibs {
+            true => Some(crate::frameworks::plan(self.sources())?),
+            false => None,
+        };
+
+        let mut info_bytes = Vec::new();
+        info_plist
+            .write(&mut info_bytes, self.plist_format)
+            .map_err(|err| Error::io(&plist, err))?;
+        let localizations: Vec<_> = localizations
+            .into_iter()
+            .map(|(locale, strings)| (locale, strings::to_bytes(&strings)))
+            .collect();
+
+        // Work out which pieces of an existing bundle are out of date, from
+        // the digests of their inputs.
+        // The manifest only describes the bundle while the executable is
+        // the one it was written with.
+        let manifest_path = manifest::path(&bundle_path);
+        let previous = match self.trampoline.force_rebuild {
+            true => None,
+            false => Manifest::load(&manifest_path)
+                .filter(|manifest| Stamp::read(&dst_exe).ok() == Some(manifest.bundled)),
+        };
+        let known = previous.as_ref().map_or(&[][..], |m| m.sources.as_slice());
+        let sources = self
+            .sources()
+            .into_iter()
+            .map(|path| {
+                Source::read(path, known).map_err(|source| Error::CopyExecutable {
+                    from: path.to_path_buf(),
+                    to: dst_exe.clone(),
+                    source,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut digest = Digest::new();
+        digest.add(&exe_name).add(&self.trampoline.fat64);
+        for source in &sources {
+            digest.add(&source.digest);
+        }
+        digest.add(&entitlements_xml);
+        if let Some(dylibs) = &dylibs {
+            for library in dylibs.sources() {
+                digest.tree(library)?;
+            }
+        }
+        let executable_digest = digest.finish();
+        let mut digest = Digest::new();
+        for resource in &self.trampoline.resources {
+            resource.fingerprint(&mut digest)?;
+        }
+        digest.add(&localizations).add(&icns);
+        let resources_digest = digest.finish();
+        let info_plist_digest = Digest::new().add(&info_bytes).finish();
+
+        let (write_executable, write_resources, write_plist) = match &previous {
+            Some(previous) => (
+                previous.executable != executable_digest,
+                previous.resources != resources_digest,
+                previous.info_plist != info_plist_digest || !plist.is_file(),
+            ),
+            None => (true, true, true),
+        };
+        match &previous {
+            // Remove the manifest until the bundle is complete again, in case
+            // writing it fails part way.
+            Some(_) if write_executable || write_resources || write_plist => {
+                std::fs::remove_file(&manifest_path)
+                    .map_err(|err| Error::io(&manifest_path, err))?;
+            }
+            Some(_) => {}
+            // Remove the app bundle if it already exists (e.g. from a
+            // previous run).
+            None => remove_dir(&bundle_path)?,
+        }
+        // Write the entitlements first, so that they can be used to sign the
+        // executable.
+        let entitlements = match &entitlements_xml {
+            Some(xml) => {
+                std::fs::write(&entitlements_path, xml)
+                    .map_err(|err| Error::io(&entitlements_path, err))?;
+                Some(entitlements_path)
+            }
+            None => None,
+        };
+
+        if write_executable {
+            let frameworks = contents_path.join("Frameworks");
+            remove_dir(&macos_path)?;
+            remove_dir(&frameworks)?;
+            std::fs::create_dir_all(&macos_path).map_err(|err| Error::io(&macos_path, err))?;
+            // Copy the executable to the MacOS directory, or write the
+            // universal binary with the permissions of the first build.
+            let first = self.sources()[0];
+            let copy = || match &universal {
+                Some(bytes) => {
+                    std::fs::write(&dst_exe, bytes)?;
+                    std::fs::set_permissions(&dst_exe, std::fs::metadata(first)?.permissions())
+                }
+                None => std::fs::copy(first, &dst_exe).map(|_| ()),
+            };
+            copy().map_err(|source| Error::CopyExecutable {
+                from: first.to_path_buf(),
+                to: dst_exe.clone(),
+                source,
+            })?;
+            if let Some(dylibs) = dylibs {
+                dylibs.apply(&dst_exe, &frameworks, entitlements.as_deref())?;
+            }
+        }
+
+        if write_resources {
+            // Copy the resources, then write the localized strings and the
+            // application icon, which take precedence over resources of the
+            // same name.
+            remove_dir(&resources_path)?;
+            std::fs::create_dir_all(&resources_path)
+                .map_err(|err| Error::io(&resources_path, err))?;
+            for resource in &self.trampoline.resources {
+                resource.copy(&resources_path)?;
+            }
+            for (locale, strings) in &localizations {
+                let lproj = resources_path.join(format!("{}.lproj", locale));
+                let path = lproj.join("InfoPlist.strings");
+                std::fs::create_dir_all(&lproj).map_err(|err| Error::io(&lproj, err))?;
+                std::fs::write(&path, strings).map_err(|err| Error::io(&path, err))?;
+            }
+            if let Some(icns) = icns {
+                let path = resources_path.join(format!("{}.icns", crate::icon::ICON_FILE));
+                std::fs::write(&path, icns).map_err(|err| Error::io(&path, err))?;
+            }
+        }
+
+        if write_plist {
+            std::fs::write(&plist, &info_bytes).map_err(|err| Error::io(&plist, err))?;
+        }
+
+        if write_executable || write_resources || write_plist {
+            let manifest = Manifest {
+                sources,
+                bundled: Stamp::read(&dst_exe).map_err(|err| Error::io(&dst_exe, err))?,
+                executable: executable_digest,
+                resources: resources_digest,
+                info_plist: info_plist_digest,
+            };
+            manifest.save(&manifest_path)?;
+        }
+
+        Ok(Bundle {
+            path: bundle_path,
+            executable: dst_exe,
+            entitlements,
+            warnings,
+        })
+    }
+}
+
+/// Remove the directory tree at `path`, if there is one.
+fn remove_dir(path: &Path) -> Result<(), Error> {
+    match path.try_exists().map_err(|err| Error::io(path, err))? {
+        true => std::fs::remove_dir_all(path).map_err(|err| Error::io(path, err)),
+        false => Ok(()),
+    }
+}
+
+// End of File
@@ -0,0 +1,371 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Declarations of the kinds of files an application can open, so that the
+//! Finder opens them with it on double-click.
+//!
+//! A [`DocumentType`] becomes an entry of `CFBundleDocumentTypes`, listing
+//! the files the application handles and in what role.  Files are matched by
+//! uniform type identifier (UTI), such as `public.png`, and a new file
+//! format also needs a [`TypeDeclaration`] which defines its UTI, file name
+//! extensions, and MIME types.  A format the application owns is declared
+//! in `UTExportedTypeDeclarations`, and one owned by another application,
+//! which may not be installed, in `UTImportedTypeDeclarations`:
+//!
+//! ```
+//! use relaunch::document::{DocumentType, Rank, Role, TypeDeclaration};
+//!
+//! let mut drawing = TypeDeclaration::new("org.example.editor.drawing");
+//! drawing
+//!     .description("Editor Drawing")
+//!     .conforms_to("public.data")
+//!     .extension("drawing");
+//! let mut document = DocumentType::new("Editor Drawing");
+//! document
+//!     .role(Role::Editor)
+//!     .rank(Rank::Owner)
+//!     .content_type("org.example.editor.drawing");
+//!
+//! let mut trampoline = relaunch::Trampoline::new("Editor", "org.example.Editor");
+//! trampoline.exported_type(drawing).document_type(document);
+//! trampoline.validate().unwrap();
+//! ```
+
+use crate::{
+    plist::{Dictionary, Value},
+    validate, ValidationError,
+};
+use std::collections::HashMap;
+
+/// How the application uses a [`DocumentType`], as `CFBundleTypeRole`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    /// The application can read, modify, and save the files.
+    Editor,
+    /// The application can read and present, but not save, the files.
+    Viewer,
+    /// The application provides runtime services for the files.
+    Shell,
+    /// The application declares information about the files, but cannot
+    /// open them.
+    None,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Editor => "Editor",
+            Role::Viewer => "Viewer",
+            Role::Shell => "Shell",
+            Role::None => "None",
+        }
+    }
+}
+
+/// How strongly the application claims a [`DocumentType`] relative to other
+/// applications which can open the same files, as `LSHandlerRank`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rank {
+    /// The application created the file format.
+    Owner,
+    /// The application is a primary handler of the files, e.g. an image
+    /// editor for PNG files.
+    Default,
+    /// The application is a secondary handler of the files.
+    Alternate,
+    /// The application is never chosen to open the files.
+    None,
+}
+
+impl Rank {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Rank::Owner => "Owner",
+            Rank::Default => "Default",
+            Rank::Alternate => "Alternate",
+            Rank::None => "None",
+        }
+    }
+}
+
+/// Add `value` to `list` unless already present.
+fn push_unique(list: &mut Vec<String>, value: &str) {
+    if !list.iter().any(|existing| existing == value) {
+        list.push(value.to_string());
+    }
+}
+
+/// Insert `values` into `dict` as an array of strings under `key`, unless
+/// there are none.
+fn insert_strings(dict: &mut Dictionary, key: &str, values: &[String]) {
+    if !values.is_empty() {
+        let values = values.iter().map(|v| Value::from(v.as_str())).collect();
+        dict.insert(key.to_string(), Value::Array(values));
+    }
+}
+
+/// A kind of file the application can open, as an entry of
+/// `CFBundleDocumentTypes`.  Files are matched by content type, or by
+/// extension or MIME type, which macOS only consults for document types
+/// without content types.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DocumentType {
+    name: String,
+    role: Role,
+    rank: Option<Rank>,
+    content_types: Vec<String>,
+    extensions: Vec<String>,
+    mime_types: Vec<String>,
+    icon: Option<String>,
+    is_package: bool,
+}
+
+impl DocumentType {
+    /// Declare a document type with the name `name`, as `CFBundleTypeName`,
+    /// which is shown to the user, e.g. in the Finder's Get Info window.
+    /// The role defaults to [`Role::Editor`].
+    pub fn new(name: &str) -> Self {
+        DocumentType {
+            name: name.to_string(),
+            role: Role::Editor,
+            rank: None,
+            content_types: Vec::new(),
+            extensions: Vec::new(),
+            mime_types: Vec::new(),
+            icon: None,
+            is_package: false,
+        }
+    }
+
+    /// The name of the document type.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Set the role of the application for these files.
+    pub fn role(&mut self, role: Role) -> &mut Self {
+        self.role = role;
+        self
+    }
+    /// Set the rank of the application among the handlers of these files.
+    /// If unset, macOS assumes [`Rank::Default`], or [`Rank::Owner`] for
+    /// exported types.
+    pub fn rank(&mut self, rank: Rank) -> &mut Self {
+        self.rank = Some(rank);
+        self
+    }
+    /// Add a uniform type identifier, e.g. `public.plain-text`, to
+    /// `LSItemContentTypes`.  Files of types conforming to it match too.
+    pub fn content_type(&mut self, uti: &str) -> &mut Self {
+        push_unique(&mut self.content_types, uti);
+        self
+    }
+    /// Add a file name extension, without the leading `.`, to
+    /// `CFBundleTypeExtensions`.
+    pub fn extension(&mut self, extension: &str) -> &mut Self {
+        push_unique(&mut self.extensions, extension);
+        self
+    }
+    /// Add a MIME type, e.g. `text/markdown`, to `CFBundleTypeMIMETypes`.
+    pub fn mime_type(&mut self, mime_type: &str) -> &mut Self {
+        push_unique(&mut self.mime_types, mime_type);
+        self
+    }
+    /// Set `CFBundleTypeIconFile`, the name of an `.icns` file in
+    /// `Contents/Resources` to show for these files.  Add the file itself
+    /// with [`Trampoline::resource()`].
+    ///
+    /// [`Trampoline::resource()`]: crate::Trampoline::resource
+    pub fn icon(&mut self, file: &str) -> &mut Self {
+        self.icon = Some(file.to_string());
+        self
+    }
+    /// Set `LSTypeIsPackage`, for documents which are directories that the
+    /// Finder shows as a single file.
+    pub fn is_package(&mut self, is_package: bool) -> &mut Self {
+        self.is_package = is_package;
+        self
+    }
+
+    /// Encode as an entry of `CFBundleDocumentTypes`.
+    pub fn to_dictionary(&self) -> Dictionary {
+        let mut dict = Dictionary::new();
+        dict.insert("CFBundleTypeName".into(), self.name.as_str().into());
+        dict.insert("CFBundleTypeRole".into(), self.role.as_str().into());
+        if let Some(rank) = self.rank {
+            dict.insert("LSHandlerRank".into(), rank.as_str().into());
+        }
+        insert_strings(&mut dict, "LSItemContentTypes", &self.content_types);
+        insert_strings(&mut dict, "CFBundleTypeExtensions", &self.extensions);
+        insert_strings(&mut dict, "CFBundleTypeMIMETypes", &self.mime_types);
+        if let Some(icon) = &self.icon {
+            dict.insert("CFBundleTypeIconFile".into(), icon.as_str().into());
+        }
+        if self.is_package {
+            dict.insert("LSTypeIsPackage".into(), true.into());
+        }
+        dict
+    }
+}
+
+/// The definition of a uniform type identifier, as an entry of
+/// `UTExportedTypeDeclarations` or `UTImportedTypeDeclarations`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypeDeclaration {
+    identifier: String,
+    description: Option<String>,
+    conforms_to: Vec<String>,
+    extensions: Vec<String>,
+    mime_types: Vec<String>,
+    icon: Option<String>,
+    reference_url: Option<String>,
+}
+
+impl TypeDeclaration {
+    /// Declare the uniform type identifier `identifier`, as
+    /// `UTTypeIdentifier`, e.g. `org.example.editor.drawing`.
+    pub fn new(identifier: &str) -> Self {
+        TypeDeclaration {
+            identifier: identifier.to_string(),
+            description: None,
+            conforms_to: Vec::new(),
+            extensions: Vec::new(),
+            mime_types: Vec::new(),
+            icon: None,
+            reference_url: None,
+        }
+    }
+
+    /// The declared uniform type identifier.
+    pub fn identifier(&self) -> &str {
+        &self.identifier
+    }
+
+    /// Set `UTTypeDescription`, the name of the type shown to the user.
+    pub fn description(&mut self, description: &str) -> &mut Self {
+        self.description = Some(description.to_string());
+        self
+    }
+    /// Add a type this one conforms to, to `UTTypeConformsTo`, e.g.
+    /// `public.data` for a flat file or `com.apple.package` for a
+    /// directory.
+    pub fn conforms_to(&mut self, uti: &str) -> &mut Self {
+        push_unique(&mut self.conforms_to, uti);
+        self
+    }
+    /// Add a file name extension, without the leading `.`, which identifies
+    /// files of this type.
+    pub fn extension(&mut self, extension: &str) -> &mut Self {
+        push_unique(&mut self.extensions, extension);
+        self
+    }
+    /// Add a MIME type which identifies data of this type.
+    pub fn mime_type(&mut self, mime_type: &str) -> &mut Self {
+        push_unique(&mut self.mime_types, mime_type);
+        self
+    }
+    /// Set `UTTypeIconFile`, the name of an `.icns` file in
+    /// `Contents/Resources`.
+    pub fn icon(&mut self, file: &str) -> &mut Self {
+        self.icon = Some(file.to_string());
+        self
+    }
+    /// Set `UTTypeReferenceURL`, a web page documenting the format.
+    pub fn reference_url(&mut self, url: &str) -> &mut Self {
+        self.reference_url = Some(url.to_string());
+        self
+    }
+
+    /// Encode as an entry of a type declarations array.  The extensions and
+    /// MIME types are written to `UTTypeTagSpecification`.
+    pub fn to_dictionary(&self) -> Dictionary {
+        let mut dict = Dictionary::new();
+        dict.insert("UTTypeIdentifier".into(), self.identifier.as_str().into());
+        if let Some(description) = &self.description {
+            dict.insert("UTTypeDescription".into(), description.as_str().into());
+        }
+        insert_strings(&mut dict, "UTTypeConformsTo", &self.conforms_to);
+        let mut tags = Dictionary::new();
+        insert_strings(&mut tags, "public.filename-extension", &self.extensions);
+        insert_strings(&mut tags, "public.mime-type", &self.mime_types);
+        if !tags.is_empty() {
+            dict.insert("UTTypeTagSpecification".into(), tags.into());
+        }
+        if let Some(icon) = &self.icon {
+            dict.insert("UTTypeIconFile".into(), icon.as_str().into());
+        }
+        if let Some(url) = &self.reference_url {
+            dict.insert("UTTypeReferenceURL".into(), url.as_str().into());
+        }
+        dict
+    }
+}
+
+/// Record that `claimant` claims `extension`, failing if another claimant
+/// in `claims` already has.  Extensions are compared case-insensitively, as
+/// the Finder does.
+fn claim(
+    claims: &mut HashMap<String, String>,
+    extension: &str,
+    claimant: &str,
+) -> Result<(), ValidationError> {
+    validate::validate_extension(extension)?;
+    match claims.insert(extension.to_lowercase(), claimant.to_string()) {
+        Some(first) if first != claimant => Err(ValidationError::DuplicateExtension {
+            extension: extension.to_string(),
+            first,
+            second: claimant.to_string(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Check the document types and type declarations: every identifier must be
+/// a well-formed UTI, each type declared only once, each document type must
+/// match some files, and no extension may be claimed by two document types
+/// or by two type declarations.
+pub(crate) fn validate(
+    document_types: &[DocumentType],
+    declarations: &[&TypeDeclaration],
+) -> Result<(), ValidationError> {
+    let mut claims = HashMap::new();
+    for document_type in document_types {
+        if document_type.content_types.is_empty()
+            && document_type.extensions.is_empty()
+            && document_type.mime_types.is_empty()
+        {
+            return Err(ValidationError::EmptyDocumentType {
+                name: document_type.name.clone(),
+            });
+        }
+        for uti in &document_type.content_types {
+            validate::validate_uti(uti)?;
+        }
+        for extension in &document_type.extensions {
+            claim(&mut claims, extension, &document_type.name)?;
+        }
+    }
+
+    let mut claims = HashMap::new();
+    let mut declared = Vec::new();
+    for declaration in declarations {
+        validate::validate_uti(&declaration.identifier)?;
+        if declared.contains(&&declaration.identifier) {
+            return Err(ValidationError::DuplicateTypeDeclaration {
+                uti: declaration.identifier.clone(),
+            });
+        }
+        declared.push(&declaration.identifier);
+        for uti in &declaration.conforms_to {
+            validate::validate_uti(uti)?;
+        }
+        for extension in &declaration.extensions {
+            claim(&mut claims, extension, &declaration.identifier)?;
+        }
+    }
+    Ok(())
+}
+
+// End of File
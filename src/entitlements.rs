@@ -0,0 +1,328 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Code signing entitlements, which grant a signed application access to
+//! resources the App Sandbox or the hardened runtime would otherwise deny,
+//! e.g. the camera, the network, or just-in-time compilation.
+//!
+//! Entitlements are a property list dictionary, embedded in the code
+//! signature by `codesign --entitlements <file>`.  The [`Entitlements`]
+//! builder has typed setters for the common keys, and [`Entitlements::set()`]
+//! for everything else:
+//!
+//! ```
+//! use relaunch::entitlements::{Entitlements, FileAccess};
+//!
+//! let mut entitlements = Entitlements::new();
+//! entitlements
+//!     .app_sandbox(true)
+//!     .network_client(true)
+//!     .camera(true)
+//!     .user_selected_files(FileAccess::ReadOnly);
+//! entitlements.validate().unwrap();
+//! ```
+
+use crate::plist::{self, Dictionary, Value};
+use std::fmt;
+
+/// `com.apple.security.app-sandbox`
+pub const APP_SANDBOX: &str = "com.apple.security.app-sandbox";
+/// `com.apple.security.application-groups`
+pub const APPLICATION_GROUPS: &str = "com.apple.security.application-groups";
+
+/// Prefixes of keys which only have meaning inside the App Sandbox.
+/// Resource access keys such as `device.camera` are shared with the
+/// hardened runtime, so are not included.
+const SANDBOX_PREFIXES: &[&str] = &[
+    "com.apple.security.network.",
+    "com.apple.security.files.",
+    "com.apple.security.assets.",
+    "com.apple.security.print",
+    "com.apple.security.device.usb",
+    "com.apple.security.device.bluetooth",
+    "com.apple.security.device.serial",
+    "com.apple.security.temporary-exception.",
+    "com.apple.security.inherit",
+];
+
+/// Access to a class of files granted by the App Sandbox.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileAccess {
+    ReadOnly,
+    ReadWrite,
+}
+
+impl FileAccess {
+    fn suffix(&self) -> &'static str {
+        match self {
+            FileAccess::ReadOnly => "read-only",
+            FileAccess::ReadWrite => "read-write",
+        }
+    }
+}
+
+/// An error found by [`Entitlements::validate()`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// `key` only has an effect inside the App Sandbox, but
+    /// `com.apple.security.app-sandbox` is not enabled.
+    RequiresAppSandbox { key: String },
+    /// The well-known entitlement `key` has a value of the wrong type, e.g.
+    /// a string where a boolean is required.
+    InvalidType { key: String, expected: &'static str },
+    /// An application group identifier is empty or contains a character
+    /// other than an ASCII alphanumeric, `-`, or `.`.
+    InvalidAppGroup { group: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::RequiresAppSandbox { key } => {
+                write!(f, "entitlement {} requires {}", key, APP_SANDBOX)
+            }
+            Error::InvalidType { key, expected } => {
+                write!(f, "entitlement {} must be {}", key, expected)
+            }
+            Error::InvalidAppGroup { group } => {
+                write!(f, "invalid application group {:?}", group)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A set of code signing entitlements.  Boolean setters write the value
+/// given, so `false` explicitly disables an entitlement; use
+/// [`Self::remove()`] to omit a key entirely.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Entitlements {
+    keys: Dictionary,
+}
+
+impl Entitlements {
+    pub fn new() -> Self {
+        Entitlements::default()
+    }
+
+    /// Set any entitlement, for keys without a typed setter.
+    pub fn set<V: Into<Value>>(&mut self, key: &str, value: V) -> &mut Self {
+        self.keys.insert(key.to_string(), value.into());
+        self
+    }
+    /// Remove an entitlement.
+    pub fn remove(&mut self, key: &str) -> &mut Self {
+        self.keys.remove(key);
+        self
+    }
+    /// The value of an entitlement, if set.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.keys.get(key)
+    }
+    pub fn as_dictionary(&self) -> &Dictionary {
+        &self.keys
+    }
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    fn flag(&mut self, key: &str, enabled: bool) -> &mut Self {
+        self.set(key, enabled)
+    }
+
+    // App Sandbox
+
+    /// `com.apple.security.app-sandbox`: run in the App Sandbox, which is
+    /// required for the Mac App Store.
+    pub fn app_sandbox(&mut self, enabled: bool) -> &mut Self {
+        self.flag(APP_SANDBOX, enabled)
+    }
+    /// `com.apple.security.network.client`: make outgoing connections.
+    pub fn network_client(&mut self, enabled: bool) -> &mut Self {
+        self.flag("com.apple.security.network.client", enabled)
+    }
+    /// `com.apple.security.network.server`: accept incoming connections.
+    pub fn network_server(&mut self, enabled: bool) -> &mut Self {
+        self.flag("com.apple.security.network.server", enabled)
+    }
+    /// `com.apple.security.files.user-selected.*`: access files the user
+    /// chooses in an open or save panel.
+    pub fn user_selected_files(&mut self, access: FileAccess) -> &mut Self {
+        let key = format!("com.apple.security.files.user-selected.{}", access.suffix());
+        self.flag(&key, true)
+    }
+    /// `com.apple.security.files.downloads.*`: access the Downloads folder.
+    pub fn downloads_folder(&mut self, access: FileAccess) -> &mut Self {
+        let key = format!("com.apple.security.files.downloads.{}", access.suffix());
+        self.flag(&key, true)
+    }
+    /// `com.apple.security.device.usb`: access USB devices.
+    pub fn usb(&mut self, enabled: bool) -> &mut Self {
+        self.flag("com.apple.security.device.usb", enabled)
+    }
+    /// `com.apple.security.device.bluetooth`: access Bluetooth devices.
+    pub fn bluetooth(&mut self, enabled: bool) -> &mut Self {
+        self.flag("com.apple.security.device.bluetooth", enabled)
+    }
+    /// `com.apple.security.print`: print documents.
+    pub fn print(&mut self, enabled: bool) -> &mut Self {
+        self.flag("com.apple.security.print", enabled)
+    }
+    /// `com.apple.security.application-groups`: share containers with other
+    /// applications from the same team, e.g. `TEAMID.org.example.shared`.
+    /// Adds to any groups already set.
+    pub fn app_groups<I, S>(&mut self, groups: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut all = match self.keys.remove(APPLICATION_GROUPS) {
+            Some(Value::Array(existing)) => existing,
+            _ => Vec::new(),
+        };
+        for group in groups {
+            let group = Value::String(group.into());
+            if !all.contains(&group) {
+                all.push(group);
+            }
+        }
+        self.set(APPLICATION_GROUPS, all)
+    }
+
+    // Resource access, shared by the App Sandbox and the hardened runtime
+
+    /// `com.apple.security.device.camera`: use the camera.
+    pub fn camera(&mut self, enabled: bool) -> &mut Self {
+        self.flag("com.apple.security.device.camera", enabled)
+    }
+    /// `com.apple.security.device.audio-input`: use the microphone.
+    pub fn audio_input(&mut self, enabled: bool) -> &mut Self {
+        self.flag("com.apple.security.device.audio-input", enabled)
+    }
+    /// `com.apple.security.personal-information.location`: use Location
+    /// Services.
+    pub fn location(&mut self, enabled: bool) -> &mut Self {
+        self.flag("com.apple.security.personal-information.location", enabled)
+    }
+    /// `com.apple.security.personal-information.addressbook`: read and
+    /// write contacts.
+    pub fn contacts(&mut self, enabled: bool) -> &mut Self {
+        self.flag(
+            "com.apple.security.personal-information.addressbook",
+            enabled,
+        )
+    }
+    /// `com.apple.security.personal-information.calendars`: read and write
+    /// calendars.
+    pub fn calendars(&mut self, enabled: bool) -> &mut Self {
+        self.flag("com.apple.security.personal-information.calendars", enabled)
+    }
+    /// `com.apple.security.personal-information.photos-library`: access the
+    /// Photos library.
+    pub fn photos_library(&mut self, enabled: bool) -> &mut Self {
+        self.flag(
+            "com.apple.security.personal-information.photos-library",
+            enabled,
+        )
+    }
+    /// `com.apple.security.automation.apple-events`: send Apple Events to
+    /// other applications.
+    pub fn apple_events(&mut self, enabled: bool) -> &mut Self {
+        self.flag("com.apple.security.automation.apple-events", enabled)
+    }
+
+    // Hardened runtime exceptions
+
+    /// `com.apple.security.cs.allow-jit`: create writable and executable
+    /// memory with `MAP_JIT`.
+    pub fn allow_jit(&mut self, enabled: bool) -> &mut Self {
+        self.flag("com.apple.security.cs.allow-jit", enabled)
+    }
+    /// `com.apple.security.cs.allow-unsigned-executable-memory`: create
+    /// writable and executable memory without `MAP_JIT`.
+    pub fn allow_unsigned_executable_memory(&mut self, enabled: bool) -> &mut Self {
+        self.flag(
+            "com.apple.security.cs.allow-unsigned-executable-memory",
+            enabled,
+        )
+    }
+    /// `com.apple.security.cs.allow-dyld-environment-variables`: honor
+    /// `DYLD_*` environment variables.
+    pub fn allow_dyld_environment_variables(&mut self, enabled: bool) -> &mut Self {
+        self.flag(
+            "com.apple.security.cs.allow-dyld-environment-variables",
+            enabled,
+        )
+    }
+    /// `com.apple.security.cs.disable-library-validation`: load libraries
+    /// and plug-ins signed by other teams, or not signed at all.
+    pub fn disable_library_validation(&mut self, enabled: bool) -> &mut Self {
+        self.flag("com.apple.security.cs.disable-library-validation", enabled)
+    }
+    /// `com.apple.security.get-task-allow`: allow debuggers to attach.
+    /// Notarization rejects this, so enable it only for development.
+    pub fn get_task_allow(&mut self, enabled: bool) -> &mut Self {
+        self.flag("com.apple.security.get-task-allow", enabled)
+    }
+
+    /// Check that the well-known keys have values of the right type, that
+    /// sandbox-only keys are enabled only inside the App Sandbox, and that
+    /// application group identifiers are well-formed.
+    pub fn validate(&self) -> Result<(), Error> {
+        let sandboxed = self.get(APP_SANDBOX).and_then(Value::as_bool) == Some(true);
+        for (key, value) in &self.keys {
+            if key == APPLICATION_GROUPS {
+                let groups = value.as_array().ok_or_else(|| Error::InvalidType {
+                    key: key.clone(),
+                    expected: "an array of strings",
+                })?;
+                for group in groups {
+                    let group = group.as_str().ok_or_else(|| Error::InvalidType {
+                        key: key.clone(),
+                        expected: "an array of strings",
+                    })?;
+                    let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '.';
+                    if group.is_empty() || !group.chars().all(valid) {
+                        return Err(Error::InvalidAppGroup {
+                            group: group.to_string(),
+                        });
+                    }
+                }
+                continue;
+            }
+            // Other keys in Apple's namespace are booleans, except for the
+            // temporary exceptions, which take lists of paths or services.
+            if key.starts_with("com.apple.security.")
+                && !key.starts_with("com.apple.security.temporary-exception.")
+                && value.as_bool().is_none()
+            {
+                return Err(Error::InvalidType {
+                    key: key.clone(),
+                    expected: "a boolean",
+                });
+            }
+            let enabled = value.as_bool() != Some(false);
+            if enabled && !sandboxed && SANDBOX_PREFIXES.iter().any(|p| key.starts_with(p)) {
+                return Err(Error::RequiresAppSandbox { key: key.clone() });
+            }
+        }
+        Ok(())
+    }
+
+    /// Encode as an XML property list, the form `codesign` expects.  Fails
+    /// only if a string contains a character which XML cannot represent.
+    pub fn to_xml(&self) -> Result<String, std::io::Error> {
+        plist::xml::to_string(&Value::Dictionary(self.keys.clone()))
+    }
+}
+
+impl From<Dictionary> for Entitlements {
+    fn from(keys: Dictionary) -> Self {
+        Entitlements { keys }
+    }
+}
+
+// End of File
@@ -0,0 +1,256 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::{entitlements, icon, macho, plist, strings, ValidationError};
+use std::{
+    fmt,
+    io::{Error as IOError, ErrorKind},
+    path::PathBuf,
+};
+
+/// Everything that can go wrong while bundling and relaunching.  Variants
+/// carry the offending path or field, and the underlying error (if any) is
+/// available through [`std::error::Error::source()`], rather than repeated
+/// in the message.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The configuration of the trampoline is invalid.
+    InvalidMetadata(ValidationError),
+    /// An `Info.plist` template or custom key tried to change keys which
+    /// relaunch manages itself.
+    ManagedKeyConflict { keys: Vec<String> },
+    /// The `[package.metadata.relaunch]` table is malformed.  `path` is the
+    /// manifest (or the file being written) when detected by a build script,
+    /// and `None` when the embedded metadata is decoded at runtime.
+    CargoMetadata {
+        path: Option<PathBuf>,
+        message: String,
+    },
+    /// The `Info.plist` template could not be read or parsed.  `path` is
+    /// `None` if the template was supplied as bytes.
+    InvalidTemplate {
+        path: Option<PathBuf>,
+        source: plist::Error,
+    },
+    /// The application icon could not be generated.  `path` is the image at
+    /// fault, if the problem is with a particular image file.
+    Icon {
+        path: Option<PathBuf>,
+        source: icon::Error,
+    },
+    /// The entitlements are invalid.
+    Entitlements(entitlements::Error),
+    /// A `.strings` file of translations could not be parsed.
+    Strings {
+        path: PathBuf,
+        source: strings::Error,
+    },
+    /// A resource could not be copied into the bundle.  `path` is the
+    /// offending source file or directory.
+    Resource { path: PathBuf, source: IOError },
+    /// A resource destination is not a relative path within
+    /// `Contents/Resources`.
+    ResourceDestination { path: PathBuf },
+    /// A resource filter contains an invalid glob pattern.
+    ResourcePattern { pattern: String, message: String },
+    /// A Mach-O executable or dynamic library could not be parsed or
+    /// rewritten.  `path` is the file at fault.
+    MachO { path: PathBuf, source: macho::Error },
+    /// The per-architecture executables at `paths` could not be combined
+    /// into a universal binary.
+    UniversalBinary {
+        paths: Vec<PathBuf>,
+        source: macho::Error,
+    },
+    /// A dynamic library `name` linked by `loader` could not be found, so it
+    /// cannot be embedded in `Contents/Frameworks`.
+    MissingDylib { name: String, loader: PathBuf },
+    /// Two different dynamic libraries would be embedded at the same path
+    /// `name` within `Contents/Frameworks`.
+    DylibConflict {
+        name: PathBuf,
+        first: PathBuf,
+        second: PathBuf,
+    },
+    /// A rewritten executable or dynamic library could not be signed again.
+    CodeSign { path: PathBuf, message: String },
+    /// The directory in which to install the bundle could not be determined,
+    /// e.g. because the user has no home directory or a custom directory
+    /// does not exist.
+    InstallDir {
+        path: Option<PathBuf>,
+        source: Option<IOError>,
+    },
+    /// The path of the running executable could not be determined.
+    CurrentExe(IOError),
+    /// The executable path has no file name usable as `CFBundleExecutable`.
+    ExecutableName { path: PathBuf },
+    /// Permission was denied while accessing `path`.
+    PermissionDenied { path: PathBuf, source: IOError },
+    /// The executable could not be copied into the bundle.
+    CopyExecutable {
+        from: PathBuf,
+        to: PathBuf,
+        source: IOError,
+    },
+    /// Any other filesystem error while accessing `path`.
+    Io { path: PathBuf, source: IOError },
+    /// The bundled executable could not be launched.
+    Launch { path: PathBuf, source: IOError },
+    /// The AppKit APIs used by [`Application`] must be called from the main
+    /// thread.
+    ///
+    /// [`Application`]: crate::Application
+    NotMainThread,
+}
+
+impl Error {
+    /// Wrap an IO error encountered while accessing `path`, singling out
+    /// permission errors.
+    pub(crate) fn io<P: Into<PathBuf>>(path: P, source: IOError) -> Self {
+        let path = path.into();
+        match source.kind() {
+            ErrorKind::PermissionDenied => Error::PermissionDenied { path, source },
+            _ => Error::Io { path, source },
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidMetadata(_) => write!(f, "invalid metadata"),
+            Error::ManagedKeyConflict { keys } => write!(
+                f,
+                "Info.plist keys managed by relaunch cannot be overridden: {}",
+                keys.join(", ")
+            ),
+            Error::CargoMetadata {
+                path: Some(path),
+                message,
+            } => write!(
+                f,
+                "invalid [package.metadata.relaunch] in {}: {}",
+                path.display(),
+                message
+            ),
+            Error::CargoMetadata {
+                path: None,
+                message,
+            } => write!(f, "invalid embedded cargo metadata: {}", message),
+            Error::InvalidTemplate {
+                path: Some(path), ..
+            } => write!(f, "could not load Info.plist template {}", path.display()),
+            Error::InvalidTemplate { path: None, .. } => {
+                write!(f, "could not load Info.plist template")
+            }
+            Error::Icon {
+                path: Some(path), ..
+            } => write!(f, "could not load icon {}", path.display()),
+            Error::Icon { path: None, .. } => write!(f, "could not generate application icon"),
+            Error::Entitlements(_) => write!(f, "invalid entitlements"),
+            Error::Strings { path, .. } => {
+                write!(f, "could not load strings file {}", path.display())
+            }
+            Error::Resource { path, .. } => {
+                write!(f, "could not copy resource {}", path.display())
+            }
+            Error::ResourceDestination { path } => write!(
+                f,
+                "resource destination {} must be a relative path within Contents/Resources",
+                path.display()
+            ),
+            Error::ResourcePattern { pattern, message } => {
+                write!(f, "invalid resource pattern {:?}: {}", pattern, message)
+            }
+            Error::MachO { path, .. } => {
+                write!(f, "could not process Mach-O file {}", path.display())
+            }
+            Error::UniversalBinary { paths, .. } => write!(
+                f,
+                "could not combine {} into a universal binary",
+                paths
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Error::MissingDylib { name, loader } => {
+                write!(f, "could not find {}, linked by {}", name, loader.display())
+            }
+            Error::DylibConflict {
+                name,
+                first,
+                second,
+            } => write!(
+                f,
+                "both {} and {} would be embedded as Frameworks/{}",
+                first.display(),
+                second.display(),
+                name.display()
+            ),
+            Error::CodeSign { path, message } => {
+                write!(f, "could not sign {}: {}", path.display(), message)
+            }
+            Error::InstallDir {
+                path: Some(path), ..
+            } => write!(f, "invalid install directory {}", path.display()),
+            Error::InstallDir { path: None, .. } => {
+                write!(f, "could not determine install directory")
+            }
+            Error::CurrentExe(_) => write!(f, "could not determine path to current executable"),
+            Error::ExecutableName { path } => write!(
+                f,
+                "could not determine executable name from {}",
+                path.display()
+            ),
+            Error::PermissionDenied { path, .. } => {
+                write!(f, "permission denied: {}", path.display())
+            }
+            Error::CopyExecutable { from, to, .. } => write!(
+                f,
+                "could not copy executable {} to {}",
+                from.display(),
+                to.display()
+            ),
+            Error::Io { path, .. } => write!(f, "could not access {}", path.display()),
+            Error::Launch { path, .. } => write!(f, "could not launch {}", path.display()),
+            Error::NotMainThread => write!(f, "must be called from the main thread"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::InvalidMetadata(err) => Some(err),
+            Error::InvalidTemplate { source, .. } => Some(source),
+            Error::Icon { source, .. } => Some(source),
+            Error::Entitlements(err) => Some(err),
+            Error::Strings { source, .. } => Some(source),
+            Error::MachO { source, .. } | Error::UniversalBinary { source, .. } => Some(source),
+            Error::InstallDir {
+                source: Some(source),
+                ..
+            } => Some(source),
+            Error::CurrentExe(source)
+            | Error::Resource { source, .. }
+            | Error::PermissionDenied { source, .. }
+            | Error::CopyExecutable { source, .. }
+            | Error::Io { source, .. }
+            | Error::Launch { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<ValidationError> for Error {
+    fn from(err: ValidationError) -> Self {
+        Error::InvalidMetadata(err)
+    }
+}
+
+// End of File
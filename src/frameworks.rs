@@ -0,0 +1,343 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Embedding the non-system dynamic libraries an executable links against
+//! into `Contents/Frameworks`, so that the bundle runs on machines without
+//! them installed.
+//!
+//! The executable's dependencies are found by walking its `LC_LOAD_DYLIB`
+//! (and weak, lazy, re-export, and upward) load commands, resolving
+//! `@rpath`, `@executable_path`, and `@loader_path` the way `dyld` does, and
+//! then recursively doing the same for each library found.  Libraries in
+//! `/usr/lib` and `/System` are part of macOS and are left alone.  Each
+//! embedded library is given the install name `@rpath/<name>`, and the
+//! executable gets the run path `@executable_path/../Frameworks`.  Libraries
+//! inside a `.framework` are embedded by copying the whole framework.
+//!
+//! Everything is resolved by [`plan()`] before the bundle is written, so a
+//! missing library does not leave a partial bundle behind.
+
+use crate::{
+    macho::{self, Changes, DylibKind},
+    Error,
+};
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+/// The run path added to the executable, pointing at `Contents/Frameworks`.
+pub(crate) const EXECUTABLE_RPATH: &str = "@executable_path/../Frameworks";
+
+/// A dynamic library to embed.
+struct Library {
+    /// The canonical path of the library binary.
+    source: PathBuf,
+    /// The `.framework` directory containing the library, if any, which is
+    /// copied in its entirety.
+    framework: Option<PathBuf>,
+    /// The path of the library binary relative to `Contents/Frameworks`.
+    dest: PathBuf,
+    /// The edits to make to the library's load commands.
+    changes: Changes,
+}
+
+/// The libraries to embed and the edits to make, as computed by [`plan()`].
+pub(crate) struct Plan {
+    libraries: Vec<Library>,
+    executable: Changes,
+}
+
+/// Whether `path` is part of macOS, and so must not be embedded.
+fn is_system(path: &Path) -> bool {
+    path.starts_with("/usr/lib") || path.starts_with("/System")
+}
+
+/// Expand a leading `@executable_path` or `@loader_path`, or return `None`
+/// for any other `@` prefix.
+fn expand(path: &str, exe_dir: &Path, loader_dir: &Path) -> Option<PathBuf> {
+    for (prefix, dir) in [("@executable_path", exe_dir), ("@loader_path", loader_dir)] {
+        if let Some(rest) = path.strip_prefix(prefix) {
+            if rest.is_empty() || rest.starts_with('/') {
+                return Some(dir.join(rest.trim_start_matches('/')));
+            }
+        }
+    }
+    (!path.starts_with('@')).then(|| PathBuf::from(path))
+}
+
+/// Find the library with install name `name`, searching `rpaths` for an
+/// `@rpath` name.
+fn resolve(name: &str, exe_dir: &Path, loader_dir: &Path, rpaths: &[PathBuf]) -> Option<PathBuf> {
+    let candidates = match name.strip_prefix("@rpath/") {
+        Some(rest) => rpaths.iter().map(|rpath| rpath.join(rest)).collect(),
+        None => Vec::from_iter(expand(name, exe_dir, loader_dir)),
+    };
+    candidates.into_iter().find(|path| path.is_file())
+}
+
+/// The `.framework` directory containing `path`, if any.
+fn framework_root(path: &Path) -> Option<PathBuf> {
+    let mut root = PathBuf::new();
+    for component in path.components() {
+        root.push(component);
+        if let Component::Normal(name) = component {
+            if Path::new(name)
+                .extension()
+                .is_some_and(|ext| ext == "framework")
+            {
+                return Some(root);
+            }
+        }
+    }
+    None
+}
+
+/// Read a Mach-O file and parse its dependencies and run paths.
+fn read(path: &Path) -> Result<(Vec<macho::Dylib>, Vec<String>), Error> {
+    let bytes = fs::read(path).map_err(|err| Error::io(path, err))?;
+    let error = |source| Error::MachO {
+        path: path.to_path_buf(),
+        source,
+    };
+    Ok((
+        macho::dylibs(&bytes).map_err(error)?,
+        macho::rpaths(&bytes).map_err(error)?,
+    ))
+}
+
+/// Find every non-system library the `executables` depend on, directly or
+/// indirectly, and work out how each binary must be rewritten.  There are
+/// several executables when they are the per-architecture builds of a
+/// universal binary, whose changes are combined.
+pub(crate) fn plan(executables: Vec<&Path>) -> Result<Plan, Error> {
+    let mut plan = Plan {
+        libraries: Vec::new(),
+        executable: Changes::default(),
+    };
+    // Index into plan.libraries of each library by canonical path, and of
+    // each destination, to detect two libraries with the same name.
+    let mut found: HashMap<PathBuf, usize> = HashMap::new();
+    let mut dests: HashMap<PathBuf, usize> = HashMap::new();
+    // Binaries still to be scanned, with the run paths inherited from the
+    // binaries which loaded them, as dyld searches the whole chain.
+    let mut queue = VecDeque::new();
+    for executable in executables {
+        let executable = fs::canonicalize(executable).map_err(|err| Error::io(executable, err))?;
+        let exe_dir = executable.parent().unwrap_or(Path::new("/")).to_path_buf();
+        queue.push_back((None, executable, exe_dir, Vec::new()));
+    }
+
+    while let Some((index, path, exe_dir, inherited)) = queue.pop_front() {
+        let loader_dir = path.parent().unwrap_or(Path::new("/")).to_path_buf();
+        let (dylibs, rpaths) = read(&path)?;
+        let mut search: Vec<PathBuf> = rpaths
+            .iter()
+            .filter_map(|rpath| expand(rpath, &exe_dir, &loader_dir))
+            .collect();
+        search.extend(inherited);
+
+        let mut changes = Changes::default();
+        for dylib in dylibs {
+            if is_system(Path::new(&dylib.name)) {
+                continue;
+            }
+            let resolved = match resolve(&dylib.name, &exe_dir, &loader_dir, &search) {
+                Some(resolved) => resolved,
+                None if dylib.kind == DylibKind::Weak => continue,
+                None => {
+                    return Err(Error::MissingDylib {
+                        name: dylib.name,
+                        loader: path,
+                    })
+                }
+            };
+            let source = fs::canonicalize(&resolved).map_err(|err| Error::io(&resolved, err))?;
+            if is_system(&source) {
+                continue;
+            }
+            let library = match found.get(&source) {
+                Some(&library) => library,
+                None => {
+                    let framework = framework_root(&source);
+                    let dest = match &framework {
+                        Some(root) => {
+                            let name = root.file_name().unwrap();
+                            Path::new(name).join(source.strip_prefix(root).unwrap())
+                        }
+                        // Keep the name the library was linked by, e.g.
+                        // libpng16.16.dylib, which is often a link to a
+                        // more specifically versioned file.
+                        None => PathBuf::from(resolved.file_name().unwrap()),
+                    };
+                    if let Some(&other) = dests.get(&dest) {
+                        return Err(Error::DylibConflict {
+                            name: dest,
+                            first: plan.libraries[other].source.clone(),
+                            second: source,
+                        });
+                    }
+                    let library = plan.libraries.len();
+                    let depth = dest.components().count() - 1;
+                    plan.libraries.push(Library {
+                        source: source.clone(),
+                        framework,
+                        dest: dest.clone(),
+                        changes: Changes {
+                            install_name: Some(format!("@rpath/{}", dest.display())),
+                            add_rpaths: vec![std::iter::once("@loader_path")
+                                .chain(std::iter::repeat_n("..", depth))
+                                .collect::<Vec<_>>()
+                                .join("/")],
+                            ..Changes::default()
+                        },
+                    });
+                    found.insert(source.clone(), library);
+                    dests.insert(dest, library);
+                    queue.push_back((Some(library), source, exe_dir.clone(), search.clone()));
+                    library
+                }
+            };
+            let name = format!("@rpath/{}", plan.libraries[library].dest.display());
+            changes.dylibs.insert(dylib.name, name);
+        }
+        // Absolute run paths point outside the bundle, where the embedded
+        // libraries came from.
+        changes.delete_rpaths = rpaths
+            .into_iter()
+            .filter(|rpath| rpath.starts_with('/') && !is_system(Path::new(rpath)))
+            .collect();
+
+        match index {
+            Some(index) => {
+                let target = &mut plan.libraries[index].changes;
+                changes.install_name = target.install_name.take();
+                changes.add_rpaths = std::mem::take(&mut target.add_rpaths);
+                *target = changes;
+            }
+            None => {
+                let target = &mut plan.executable;
+                target.dylibs.append(&mut changes.dylibs);
+                for rpath in changes.delete_rpaths {
+                    if !target.delete_rpaths.contains(&rpath) {
+                        target.delete_rpaths.push(rpath);
+                    }
+                }
+                target.add_rpaths = vec![EXECUTABLE_RPATH.to_string()];
+            }
+        }
+    }
+    Ok(plan)
+}
+
+impl Plan {
+    /// Whether there is nothing to embed.
+    pub fn is_empty(&self) -> bool {
+        self.libraries.is_empty()
+    }
+
+    /// The files and `.framework` directories which will be copied.
+    pub fn sources(&self) -> BTreeSet<&Path> {
+        self.libraries
+            .iter()
+            .map(|library| {
+                library
+                    .framework
+                    .as_ref()
+                    .unwrap_or(&library.source)
+                    .as_path()
+            })
+            .collect()
+    }
+
+    /// Copy the libraries into the `Contents/Frameworks` directory at
+    /// `frameworks`, and rewrite them and the bundled copy of the executable
+    /// at `executable`.  On macOS, each rewritten file is signed again with
+    /// an ad-hoc signature, as rewriting invalidates the original, and the
+    /// executable's signature includes the `entitlements` file, if any.
+    pub fn apply(
+        &self,
+        executable: &Path,
+        frameworks: &Path,
+        entitlements: Option<&Path>,
+    ) -> Result<(), Error> {
+        if self.is_empty() {
+            return Ok(());
+        }
+        fs::create_dir_all(frameworks).map_err(|err| Error::io(frameworks, err))?;
+        let mut copied = BTreeSet::new();
+        for library in &self.libraries {
+            let dest = frameworks.join(&library.dest);
+            match &library.framework {
+                Some(root) => {
+                    if copied.insert(root) {
+                        let name = root.file_name().unwrap();
+                        crate::resource::copy_dir(root, &frameworks.join(name))?;
+                    }
+                }
+                None => {
+                    fs::copy(&library.source, &dest).map_err(|err| Error::io(&dest, err))?;
+                }
+            }
+            rewrite(&dest, &library.changes, None)?;
+        }
+        rewrite(executable, &self.executable, entitlements)
+    }
+}
+
+/// Apply `changes` to the Mach-O file at `path`, and sign it again.
+fn rewrite(path: &Path, changes: &Changes, entitlements: Option<&Path>) -> Result<(), Error> {
+    let mut bytes = fs::read(path).map_err(|err| Error::io(path, err))?;
+    macho::rewrite(&mut bytes, changes).map_err(|source| Error::MachO {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    // Installed libraries are often read-only.
+    let mut permissions = fs::metadata(path)
+        .map_err(|err| Error::io(path, err))?
+        .permissions();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        permissions.set_mode(permissions.mode() | 0o200);
+    }
+    #[cfg(not(unix))]
+    permissions.set_readonly(false);
+    fs::set_permissions(path, permissions).map_err(|err| Error::io(path, err))?;
+    fs::write(path, bytes).map_err(|err| Error::io(path, err))?;
+    sign(path, entitlements)
+}
+
+/// Replace the code signature of `path` with an ad-hoc signature, which
+/// Apple Silicon requires of every executable and library.
+#[cfg(target_os = "macos")]
+fn sign(path: &Path, entitlements: Option<&Path>) -> Result<(), Error> {
+    let mut command = std::process::Command::new("codesign");
+    command.args(["--force", "--sign", "-"]);
+    if let Some(entitlements) = entitlements {
+        command.arg("--entitlements").arg(entitlements);
+    }
+    let output = command.arg(path).output().map_err(|err| Error::CodeSign {
+        path: path.to_path_buf(),
+        message: err.to_string(),
+    })?;
+    match output.status.success() {
+        true => Ok(()),
+        false => Err(Error::CodeSign {
+            path: path.to_path_buf(),
+            message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        }),
+    }
+}
+
+/// Code signing is only possible on macOS, so the bundle must be signed
+/// there before it is run.
+#[cfg(not(target_os = "macos"))]
+fn sign(_path: &Path, _entitlements: Option<&Path>) -> Result<(), Error> {
+    Ok(())
+}
+
+// End of File
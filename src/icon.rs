@@ -0,0 +1,481 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Application icons, and an encoder for the Apple Icon Image (`.icns`)
+//! format in which they are stored within an app bundle.
+//!
+//! An `.icns` file is a big-endian container: the magic `icns`, the total
+//! file length, and then a sequence of entries, each consisting of a
+//! four-character type code, the entry length including its eight byte
+//! header, and the entry data.  Modern icon types simply contain a PNG
+//! image.  Each type holds one pixel size at one display scale, e.g. `ic11`
+//! is the 16x16 point icon at @2x, which is 32x32 pixels.  A leading `TOC `
+//! entry lists the type and length of every other entry, as written by
+//! `iconutil`.
+//!
+//! Icons are supplied as one or more square PNG images.  Each icon type is
+//! filled from the source image of exactly its pixel size if there is one,
+//! and otherwise by downscaling the smallest larger source image.  Types
+//! larger than every source image are left out rather than upscaled.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::Error as IOError,
+    path::{Path, PathBuf},
+};
+
+/// The name of the icon file in `Contents/Resources`, as it appears in the
+/// `CFBundleIconFile` key, which by convention omits the extension.
+pub(crate) const ICON_FILE: &str = "AppIcon";
+
+/// The icon types written to an `.icns` file, in order, as
+/// `(type, points, scale)`.  The pixel size is `points * scale`.
+pub const ICON_TYPES: [([u8; 4], u32, u32); 11] = [
+    (*b"icp4", 16, 1),
+    (*b"icp5", 32, 1),
+    (*b"icp6", 64, 1),
+    (*b"ic07", 128, 1),
+    (*b"ic08", 256, 1),
+    (*b"ic09", 512, 1),
+    (*b"ic10", 512, 2),
+    (*b"ic11", 16, 2),
+    (*b"ic12", 32, 2),
+    (*b"ic13", 128, 2),
+    (*b"ic14", 256, 2),
+];
+
+/// An entry of an `.icns` file: its four-character type and its data.
+pub type Entry = ([u8; 4], Vec<u8>);
+
+/// The `.icns` file signature.
+const MAGIC: &[u8; 4] = b"icns";
+/// The type of the table of contents entry.
+const TOC: &[u8; 4] = b"TOC ";
+/// The length of the file header, and of each entry header.
+const HEADER_LEN: usize = 8;
+
+/// A source image for the application icon, to be loaded when the bundle is
+/// generated, either from a file or from bytes in memory (e.g. via
+/// `include_bytes!`).  Must be a square PNG image.
+#[derive(Clone, Debug)]
+pub enum Icon {
+    /// Read the PNG image from this file.
+    Path(PathBuf),
+    /// Decode the PNG image from these bytes.
+    Png(Vec<u8>),
+}
+
+impl Icon {
+    /// Read and decode the image.
+    pub fn load(&self) -> Result<Image, Error> {
+        match self {
+            Icon::Path(path) => Image::from_png(std::fs::read(path)?),
+            Icon::Png(bytes) => Image::from_png(bytes.clone()),
+        }
+    }
+}
+
+impl From<&Path> for Icon {
+    fn from(path: &Path) -> Self {
+        Icon::Path(path.to_path_buf())
+    }
+}
+impl From<PathBuf> for Icon {
+    fn from(path: PathBuf) -> Self {
+        Icon::Path(path)
+    }
+}
+impl From<&[u8]> for Icon {
+    fn from(bytes: &[u8]) -> Self {
+        Icon::Png(bytes.to_vec())
+    }
+}
+impl<const N: usize> From<&[u8; N]> for Icon {
+    fn from(bytes: &[u8; N]) -> Self {
+        Icon::Png(bytes.to_vec())
+    }
+}
+impl From<Vec<u8>> for Icon {
+    fn from(bytes: Vec<u8>) -> Self {
+        Icon::Png(bytes)
+    }
+}
+
+/// A decoded image with 8-bit RGBA pixels, which remembers the PNG it was
+/// decoded from so that it can be stored without re-encoding.
+#[derive(Clone, Debug)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+    png: Vec<u8>,
+}
+
+impl Image {
+    /// Decode a PNG image of any color type and bit depth.
+    pub fn from_png(png: Vec<u8>) -> Result<Self, Error> {
+        let mut decoder = png::Decoder::new(png.as_slice());
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder
+            .read_info()
+            .map_err(|err| Error::Png(err.to_string()))?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader
+            .next_frame(&mut buf)
+            .map_err(|err| Error::Png(err.to_string()))?;
+        buf.truncate(info.buffer_size());
+        let rgba = match info.color_type {
+            png::ColorType::Rgba => buf,
+            png::ColorType::Rgb => buf
+                .chunks_exact(3)
+                .flat_map(|p| [p[0], p[1], p[2], 0xff])
+                .collect(),
+            png::ColorType::GrayscaleAlpha => buf
+                .chunks_exact(2)
+                .flat_map(|p| [p[0], p[0], p[0], p[1]])
+                .collect(),
+            png::ColorType::Grayscale => buf.iter().flat_map(|&g| [g, g, g, 0xff]).collect(),
+            png::ColorType::Indexed => {
+                return Err(Error::Png("indexed color was not expanded".to_string()))
+            }
+        };
+        Ok(Image {
+            width: info.width,
+            height: info.height,
+            rgba,
+            png,
+        })
+    }
+
+    /// Create an image from 8-bit RGBA pixels, in rows from top to bottom.
+    /// Returns `None` if either dimension is zero, or if `rgba` is not
+    /// `width * height * 4` bytes long.
+    pub fn from_rgba(width: u32, height: u32, rgba: Vec<u8>) -> Option<Self> {
+        if width == 0 || height == 0 || rgba.len() as u64 != width as u64 * height as u64 * 4 {
+            return None;
+        }
+        let png = encode_png(width, height, &rgba);
+        Some(Image {
+            width,
+            height,
+            rgba,
+            png,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    /// The 8-bit RGBA pixels, in rows from top to bottom.
+    pub fn rgba(&self) -> &[u8] {
+        &self.rgba
+    }
+    /// The image encoded as a PNG.
+    pub fn png(&self) -> &[u8] {
+        &self.png
+    }
+
+    /// Downscale to `width` by `height` pixels, averaging the source pixels
+    /// which each target pixel covers.  Color is weighted by alpha, so that
+    /// fully transparent pixels do not bleed into the result.  Requesting a
+    /// size larger than the image upscales with the same filter, which is
+    /// equivalent to nearest-neighbor.  Returns `None` if either dimension
+    /// is zero.
+    pub fn resize(&self, width: u32, height: u32) -> Option<Image> {
+        if width == 0 || height == 0 {
+            return None;
+        }
+        let (w, h) = (self.width as usize, self.height as usize);
+        let (nw, nh) = (width as usize, height as usize);
+        let mut pixels: Vec<f32> = self
+            .rgba
+            .chunks_exact(4)
+            .flat_map(|p| {
+                let a = p[3] as f32 / 255.0;
+                [
+                    p[0] as f32 * a,
+                    p[1] as f32 * a,
+                    p[2] as f32 * a,
+                    p[3] as f32,
+                ]
+            })
+            .collect();
+        pixels = resample_rows(&pixels, w, h, nw);
+        pixels = transpose(&pixels, nw, h);
+        pixels = resample_rows(&pixels, h, nw, nh);
+        pixels = transpose(&pixels, nh, nw);
+        let rgba: Vec<u8> = pixels
+            .chunks_exact(4)
+            .flat_map(|p| {
+                let a = p[3] / 255.0;
+                let c = |v: f32| {
+                    if a > 0.0 {
+                        (v / a).round().clamp(0.0, 255.0) as u8
+                    } else {
+                        0
+                    }
+                };
+                [
+                    c(p[0]),
+                    c(p[1]),
+                    c(p[2]),
+                    p[3].round().clamp(0.0, 255.0) as u8,
+                ]
+            })
+            .collect();
+        let png = encode_png(width, height, &rgba);
+        Some(Image {
+            width,
+            height,
+            rgba,
+            png,
+        })
+    }
+}
+
+/// Encode 8-bit RGBA pixels as a PNG image.
+fn encode_png(width: u32, height: u32, rgba: &[u8]) -> Vec<u8> {
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    // Writing to a Vec<u8> cannot fail, and the dimensions and buffer length
+    // were checked by the caller.
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(rgba).unwrap();
+    writer.finish().unwrap();
+    png
+}
+
+/// Resample each row of a `width` by `height` image of four-channel pixels
+/// to `new_width` pixels, with each target pixel the coverage-weighted
+/// average of the source pixels it overlaps.
+fn resample_rows(src: &[f32], width: usize, height: usize, new_width: usize) -> Vec<f32> {
+    // The source pixels and weights contributing to each target pixel.
+    let scale = width as f64 / new_width as f64;
+    let weights: Vec<(usize, Vec<f32>)> = (0..new_width)
+        .map(|x| {
+            let start = x as f64 * scale;
+            let end = (x + 1) as f64 * scale;
+            let first = start.floor() as usize;
+            let last = (end.ceil() as usize).min(width);
+            let weights = (first..last)
+                .map(|i| ((((i + 1) as f64).min(end) - (i as f64).max(start)) / scale) as f32)
+                .collect();
+            (first, weights)
+        })
+        .collect();
+
+    let mut dst = Vec::with_capacity(new_width * height * 4);
+    for row in src.chunks_exact(width * 4) {
+        for (first, weights) in &weights {
+            let mut pixel = [0.0f32; 4];
+            for (i, weight) in weights.iter().enumerate() {
+                let s = (first + i) * 4;
+                for c in 0..4 {
+                    pixel[c] += row[s + c] * weight;
+                }
+            }
+            dst.extend_from_slice(&pixel);
+        }
+    }
+    dst
+}
+
+/// Swap the rows and columns of a `width` by `height` image of four-channel
+/// pixels.
+fn transpose(src: &[f32], width: usize, height: usize) -> Vec<f32> {
+    let mut dst = vec![0.0; src.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let s = (y * width + x) * 4;
+            let d = (x * height + y) * 4;
+            dst[d..d + 4].copy_from_slice(&src[s..s + 4]);
+        }
+    }
+    dst
+}
+
+/// A collection of source images from which an `.icns` file is generated.
+#[derive(Clone, Debug, Default)]
+pub struct IconSet {
+    images: Vec<Image>,
+}
+
+impl IconSet {
+    pub fn new() -> Self {
+        IconSet::default()
+    }
+
+    /// Add a source image, which must be square.  An image of the same size
+    /// as one already added replaces it.
+    pub fn add(&mut self, image: Image) -> Result<&mut Self, Error> {
+        if image.width != image.height {
+            return Err(Error::NotSquare {
+                width: image.width,
+                height: image.height,
+            });
+        }
+        self.images.retain(|i| i.width != image.width);
+        self.images.push(image);
+        self.images.sort_by_key(|i| i.width);
+        Ok(self)
+    }
+
+    /// The PNG for an icon of `size` by `size` pixels, or `None` if every
+    /// source image is smaller.
+    fn png(&self, size: u32) -> Option<Vec<u8>> {
+        let image = self.images.iter().find(|i| i.width >= size)?;
+        Some(match image.width == size {
+            true => image.png.clone(),
+            false => image.resize(size, size)?.png,
+        })
+    }
+
+    /// Generate the `.icns` file.  Fails if there are no source images, or
+    /// if none is at least 16x16 pixels.
+    pub fn to_icns(&self) -> Result<Vec<u8>, Error> {
+        let largest = match self.images.last() {
+            Some(image) => image.width,
+            None => return Err(Error::NoImages),
+        };
+        // Several types share a pixel size, e.g. `icp5` and `ic11`, so only
+        // generate each size once.
+        let mut pngs: BTreeMap<u32, Option<Vec<u8>>> = BTreeMap::new();
+        let entries: Vec<Entry> = ICON_TYPES
+            .iter()
+            .filter_map(|(ty, points, scale)| {
+                let size = points * scale;
+                let png = pngs.entry(size).or_insert_with(|| self.png(size));
+                Some((*ty, png.clone()?))
+            })
+            .collect();
+        if entries.is_empty() {
+            return Err(Error::TooSmall { size: largest });
+        }
+        Ok(write_icns(&entries))
+    }
+}
+
+/// Assemble an `.icns` file from `(type, data)` entries, preceded by a table
+/// of contents.
+pub fn write_icns(entries: &[Entry]) -> Vec<u8> {
+    let toc_len = HEADER_LEN + entries.len() * HEADER_LEN;
+    let total = HEADER_LEN
+        + toc_len
+        + entries
+            .iter()
+            .map(|(_, data)| HEADER_LEN + data.len())
+            .sum::<usize>();
+    let mut icns = Vec::with_capacity(total);
+    icns.extend_from_slice(MAGIC);
+    icns.extend_from_slice(&(total as u32).to_be_bytes());
+    icns.extend_from_slice(TOC);
+    icns.extend_from_slice(&(toc_len as u32).to_be_bytes());
+    for (ty, data) in entries {
+        icns.extend_from_slice(ty);
+        icns.extend_from_slice(&((HEADER_LEN + data.len()) as u32).to_be_bytes());
+    }
+    for (ty, data) in entries {
+        icns.extend_from_slice(ty);
+        icns.extend_from_slice(&((HEADER_LEN + data.len()) as u32).to_be_bytes());
+        icns.extend_from_slice(data);
+    }
+    icns
+}
+
+/// Split an `.icns` file into its `(type, data)` entries, in file order,
+/// including any table of contents.
+pub fn read_icns(bytes: &[u8]) -> Result<Vec<Entry>, Error> {
+    let malformed = |offset: usize, message: &str| Error::Icns {
+        offset,
+        message: message.to_string(),
+    };
+    let header = |offset: usize| -> Result<([u8; 4], usize), Error> {
+        let bytes = bytes
+            .get(offset..offset + HEADER_LEN)
+            .ok_or_else(|| malformed(offset, "truncated header"))?;
+        let len = u32::from_be_bytes(bytes[4..].try_into().unwrap()) as usize;
+        Ok((bytes[..4].try_into().unwrap(), len))
+    };
+
+    let (magic, total) = header(0)?;
+    if &magic != MAGIC {
+        return Err(malformed(0, "missing `icns` signature"));
+    }
+    if total != bytes.len() {
+        return Err(malformed(4, "file length does not match header"));
+    }
+    let mut entries = Vec::new();
+    let mut offset = HEADER_LEN;
+    while offset < total {
+        let (ty, len) = header(offset)?;
+        if len < HEADER_LEN || len > total - offset {
+            return Err(malformed(offset + 4, "entry length out of range"));
+        }
+        entries.push((ty, bytes[offset + HEADER_LEN..offset + len].to_vec()));
+        offset += len;
+    }
+    Ok(entries)
+}
+
+/// An error encountered while loading icon images or generating an `.icns`
+/// file.
+#[derive(Debug)]
+pub enum Error {
+    /// The image file could not be read.
+    Io(IOError),
+    /// The image is not a valid PNG.
+    Png(String),
+    /// The image is not square.
+    NotSquare { width: u32, height: u32 },
+    /// No source images were provided.
+    NoImages,
+    /// Every source image is smaller than the smallest icon size, 16x16.
+    TooSmall { size: u32 },
+    /// The `.icns` file is malformed.  `offset` is the byte offset at which
+    /// the problem was found.
+    Icns { offset: usize, message: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "error reading icon: {}", err),
+            Error::Png(message) => write!(f, "invalid PNG image: {}", message),
+            Error::NotSquare { width, height } => {
+                write!(f, "icon image must be square, not {}x{}", width, height)
+            }
+            Error::NoImages => write!(f, "no icon images"),
+            Error::TooSmall { size } => {
+                write!(f, "icon image is {0}x{0}, but must be at least 16x16", size)
+            }
+            Error::Icns { offset, message } => {
+                write!(f, "invalid icns file at offset {}: {}", offset, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<IOError> for Error {
+    fn from(err: IOError) -> Self {
+        Error::Io(err)
+    }
+}
+
+// End of File
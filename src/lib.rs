@@ -7,7 +7,60 @@
 //! order to access OS features that are only available to app bundles and not
 //! command-line applications.
 
-use std::{io::Error as IOError, path::PathBuf, process::ExitCode};
+use std::{
+    collections::BTreeMap,
+    path::{Component, Path, PathBuf},
+    process::ExitCode,
+    sync::mpsc::{self, Receiver},
+};
+
+mod error;
+pub use error::Error;
+
+mod bundle;
+pub use bundle::{Bundle, BundleBuilder};
+
+mod validate;
+pub use validate::{ValidationError, Warning};
+
+mod version;
+pub use version::{BundleVersion, Stage, VersionError};
+
+mod package;
+pub use package::PackageInfo;
+
+#[cfg(feature = "build")]
+pub mod build;
+
+pub mod icon;
+use icon::{Icon, IconSet};
+
+mod resource;
+use resource::Resource;
+pub use resource::ResourceFilter;
+
+mod frameworks;
+pub mod macho;
+mod manifest;
+
+pub mod strings;
+use strings::{Localization, Strings};
+
+pub mod entitlements;
+pub use entitlements::Entitlements;
+
+mod permission;
+pub use permission::Permission;
+
+pub mod document;
+use document::{DocumentType, TypeDeclaration};
+
+mod open;
+pub use open::OpenRequest;
+
+pub mod plist;
+pub use plist::InfoPlist;
+use plist::{Dictionary, Template, Value};
 
 mod platform_impl;
 use platform_impl::{MainThreadMarker, NSApplication, NSBundle, Retained};
@@ -26,6 +79,46 @@ pub enum InstallDir {
     Custom(PathBuf),
 }
 
+impl InstallDir {
+    /// Resolve to an absolute directory path, which can be passed to
+    /// [`BundleBuilder::new()`].
+    pub fn resolve(self) -> Result<PathBuf, Error> {
+        match self {
+            InstallDir::Temp => Ok(std::env::temp_dir()),
+            InstallDir::SystemApplications => Ok(PathBuf::from("/Applications")),
+            InstallDir::UserApplications => match dirs::home_dir() {
+                Some(home) => Ok(home.join("Applications")),
+                None => Err(Error::InstallDir {
+                    path: None,
+                    source: None,
+                }),
+            },
+            InstallDir::Custom(path) => match std::fs::canonicalize(&path) {
+                Ok(path) => Ok(path),
+                Err(err) => Err(Error::InstallDir {
+                    path: Some(path),
+                    source: Some(err),
+                }),
+            },
+        }
+    }
+}
+
+/// How the application presents itself to the user, which decides whether
+/// it has a Dock icon and a menu bar.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Presentation {
+    /// An ordinary application, with a Dock icon and a menu bar.
+    #[default]
+    Regular,
+    /// An agent application, e.g. a menu bar utility, which has no Dock
+    /// icon or menu bar but may show windows.  Sets `LSUIElement`.
+    Accessory,
+    /// A background-only application, e.g. a helper, which has no user
+    /// interface at all.  Sets `LSBackgroundOnly`.
+    BackgroundOnly,
+}
+
 /// The applicaiton relauncher, which is used to build the app bundle, launch
 /// it as a subprocess, and then wait for it to exit.  Or if we are already
 /// running from within an app bundle, do nothing.
@@ -37,26 +130,140 @@ pub struct Trampoline {
     /// DNS format, e.g. "org.example.MyApp", and must contain only
     /// alpha-numeric characters, '-', and '.'.
     ident: String,
-    /// The version number of the application, which should be in the format
-    /// "major.minor.patch", e.g. "1.0.0".
+    /// The version number of the application, as a semantic version string
+    /// which is parsed into a [`BundleVersion`] when the bundle is generated.
     version: String,
+    /// Metadata of the application's crate, if known.
+    package: Option<PackageInfo>,
+    /// The directory searched last by [`Application::resource_path()`].
+    source_dir: Option<PathBuf>,
+    /// An `Info.plist` fragment to merge over the generated defaults.
+    plist_template: Option<Template>,
+    /// Additional `Info.plist` keys supplied by the caller, which are merged
+    /// over the generated defaults and the template.
+    plist: Dictionary,
+    /// Source images for the application icon.
+    icons: Vec<Icon>,
+    /// Files and directories to copy into `Contents/Resources`.
+    resources: Vec<Resource>,
+    /// Whether to copy dependent dynamic libraries into
+    /// `Contents/Frameworks`.
+    embed_dylibs: bool,
+    /// Whether to derive system requirements from the executable.
+    inspect_executable: bool,
+    /// Per-architecture builds to combine into a universal executable, or
+    /// empty to bundle the executable as-is.
+    universal: Vec<PathBuf>,
+    /// Whether to use the 64-bit fat format for the universal executable.
+    fat64: bool,
+    /// Whether to rewrite an existing bundle from scratch.
+    force_rebuild: bool,
+    /// The locale of the unlocalized strings, if set explicitly.
+    development_region: Option<String>,
+    /// `InfoPlist.strings` entries by locale.
+    localizations: BTreeMap<String, Localization>,
+    /// Code signing entitlements, if any.
+    entitlements: Option<Entitlements>,
+    /// Unlocalized privacy usage descriptions.
+    usage_descriptions: BTreeMap<Permission, String>,
+    /// Kinds of files the application can open.
+    document_types: Vec<DocumentType>,
+    /// Uniform type identifiers owned by the application.
+    exported_types: Vec<TypeDeclaration>,
+    /// Uniform type identifiers owned by other applications.
+    imported_types: Vec<TypeDeclaration>,
+    /// URL schemes which open the application.
+    url_schemes: Vec<String>,
+    /// Whether the application has a Dock icon and menu bar.
+    presentation: Presentation,
+    /// `LSMinimumSystemVersion`, if set explicitly.
+    minimum_system_version: Option<String>,
+    /// `LSMinimumSystemVersionByArchitecture`.
+    minimum_system_version_by_arch: Vec<(macho::Arch, String)>,
+    /// `LSArchitecturePriority`, if set explicitly.
+    architecture_priority: Vec<macho::Arch>,
+    /// `LSRequiresNativeExecution`, if set.
+    requires_native_execution: Option<bool>,
 }
 
 impl Trampoline {
+    /// Create a trampoline with the given name and bundle identifier.
+    ///
+    /// The version defaults to that of the `relaunch` crate, because that is
+    /// the only `CARGO_PKG_VERSION` visible here.  Use the [`trampoline!`]
+    /// macro to pick up the version of the calling crate instead, or set it
+    /// explicitly with [`Self::version()`].
     pub fn new(name: &str, ident: &str) -> Self {
         Trampoline {
-            name: name.to_string(),
+            name: validate::normalize_name(name),
             ident: ident.to_string(),
-            // FIXME: This defaults to the relaunch crate version, not the
-            //        version of the binary being built.  This is almost
-            //        certainly not what the user wants.
             version: env!("CARGO_PKG_VERSION").to_string(),
+            package: None,
+            source_dir: None,
+            plist_template: None,
+            plist: Dictionary::new(),
+            icons: Vec::new(),
+            resources: Vec::new(),
+            embed_dylibs: false,
+            inspect_executable: false,
+            universal: Vec::new(),
+            fat64: false,
+            force_rebuild: false,
+            development_region: None,
+            localizations: BTreeMap::new(),
+            entitlements: None,
+            usage_descriptions: BTreeMap::new(),
+            document_types: Vec::new(),
+            exported_types: Vec::new(),
+            imported_types: Vec::new(),
+            url_schemes: Vec::new(),
+            presentation: Presentation::Regular,
+            minimum_system_version: None,
+            minimum_system_version_by_arch: Vec::new(),
+            architecture_priority: Vec::new(),
+            requires_native_execution: None,
         }
     }
 
+    /// Create a trampoline configured from the `[package.metadata.relaunch]`
+    /// table of the application's `Cargo.toml`, as embedded by the
+    /// [`cargo_metadata!`] macro.  Builder methods such as [`Self::name()`],
+    /// [`Self::ident()`], and [`Self::version()`] may be called afterwards
+    /// to override the manifest.  See the `build` module, enabled by the
+    /// `build` feature, for the supported keys.
+    ///
+    /// ```ignore
+    /// let app = relaunch::Trampoline::from_cargo_metadata(relaunch::cargo_metadata!())?
+    ///     .bundle(relaunch::InstallDir::Temp)?;
+    /// ```
+    pub fn from_cargo_metadata(metadata: &str) -> Result<Self, Error> {
+        let metadata = package::CargoMetadata::from_str(metadata)?;
+        let name = metadata.name.as_deref().unwrap_or(&metadata.package.name);
+        let mut trampoline = Trampoline::new(name, &metadata.ident);
+        trampoline.package(metadata.package);
+        if let Some(dir) = metadata.manifest_dir {
+            trampoline.source_dir(dir);
+        }
+        if let Some(version) = metadata.version {
+            trampoline.version(version);
+        }
+        if let Some(category) = metadata.category {
+            trampoline.plist_key("LSApplicationCategoryType", category);
+        }
+        if let Some(template) = metadata.info_plist {
+            trampoline.info_plist_template(template);
+        }
+        for image in metadata.icons {
+            trampoline.icon(image);
+        }
+        trampoline.merge_plist(metadata.plist);
+        Ok(trampoline)
+    }
+
     /// Set the name of the app bundle.  Overrides value provided to `new()`.
+    /// The name is normalized to Unicode Normalization Form C.
     pub fn name(&mut self, name: &str) -> &mut Self {
-        self.name = name.to_string();
+        self.name = validate::normalize_name(name);
         self
     }
     /// Set the app bundle ID.  Overrides value provided to `new()`.
@@ -64,12 +271,688 @@ impl Trampoline {
         self.ident = ident.to_string();
         self
     }
-    /// Set the app bundle version.  Overrides the default value pulled from
-    /// `CARGO_PKG_VERSION`.
-    pub fn version(&mut self, version: &str) -> &mut Self {
+    /// Set the app bundle version, either as a [`BundleVersion`] or as a
+    /// semantic version string such as `"1.2.0-beta.3"`.  Overrides the
+    /// default value pulled from `CARGO_PKG_VERSION`.  A string which cannot
+    /// be represented as a [`BundleVersion`] is reported by
+    /// [`Self::validate()`].
+    pub fn version<V: ToString>(&mut self, version: V) -> &mut Self {
         self.version = version.to_string();
         self
     }
+    /// Record the metadata of the application's crate, and take the bundle
+    /// version from it.  Called by the [`trampoline!`] macro; a later call
+    /// to [`Self::version()`] still overrides the version.
+    pub fn package(&mut self, package: PackageInfo) -> &mut Self {
+        self.version = package.version.clone();
+        self.package = Some(package);
+        self
+    }
+    /// The metadata of the application's crate, if it was provided via
+    /// [`Self::package()`] or the [`trampoline!`] macro.
+    pub fn package_info(&self) -> Option<&PackageInfo> {
+        self.package.as_ref()
+    }
+
+    /// Add a source image for the application icon, which must be a square
+    /// PNG.  Call repeatedly to supply several sizes, e.g. a detailed
+    /// 1024x1024 image and a simplified 32x32 image.  The images are packed
+    /// into `Contents/Resources/AppIcon.icns` when the bundle is generated,
+    /// with any missing icon sizes downscaled from the next larger image.
+    /// See the [`icon`] module for details.
+    pub fn icon<I: Into<Icon>>(&mut self, image: I) -> &mut Self {
+        self.icons.push(image.into());
+        self
+    }
+
+    /// Load the icon images and encode them as an `.icns` file, or return
+    /// `None` if no icon was set.
+    pub(crate) fn icns(&self) -> Result<Option<Vec<u8>>, Error> {
+        if self.icons.is_empty() {
+            return Ok(None);
+        }
+        let mut set = IconSet::new();
+        for icon in &self.icons {
+            let path = match icon {
+                Icon::Path(path) => Some(path.clone()),
+                Icon::Png(_) => None,
+            };
+            icon.load()
+                .and_then(|image| set.add(image).map(|_| ()))
+                .map_err(|source| Error::Icon { path, source })?;
+        }
+        set.to_icns()
+            .map(Some)
+            .map_err(|source| Error::Icon { path: None, source })
+    }
+
+    /// Set the development source directory, which
+    /// [`Application::resource_path()`] searches after the bundle and the
+    /// directory of the executable, so that resources are found while
+    /// running unbundled, e.g. via `cargo run` on another platform.  Set
+    /// automatically to the crate's manifest directory by [`trampoline!`]
+    /// and [`Self::from_cargo_metadata()`].
+    pub fn source_dir<P: AsRef<Path>>(&mut self, dir: P) -> &mut Self {
+        self.source_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set the name shown to users whose preferred language is `locale`,
+    /// e.g. `"fr"` or `"pt-BR"`, by localizing `CFBundleDisplayName` and
+    /// `CFBundleName`.  The bundle directory keeps the unlocalized name.
+    /// Shorthand for two calls to [`Self::localized_string()`].
+    pub fn localized_name(&mut self, locale: &str, name: &str) -> &mut Self {
+        let name = validate::normalize_name(name);
+        self.localized_string(locale, "CFBundleDisplayName", &name)
+            .localized_string(locale, "CFBundleName", &name)
+    }
+    /// Localize the `Info.plist` key `key` for `locale`.  Each locale's
+    /// strings are written to `Contents/Resources/<locale>.lproj/
+    /// InfoPlist.strings`, and the locales are listed in
+    /// `CFBundleLocalizations`.
+    pub fn localized_string(&mut self, locale: &str, key: &str, value: &str) -> &mut Self {
+        self.localizations
+            .entry(locale.to_string())
+            .or_default()
+            .strings
+            .insert(key.to_string(), value.to_string());
+        self
+    }
+    /// Import the `.strings` file at `path` as translations for `locale`,
+    /// e.g. an existing `fr.lproj/InfoPlist.strings`.  The file is read
+    /// when the bundle is generated; strings set with
+    /// [`Self::localized_string()`] take precedence over it.  See the
+    /// [`strings`] module for the accepted formats.
+    pub fn localized_strings_file<P: AsRef<Path>>(&mut self, locale: &str, path: P) -> &mut Self {
+        self.localizations
+            .entry(locale.to_string())
+            .or_default()
+            .files
+            .push(path.as_ref().to_path_buf());
+        self
+    }
+    /// Set `CFBundleDevelopmentRegion`, the locale of the unlocalized name
+    /// and strings, which is used when none of the user's preferred
+    /// languages is available.  Defaults to `"en"`.
+    pub fn development_region(&mut self, locale: &str) -> &mut Self {
+        self.development_region = Some(locale.to_string());
+        self
+    }
+
+    /// Load the `InfoPlist.strings` entries of each locale.
+    pub(crate) fn localized_strings(&self) -> Result<BTreeMap<String, Strings>, Error> {
+        self.localizations
+            .iter()
+            .map(|(locale, localization)| Ok((locale.clone(), localization.load()?)))
+            .collect()
+    }
+
+    /// Copy the file `src` into the bundle at `dest`, a path relative to
+    /// `Contents/Resources`, e.g. `fonts/Inter.ttf`.  The file's permissions
+    /// are preserved, and if `src` is a symbolic link, the file it points
+    /// to is copied.  Resources
+    /// are copied in the order they are declared, so a later resource
+    /// replaces an earlier one at the same destination.
+    pub fn resource<P: AsRef<Path>, Q: AsRef<Path>>(&mut self, src: P, dest: Q) -> &mut Self {
+        self.resources.push(Resource {
+            source: src.as_ref().to_path_buf(),
+            dest: dest.as_ref().to_path_buf(),
+            filter: None,
+        });
+        self
+    }
+    /// Copy the directory tree `src` into the bundle at `dest`, a path
+    /// relative to `Contents/Resources`.  An empty `dest` (or `"."`) copies
+    /// the contents of `src` directly into `Contents/Resources`.
+    /// Permissions are preserved, except that directories stay writable by
+    /// their owner.  If `src` is a symbolic link it is followed, but links
+    /// within the tree are copied as links rather than followed.
+    pub fn resource_dir<P: AsRef<Path>, Q: AsRef<Path>>(&mut self, src: P, dest: Q) -> &mut Self {
+        self.resource_dir_filtered(src, dest, ResourceFilter::new())
+    }
+    /// Like [`Self::resource_dir()`], but copy only the files selected by
+    /// the glob patterns of `filter`.
+    pub fn resource_dir_filtered<P: AsRef<Path>, Q: AsRef<Path>>(
+        &mut self,
+        src: P,
+        dest: Q,
+        filter: ResourceFilter,
+    ) -> &mut Self {
+        self.resources.push(Resource {
+            source: src.as_ref().to_path_buf(),
+            dest: dest.as_ref().to_path_buf(),
+            filter: Some(filter),
+        });
+        self
+    }
+
+    /// Copy the non-system dynamic libraries the executable links against,
+    /// and their dependencies in turn, into `Contents/Frameworks`, and
+    /// rewrite their install names to `@rpath/<name>` with the executable's
+    /// run path set to `@executable_path/../Frameworks`.  This makes the
+    /// bundle self-contained when the executable links against e.g.
+    /// Homebrew libraries.  Libraries in `/usr/lib` and `/System` are never
+    /// embedded.  Disabled by default.
+    ///
+    /// Rewriting load commands requires free space in the Mach-O header, so
+    /// link with `-C link-arg=-Wl,-headerpad_max_install_names` if bundling
+    /// fails for lack of it.  Rewritten files are given an ad-hoc code
+    /// signature when bundling on macOS, and must be signed again before
+    /// running when bundling elsewhere.
+    pub fn embed_dylibs(&mut self, embed: bool) -> &mut Self {
+        self.embed_dylibs = embed;
+        self
+    }
+
+    /// Bundle a universal binary combining the given per-architecture builds
+    /// of the executable, e.g. from `target/aarch64-apple-darwin/release`
+    /// and `target/x86_64-apple-darwin/release`, instead of the executable
+    /// itself.  The builds must be distinct architectures of the same
+    /// program.  It is written to `Contents/MacOS` under the usual executable
+    /// name, with the permissions of the first build.  See
+    /// [`macho::build_fat()`] for the layout.
+    pub fn universal_binary<I, P>(&mut self, executables: I) -> &mut Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        self.universal = executables
+            .into_iter()
+            .map(|path| path.as_ref().to_path_buf())
+            .collect();
+        self
+    }
+    /// Write the universal binary in the 64-bit fat format, needed only for
+    /// executables larger than 4 GiB.  Requires macOS 10.15 or later.
+    pub fn fat64(&mut self, fat64: bool) -> &mut Self {
+        self.fat64 = fat64;
+        self
+    }
+
+    /// Remove any existing bundle and write it again from scratch, instead
+    /// of reusing the pieces whose inputs are unchanged.  By default,
+    /// [`BundleBuilder::build()`] records what went into the bundle, and on
+    /// the next build copies the executable, the resources, and
+    /// `Info.plist` again only if they would differ.  Set this if the bundle
+    /// might have been modified by hand, which is not detected.
+    pub fn force_rebuild(&mut self, force: bool) -> &mut Self {
+        self.force_rebuild = force;
+        self
+    }
+
+    /// Set the code signing entitlements, such as camera access or the
+    /// hardened runtime's just-in-time compilation exception.  They are
+    /// checked with [`Entitlements::validate()`] and written next to the
+    /// bundle as `<name>.entitlements`, whose path is returned in
+    /// [`Bundle::entitlements`] for the signing step, and are included
+    /// whenever relaunch itself signs the executable.
+    pub fn entitlements(&mut self, entitlements: Entitlements) -> &mut Self {
+        self.entitlements = Some(entitlements);
+        self
+    }
+
+    /// Explain to the user why the application needs access to a
+    /// privacy-protected resource, e.g. the camera.  macOS shows the
+    /// description when asking the user for access, and terminates an
+    /// application which asks without one.  Written to `Info.plist` under
+    /// [`Permission::key()`], e.g. `NSCameraUsageDescription`.
+    pub fn usage_description(&mut self, permission: Permission, description: &str) -> &mut Self {
+        self.usage_descriptions
+            .insert(permission, description.to_string());
+        self
+    }
+    /// Translate the usage description of `permission` for `locale`.  Like
+    /// other localized strings, it is only used if there is also an
+    /// unlocalized description set with [`Self::usage_description()`].
+    pub fn localized_usage_description(
+        &mut self,
+        locale: &str,
+        permission: Permission,
+        description: &str,
+    ) -> &mut Self {
+        self.localized_string(locale, permission.key(), description)
+    }
+
+    /// Declare a kind of file the application can open, written to
+    /// `CFBundleDocumentTypes`.  See the [`document`] module.
+    pub fn document_type(&mut self, document_type: DocumentType) -> &mut Self {
+        self.document_types.push(document_type);
+        self
+    }
+    /// Declare a uniform type identifier for a file format the application
+    /// owns, written to `UTExportedTypeDeclarations`.
+    pub fn exported_type(&mut self, declaration: TypeDeclaration) -> &mut Self {
+        self.exported_types.push(declaration);
+        self
+    }
+    /// Declare a uniform type identifier for a file format owned by another
+    /// application, written to `UTImportedTypeDeclarations`.  Should the
+    /// other application be installed, its declaration takes precedence.
+    pub fn imported_type(&mut self, declaration: TypeDeclaration) -> &mut Self {
+        self.imported_types.push(declaration);
+        self
+    }
+
+    /// Register the URL scheme `scheme`, without the trailing `:`, e.g.
+    /// `"myapp"` for `myapp://` links, which macOS then opens with the
+    /// application.  Written to `CFBundleURLTypes`.  The URLs are delivered
+    /// by [`Application::open_requests()`].  Schemes are case-insensitive.
+    pub fn url_scheme(&mut self, scheme: &str) -> &mut Self {
+        if !self
+            .url_schemes
+            .iter()
+            .any(|s| s.eq_ignore_ascii_case(scheme))
+        {
+            self.url_schemes.push(scheme.to_string());
+        }
+        self
+    }
+
+    /// Set how the application presents itself: with a Dock icon and menu
+    /// bar, the default, or as an agent or background-only application.
+    /// This is written to `Info.plist`, so that macOS never shows a Dock
+    /// icon for an agent application, even briefly while it launches, and
+    /// the activation policy of `NSApplication` is set to match when the
+    /// [`Application`] is created.
+    pub fn presentation(&mut self, presentation: Presentation) -> &mut Self {
+        self.presentation = presentation;
+        self
+    }
+
+    /// Check for configuration which is valid but likely a mistake, such as
+    /// enabling the camera entitlement without a camera usage description.
+    /// The generated `Info.plist` is checked, so descriptions from a
+    /// template or custom key count.  The same warnings are returned in
+    /// [`Bundle::warnings`] when a bundle is generated, along with those
+    /// which need the executable, e.g. a deployment target later than
+    /// `LSMinimumSystemVersion`, if [`Self::inspect_executable()`] is set.
+    pub fn warnings(&self) -> Result<Vec<Warning>, Error> {
+        Ok(self.warnings_for(&self.info_plist(&self.name)?, None))
+    }
+
+    /// Check the generated `Info.plist` `plist`, and the parsed `binary` if
+    /// given.  See [`Self::warnings()`].
+    pub(crate) fn warnings_for(
+        &self,
+        plist: &InfoPlist,
+        binary: Option<&macho::MachO>,
+    ) -> Vec<Warning> {
+        let mut warnings = match &self.entitlements {
+            Some(entitlements) => permission::check(plist, entitlements),
+            None => Vec::new(),
+        };
+        let minimum = plist.get("LSMinimumSystemVersion").and_then(Value::as_str);
+        let target = binary.and_then(|binary| binary.minimum_system_version());
+        if let (Some(minimum), Some(target)) = (minimum, target) {
+            // A malformed version from a template or custom key is left for
+            // macOS to interpret.
+            if validate::parse_system_version(minimum).is_ok_and(|minimum| minimum < target) {
+                warnings.push(Warning::DeploymentTarget {
+                    minimum_system_version: minimum.to_string(),
+                    deployment_target: target,
+                });
+            }
+        }
+        warnings
+    }
+
+    /// Read the executable's Mach-O headers when the bundle is generated, and
+    /// derive `LSMinimumSystemVersion` from its deployment target and
+    /// `LSArchitecturePriority` from the architectures it contains, instead
+    /// of assuming macOS 10.10.  Like the other generated defaults, these
+    /// may be overridden by a template or custom key.  Disabled by default,
+    /// since it requires the executable to be a Mach-O binary; see
+    /// [`macho::MachO`] to inspect one directly.
+    pub fn inspect_executable(&mut self, inspect: bool) -> &mut Self {
+        self.inspect_executable = inspect;
+        self
+    }
+
+    /// Set `LSMinimumSystemVersion`, the earliest version of macOS the
+    /// application runs on, as `major[.minor[.patch]]`, e.g. `"11.0"`.  On
+    /// earlier versions, macOS refuses to launch it with an explanation,
+    /// rather than letting it crash.  Takes precedence over the deployment
+    /// target found by [`Self::inspect_executable()`]; if neither is given,
+    /// it is 10.10.  The version is checked by [`Self::validate()`].
+    pub fn minimum_system_version(&mut self, version: &str) -> &mut Self {
+        self.minimum_system_version = Some(version.to_string());
+        self
+    }
+    /// Set the minimum version of macOS for running the application's
+    /// `arch` slice, in `LSMinimumSystemVersionByArchitecture`.  A Mac with
+    /// an earlier version runs another architecture instead, if it can.
+    /// For `arm64`, the version must be 11.0 or later.
+    pub fn minimum_system_version_for(&mut self, arch: macho::Arch, version: &str) -> &mut Self {
+        self.minimum_system_version_by_arch
+            .retain(|(existing, _)| *existing != arch);
+        self.minimum_system_version_by_arch
+            .push((arch, version.to_string()));
+        self
+    }
+    /// Set `LSArchitecturePriority`, the architectures of a universal binary
+    /// in order of preference.  Takes precedence over the order derived by
+    /// [`Self::inspect_executable()`].
+    pub fn architecture_priority<I>(&mut self, archs: I) -> &mut Self
+    where
+        I: IntoIterator<Item = macho::Arch>,
+    {
+        self.architecture_priority.clear();
+        for arch in archs {
+            if !self.architecture_priority.contains(&arch) {
+                self.architecture_priority.push(arch);
+            }
+        }
+        self
+    }
+    /// Set `LSRequiresNativeExecution`, which prevents an Apple Silicon Mac
+    /// from running the `x86_64` slice of a universal binary under Rosetta,
+    /// e.g. when the user selects "Open using Rosetta" in the Finder.
+    pub fn requires_native_execution(&mut self, native: bool) -> &mut Self {
+        self.requires_native_execution = Some(native);
+        self
+    }
+
+    /// The name of `arch` for use in `Info.plist`.
+    fn arch_name(arch: macho::Arch) -> Result<&'static str, ValidationError> {
+        arch.name()
+            .ok_or_else(|| ValidationError::UnsupportedArchitecture {
+                arch: arch.to_string(),
+            })
+    }
+
+    /// Set a custom `Info.plist` key, e.g. `LSEnvironment` or
+    /// `NSAppTransportSecurity`.  The value may be any property list value,
+    /// including nested arrays and dictionaries.  Setting the same key again
+    /// replaces the earlier custom value.
+    ///
+    /// The generated `Info.plist` is assembled in order of increasing
+    /// precedence from:
+    ///
+    /// 1. the defaults generated by relaunch, e.g. `LSMinimumSystemVersion`;
+    /// 2. the fragment given to [`Self::info_plist_template()`], if any;
+    /// 3. custom keys set with this method or [`Self::merge_plist()`].
+    ///
+    /// Each layer is merged over the previous one using [`plist::merge()`].
+    /// The exception is [`Self::MANAGED_KEYS`], which are derived from the
+    /// trampoline's configuration and the bundle layout.  A template or
+    /// custom key may repeat a managed key's generated value, but any other
+    /// value is reported as a conflict when the `Info.plist` is generated.
+    pub fn plist_key<V: Into<Value>>(&mut self, key: &str, value: V) -> &mut Self {
+        self.plist.insert(key.to_string(), value.into());
+        self
+    }
+    /// Use an existing `Info.plist` fragment, in either the XML or binary
+    /// format, as the basis for the generated `Info.plist`.  This is useful
+    /// for document types, usage descriptions, and other metadata maintained
+    /// outside of Rust.  Accepts a path, which is read when the bundle is
+    /// generated, or the bytes of the fragment itself.  See
+    /// [`Self::plist_key()`] for how the fragment is combined with the other
+    /// keys.
+    pub fn info_plist_template<T: Into<Template>>(&mut self, template: T) -> &mut Self {
+        self.plist_template = Some(template.into());
+        self
+    }
+    /// Merge a dictionary of custom `Info.plist` keys into those previously
+    /// set, using [`plist::merge()`].  See [`Self::plist_key()`] for how
+    /// custom keys interact with the generated defaults.
+    pub fn merge_plist(&mut self, keys: Dictionary) -> &mut Self {
+        plist::merge(&mut self.plist, keys);
+        self
+    }
+
+    /// The `Info.plist` keys which relaunch always generates itself, and which
+    /// cannot be changed with [`Self::plist_key()`] or a template.  Use
+    /// [`Self::name()`], [`Self::ident()`], and [`Self::version()`] instead.
+    pub const MANAGED_KEYS: &'static [&'static str] = &[
+        "CFBundleExecutable",
+        "CFBundleIdentifier",
+        "CFBundleInfoDictionaryVersion",
+        "CFBundleName",
+        "CFBundlePackageType",
+        "CFBundleShortVersionString",
+        "CFBundleVersion",
+    ];
+
+    /// Check that the name is usable as a bundle directory name, that the
+    /// identifier is a well-formed reverse-DNS identifier, that the version
+    /// is representable as a [`BundleVersion`], that the locales are usable
+    /// as `.lproj` directory names, that the document types and type
+    /// declarations are well-formed and unambiguous, that the URL schemes
+    /// are well-formed, and that the minimum macOS versions exist and
+    /// support their architectures.  This is done automatically before a
+    /// bundle is generated, so that nothing is written to the filesystem for
+    /// an invalid configuration.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        self.bundle_version().map(|_| ())
+    }
+
+    /// Validate the configuration and return the parsed version.
+    fn bundle_version(&self) -> Result<BundleVersion, ValidationError> {
+        validate::validate_name(&self.name)?;
+        validate::validate_ident(&self.ident)?;
+        for locale in self
+            .localizations
+            .keys()
+            .chain(self.development_region.as_ref())
+        {
+            validate::validate_locale(locale)?;
+        }
+        let declarations: Vec<&TypeDeclaration> = self
+            .exported_types
+            .iter()
+            .chain(&self.imported_types)
+            .collect();
+        document::validate(&self.document_types, &declarations)?;
+        for scheme in &self.url_schemes {
+            validate::validate_url_scheme(scheme)?;
+        }
+        if let Some(version) = &self.minimum_system_version {
+            validate::parse_system_version(version)?;
+        }
+        for (arch, version) in &self.minimum_system_version_by_arch {
+            let name = Self::arch_name(*arch)?;
+            let parsed = validate::parse_system_version(version)?;
+            let first = match arch {
+                macho::Arch::Arm64 | macho::Arch::Arm64e => macho::OsVersion::new(11, 0, 0),
+                _ => macho::OsVersion::new(10, 0, 0),
+            };
+            if parsed < first {
+                return Err(ValidationError::ArchitectureSystemVersion {
+                    arch: name.to_string(),
+                    version: version.clone(),
+                    minimum: first.to_string(),
+                });
+            }
+        }
+        for arch in &self.architecture_priority {
+            Self::arch_name(*arch)?;
+        }
+        self.version
+            .parse()
+            .map_err(ValidationError::InvalidVersion)
+    }
+
+    /// Generate the `Info.plist` metadata for an app bundle whose
+    /// `Contents/MacOS` directory contains an executable named `executable`.
+    /// Fails if the name or identifier is invalid, if the template cannot be
+    /// loaded, or if the template or custom keys conflict with
+    /// [`Self::MANAGED_KEYS`].
+    pub fn info_plist(&self, executable: &str) -> Result<InfoPlist, Error> {
+        self.info_plist_for(executable, None)
+    }
+
+    /// Generate the `Info.plist`, deriving system requirements from the
+    /// parsed `binary` if given.  See [`Self::inspect_executable()`].
+    pub(crate) fn info_plist_for(
+        &self,
+        executable: &str,
+        binary: Option<&macho::MachO>,
+    ) -> Result<InfoPlist, Error> {
+        let version = self.bundle_version()?;
+        let minimum_system_version = match &self.minimum_system_version {
+            Some(version) => validate::parse_system_version(version)?.to_string(),
+            None => binary
+                .and_then(|binary| binary.minimum_system_version())
+                .map_or("10.10.0".to_string(), |version| version.to_string()),
+        };
+
+        let mut plist = InfoPlist::new();
+        plist.insert("CFBundleName".into(), self.name.as_str().into());
+        plist.insert("CFBundleDisplayName".into(), self.name.as_str().into());
+        plist.insert("CFBundleIdentifier".into(), self.ident.as_str().into());
+        plist.insert("CFBundleExecutable".into(), executable.into());
+        plist.insert(
+            "CFBundleShortVersionString".into(),
+            version.short_version_string().into(),
+        );
+        plist.insert(
+            "CFBundleSupportedPlatforms".into(),
+            plist::Value::Array(vec!["MacOSX".into()]),
+        );
+        plist.insert("CFBundleVersion".into(), version.bundle_version().into());
+        plist.insert("NSPrincipalClass".into(), "NSApplication".into());
+        plist.insert("NSHighResolutionCapable".into(), true.into());
+        plist.insert("CFBundleInfoDictionaryVersion".into(), "6.0".into());
+        plist.insert("CFBundlePackageType".into(), "APPL".into());
+        plist.insert("CFBundleSignature".into(), "????".into());
+        plist.insert(
+            "LSMinimumSystemVersion".into(),
+            minimum_system_version.into(),
+        );
+        if self.development_region.is_some() || !self.localizations.is_empty() {
+            let region = self.development_region.as_deref().unwrap_or("en");
+            let mut locales: Vec<&str> = self.localizations.keys().map(String::as_str).collect();
+            if !locales.contains(&region) {
+                locales.push(region);
+                locales.sort();
+            }
+            plist.insert("CFBundleDevelopmentRegion".into(), region.into());
+            plist.insert(
+                "CFBundleLocalizations".into(),
+                Value::Array(locales.into_iter().map(Value::from).collect()),
+            );
+        }
+        let archs = match (self.architecture_priority.is_empty(), binary) {
+            (false, _) => self
+                .architecture_priority
+                .iter()
+                .map(|arch| Self::arch_name(*arch))
+                .collect::<Result<Vec<_>, _>>()?,
+            (true, Some(binary)) => binary.architecture_priority(),
+            (true, None) => Vec::new(),
+        };
+        if !archs.is_empty() {
+            plist.insert(
+                "LSArchitecturePriority".into(),
+                Value::Array(archs.into_iter().map(Value::from).collect()),
+            );
+        }
+        if !self.minimum_system_version_by_arch.is_empty() {
+            let mut versions = Dictionary::new();
+            for (arch, version) in &self.minimum_system_version_by_arch {
+                let version = validate::parse_system_version(version)?;
+                versions.insert(Self::arch_name(*arch)?.into(), version.to_string().into());
+            }
+            plist.insert(
+                "LSMinimumSystemVersionByArchitecture".into(),
+                versions.into(),
+            );
+        }
+        if let Some(native) = self.requires_native_execution {
+            plist.insert("LSRequiresNativeExecution".into(), native.into());
+        }
+        if !self.icons.is_empty() {
+            plist.insert("CFBundleIconFile".into(), icon::ICON_FILE.into());
+        }
+        let arrays = [
+            (
+                "CFBundleDocumentTypes",
+                self.document_types
+                    .iter()
+                    .map(DocumentType::to_dictionary)
+                    .collect::<Vec<_>>(),
+            ),
+            (
+                "UTExportedTypeDeclarations",
+                self.exported_types
+                    .iter()
+                    .map(TypeDeclaration::to_dictionary)
+                    .collect(),
+            ),
+            (
+                "UTImportedTypeDeclarations",
+                self.imported_types
+                    .iter()
+                    .map(TypeDeclaration::to_dictionary)
+                    .collect(),
+            ),
+        ];
+        for (key, entries) in arrays {
+            if !entries.is_empty() {
+                let entries = entries.into_iter().map(Value::from).collect();
+                plist.insert(key.into(), Value::Array(entries));
+            }
+        }
+        if !self.url_schemes.is_empty() {
+            let mut url_type = Dictionary::new();
+            url_type.insert("CFBundleURLName".into(), self.ident.as_str().into());
+            url_type.insert("CFBundleTypeRole".into(), "Viewer".into());
+            url_type.insert(
+                "CFBundleURLSchemes".into(),
+                Value::Array(
+                    self.url_schemes
+                        .iter()
+                        .map(|s| Value::from(s.as_str()))
+                        .collect(),
+                ),
+            );
+            plist.insert(
+                "CFBundleURLTypes".into(),
+                Value::Array(vec![url_type.into()]),
+            );
+        }
+        match self.presentation {
+            Presentation::Regular => {}
+            Presentation::Accessory => {
+                plist.insert("LSUIElement".into(), true.into());
+            }
+            Presentation::BackgroundOnly => {
+                plist.insert("LSBackgroundOnly".into(), true.into());
+            }
+        }
+        for (permission, description) in &self.usage_descriptions {
+            plist.insert(permission.key().into(), description.as_str().into());
+        }
+
+        // Layer the custom keys over the template, then check the result
+        // against the keys we generate ourselves before merging.
+        let mut overrides = match &self.plist_template {
+            Some(template) => template.load().map_err(|source| Error::InvalidTemplate {
+                path: match template {
+                    Template::Path(path) => Some(path.clone()),
+                    Template::Bytes(_) => None,
+                },
+                source,
+            })?,
+            None => Dictionary::new(),
+        };
+        plist::merge(&mut overrides, self.plist.clone());
+        let conflicts: Vec<String> = Self::MANAGED_KEYS
+            .iter()
+            .filter(|key| {
+                overrides
+                    .get(**key)
+                    .is_some_and(|v| Some(v) != plist.get(**key))
+            })
+            .map(|key| key.to_string())
+            .collect();
+        if !conflicts.is_empty() {
+            return Err(Error::ManagedKeyConflict { keys: conflicts });
+        }
+
+        plist::merge(&mut plist, overrides);
+        Ok(plist)
+    }
 
     /// Get a reference to the NSBundle class, which we will use to query if
     /// our process is running as an app bundle.
@@ -91,7 +974,7 @@ impl Trampoline {
         Self::get_bundle().is_some()
     }
 
-    pub fn bundle(&self, location: InstallDir) -> Result<Application, IOError> {
+    pub fn bundle(&self, location: InstallDir) -> Result<Application, Error> {
         platform_impl::bundle(self, location)
     }
 
@@ -114,8 +997,12 @@ impl Trampoline {
         }
         impl ApplicationHandler for WinitApp {
             fn resumed(&mut self, event_loop: &ActiveEventLoop) {
-                // Required to be implemented, but we don't need to do anything.
                 let _ = event_loop;
+                // The application has finished launching, which replaces the
+                // Apple Event handlers installed when it was created.
+                if let Some(mtm) = MainThreadMarker::new() {
+                    self.relaunch_app.open_handlers.install(mtm);
+                }
             }
 
             fn window_event(
@@ -140,9 +1027,12 @@ impl Trampoline {
             }
         }
 
-        // We don't launch any windows, so we aren't a graphical application.
-        // There should be an item in the dock, but no windows or menubar.
-        let event_loop = EventLoop::new().expect("Failed to create event loop");
+        // We don't launch any windows.  Whether there is an item in the Dock
+        // and a menubar is decided by the trampoline's presentation, which
+        // the event loop would otherwise override as it launches.
+        let mut builder = EventLoop::builder();
+        platform_impl::configure_event_loop(&mut builder, self.presentation);
+        let event_loop = builder.build().expect("Failed to create event loop");
 
         // Relaunch the application as a bundled application.
         let relaunch_app = self.bundle(location).unwrap_or_else(|error| {
@@ -183,32 +1073,129 @@ pub struct Application {
     /// A reference to the `[NSApplication sharedApplication]` instance for
     /// the application.
     pub app: Retained<NSApplication>,
+    /// The directory containing the running executable.
+    executable_dir: PathBuf,
+    /// The development source directory, from [`Trampoline::source_dir()`].
+    source_dir: Option<PathBuf>,
+    /// URLs and files the application has been asked to open.
+    open_requests: Receiver<OpenRequest>,
+    /// The handlers delivering to `open_requests`, which are installed
+    /// again once the application has finished launching.
+    open_handlers: platform_impl::OpenHandlers,
+    /// How the application presents itself, from
+    /// [`Trampoline::presentation()`].
+    presentation: Presentation,
 }
 
 impl Application {
-    fn new(name: String, ident: String, bundle: Retained<NSBundle>) -> Self {
+    fn new(
+        trampoline: &Trampoline,
+        bundle: Retained<NSBundle>,
+        requests: Vec<OpenRequest>,
+    ) -> Result<Self, Error> {
         // Get the path to app bundle from which we are running.
-        let mut bundle_path =
-            std::env::current_exe().expect("Could not determine path to current executable.");
-        bundle_path.pop(); // [exe]
+        let executable = std::env::current_exe().map_err(Error::CurrentExe)?;
+        let executable_dir = executable.parent().unwrap_or(Path::new("")).to_path_buf();
+        let mut bundle_path = executable_dir.clone();
         bundle_path.pop(); // MacOS
         bundle_path.pop(); // Contents
 
         // Establish that we are running on the main thread.
-        let mtm =
-            MainThreadMarker::new().expect("Must call Application::new() from the main thread.");
+        let mtm = MainThreadMarker::new().ok_or(Error::NotMainThread)?;
 
         // Get a reference to the shared application instance.
         let app = NSApplication::sharedApplication(mtm);
+        platform_impl::set_presentation(&app, trampoline.presentation);
+
+        // Deliver the URLs and files to open which were passed on when
+        // relaunching into the bundle, then listen for more.
+        let (open_sender, open_requests) = mpsc::channel();
+        for request in requests {
+            let _ = open_sender.send(request);
+        }
+        let open_handlers = platform_impl::OpenHandlers::new(mtm, open_sender);
 
         // Return the new Application instance.
-        Self {
-            name,
-            ident,
+        Ok(Self {
+            name: trampoline.name.clone(),
+            ident: trampoline.ident.clone(),
             bundle_path,
             bundle,
             app,
+            executable_dir,
+            source_dir: trampoline.source_dir.clone(),
+            open_requests,
+            open_handlers,
+            presentation: trampoline.presentation,
+        })
+    }
+
+    /// How the application presents itself, which has been applied to the
+    /// activation policy of `NSApplication`.
+    pub fn presentation(&self) -> Presentation {
+        self.presentation
+    }
+
+    /// The URLs and files the application has been asked to open, in the
+    /// order the requests arrived.  Those given on the command line of the
+    /// trampoline are available immediately: URLs with a scheme registered
+    /// with [`Trampoline::url_scheme()`], and, if the application declares a
+    /// [`Trampoline::document_type()`], paths of existing files.  Other
+    /// command line arguments are never treated as requests.  On macOS,
+    /// URLs and documents opened while the application is running, e.g. by
+    /// clicking a link, are delivered as they arrive while the event loop
+    /// runs, so poll with [`Receiver::try_recv()`] from the event loop.
+    pub fn open_requests(&self) -> &Receiver<OpenRequest> {
+        &self.open_requests
+    }
+
+    /// The directory containing the running executable.
+    pub fn executable_dir(&self) -> &Path {
+        &self.executable_dir
+    }
+
+    /// The `Contents/Resources` directory of the app bundle, or `None` if the
+    /// executable is not running from within an app bundle.  This is decided
+    /// from the layout of the filesystem alone, so that it behaves the same
+    /// on every platform.
+    pub fn resource_dir(&self) -> Option<PathBuf> {
+        let contents = self.executable_dir.parent()?;
+        if self.executable_dir.file_name()? != "MacOS" || contents.file_name()? != "Contents" {
+            return None;
+        }
+        let resources = contents.join("Resources");
+        resources.is_dir().then_some(resources)
+    }
+
+    /// The directories searched by [`Self::resource_path()`], in order: the
+    /// bundle's `Contents/Resources` directory, the directory containing the
+    /// executable, and the development source directory set with
+    /// [`Trampoline::source_dir()`].  Directories which do not apply are
+    /// left out.
+    pub fn resource_search_path(&self) -> Vec<PathBuf> {
+        self.resource_dir()
+            .into_iter()
+            .chain(Some(self.executable_dir.clone()))
+            .chain(self.source_dir.clone())
+            .collect()
+    }
+
+    /// Find the resource `name`, a relative path such as `shaders/blit.wgsl`,
+    /// in the first directory of [`Self::resource_search_path()`] which
+    /// contains it.  Returns `None` if it is found nowhere, or if `name` is
+    /// absolute or contains `..`.
+    pub fn resource_path<P: AsRef<Path>>(&self, name: P) -> Option<PathBuf> {
+        let name = name.as_ref();
+        if name
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return None;
         }
+        self.resource_search_path()
+            .into_iter()
+            .map(|dir| dir.join(name))
+            .find(|path| path.exists())
     }
 }
 
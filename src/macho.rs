@@ -0,0 +1,1025 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Reading and rewriting the load commands of Mach-O executables and dynamic
+//! libraries, in pure Rust so that it works (and can be tested) on any host.
+//!
+//! A Mach-O file is either thin, consisting of a header, a sequence of load
+//! commands, and the segment data, or fat (universal), consisting of a table
+//! of architectures followed by one thin image per architecture.  Both byte
+//! orders and both the 32- and 64-bit formats are supported.
+//!
+//! [`MachO::parse()`] summarizes what a bundler needs to know about a file:
+//! the architectures it contains, the OS version each was built for, its
+//! dynamic library dependencies, and whether it is code signed.
+//!
+//! [`build_fat()`] combines per-architecture builds into a fat file, as
+//! `lipo -create` does.
+//!
+//! Rewriting only touches the load commands, which are followed by padding
+//! before the first section's data.  Growing the load commands consumes that
+//! padding, and fails with [`Error::HeaderPadding`] if there is not enough,
+//! just as `install_name_tool` does; linking with
+//! `-headerpad_max_install_names` reserves plenty.  Rewriting invalidates any
+//! code signature, so the file must be signed again afterwards.
+
+use std::{collections::BTreeMap, fmt, ops::Range};
+
+/// The magic number of a fat file, which is always big-endian.
+pub(crate) const FAT_MAGIC: u32 = 0xcafe_babe;
+/// The magic number of a fat file with 64-bit offsets.
+pub(crate) const FAT_MAGIC_64: u32 = 0xcafe_babf;
+const MH_MAGIC: u32 = 0xfeed_face;
+const MH_MAGIC_64: u32 = 0xfeed_facf;
+
+pub(crate) const LC_SEGMENT: u32 = 0x1;
+pub(crate) const LC_LOAD_DYLIB: u32 = 0xc;
+pub(crate) const LC_ID_DYLIB: u32 = 0xd;
+pub(crate) const LC_SEGMENT_64: u32 = 0x19;
+pub(crate) const LC_CODE_SIGNATURE: u32 = 0x1d;
+pub(crate) const LC_VERSION_MIN_MACOSX: u32 = 0x24;
+pub(crate) const LC_BUILD_VERSION: u32 = 0x32;
+pub(crate) const LC_LAZY_LOAD_DYLIB: u32 = 0x20;
+pub(crate) const LC_LOAD_WEAK_DYLIB: u32 = 0x8000_0018;
+pub(crate) const LC_RPATH: u32 = 0x8000_001c;
+pub(crate) const LC_REEXPORT_DYLIB: u32 = 0x8000_001f;
+pub(crate) const LC_LOAD_UPWARD_DYLIB: u32 = 0x8000_0023;
+
+/// Section types whose contents occupy no space in the file.
+const S_ZEROFILL: u32 = 0x1;
+const S_GB_ZEROFILL: u32 = 0xc;
+const S_THREAD_LOCAL_ZEROFILL: u32 = 0x12;
+
+/// How a dynamic library is linked, from the load command naming it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DylibKind {
+    /// `LC_LOAD_DYLIB`: required at launch.
+    Load,
+    /// `LC_LOAD_WEAK_DYLIB`: may be missing at launch.
+    Weak,
+    /// `LC_REEXPORT_DYLIB`: required, and its symbols are re-exported.
+    Reexport,
+    /// `LC_LAZY_LOAD_DYLIB`: loaded on first use.
+    Lazy,
+    /// `LC_LOAD_UPWARD_DYLIB`: required, with a circular dependency.
+    Upward,
+}
+
+impl DylibKind {
+    fn from_cmd(cmd: u32) -> Option<Self> {
+        match cmd {
+            LC_LOAD_DYLIB => Some(DylibKind::Load),
+            LC_LOAD_WEAK_DYLIB => Some(DylibKind::Weak),
+            LC_REEXPORT_DYLIB => Some(DylibKind::Reexport),
+            LC_LAZY_LOAD_DYLIB => Some(DylibKind::Lazy),
+            LC_LOAD_UPWARD_DYLIB => Some(DylibKind::Upward),
+            _ => None,
+        }
+    }
+}
+
+/// A dynamic library dependency.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Dylib {
+    /// The install name, e.g. `/opt/homebrew/lib/libpng16.16.dylib` or
+    /// `@rpath/libfoo.dylib`.
+    pub name: String,
+    pub kind: DylibKind,
+}
+
+/// An error encountered while parsing or rewriting a Mach-O file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The file does not begin with a Mach-O or fat magic number.
+    NotMachO,
+    /// The file is malformed.  `offset` is the byte offset into the file at
+    /// which the problem was found.
+    Malformed { offset: usize, message: String },
+    /// The rewritten load commands do not fit in the space before the first
+    /// section.  Relink with `-headerpad_max_install_names`.
+    HeaderPadding { needed: usize, available: usize },
+    /// A fat file must contain at least one architecture.
+    NoArchitectures,
+    /// The same architecture was supplied twice for a fat file.
+    DuplicateArchitecture(Arch),
+    /// The architectures of a fat file are not the same kind of file, e.g.
+    /// an executable and a dynamic library.
+    FileTypeMismatch {
+        arch: Arch,
+        expected: FileType,
+        found: FileType,
+    },
+    /// The architectures of a fat dynamic library have different install
+    /// names, so are not builds of the same library.
+    InstallNameMismatch {
+        arch: Arch,
+        expected: Option<String>,
+        found: Option<String>,
+    },
+    /// The fat file would be larger than 4 GiB, which requires the 64-bit
+    /// fat format.
+    FatOverflow,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NotMachO => write!(f, "not a Mach-O file"),
+            Error::Malformed { offset, message } => {
+                write!(f, "malformed Mach-O file at offset {}: {}", offset, message)
+            }
+            Error::HeaderPadding { needed, available } => write!(
+                f,
+                "load commands need {} bytes but only {} are available; relink with -headerpad_max_install_names",
+                needed, available
+            ),
+            Error::NoArchitectures => write!(f, "no architectures to combine"),
+            Error::DuplicateArchitecture(arch) => {
+                write!(f, "architecture {} was given more than once", arch)
+            }
+            Error::FileTypeMismatch {
+                arch,
+                expected,
+                found,
+            } => write!(
+                f,
+                "architecture {} is a {:?} file, but expected {:?}",
+                arch, found, expected
+            ),
+            Error::InstallNameMismatch {
+                arch,
+                expected,
+                found,
+            } => write!(
+                f,
+                "architecture {} has install name {:?}, but expected {:?}",
+                arch, found, expected
+            ),
+            Error::FatOverflow => {
+                write!(f, "fat file exceeds 4 GiB; use the 64-bit fat format")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+fn malformed(offset: usize, message: &str) -> Error {
+    Error::Malformed {
+        offset,
+        message: message.to_string(),
+    }
+}
+
+/// Byte order and word size of a thin image.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Format {
+    pub big_endian: bool,
+    pub is_64: bool,
+}
+
+impl Format {
+    pub fn u32(&self, bytes: &[u8], offset: usize) -> Result<u32, Error> {
+        let b: [u8; 4] = bytes
+            .get(offset..offset + 4)
+            .ok_or_else(|| malformed(offset, "unexpected end of file"))?
+            .try_into()
+            .unwrap();
+        Ok(match self.big_endian {
+            true => u32::from_be_bytes(b),
+            false => u32::from_le_bytes(b),
+        })
+    }
+
+    pub fn u64(&self, bytes: &[u8], offset: usize) -> Result<u64, Error> {
+        let b: [u8; 8] = bytes
+            .get(offset..offset + 8)
+            .ok_or_else(|| malformed(offset, "unexpected end of file"))?
+            .try_into()
+            .unwrap();
+        Ok(match self.big_endian {
+            true => u64::from_be_bytes(b),
+            false => u64::from_le_bytes(b),
+        })
+    }
+
+    fn put_u32(&self, value: u32) -> [u8; 4] {
+        match self.big_endian {
+            true => value.to_be_bytes(),
+            false => value.to_le_bytes(),
+        }
+    }
+
+    /// The size of the Mach-O header.
+    pub fn header_size(&self) -> usize {
+        match self.is_64 {
+            true => 32,
+            false => 28,
+        }
+    }
+
+    /// The alignment of load command sizes.
+    fn alignment(&self) -> usize {
+        match self.is_64 {
+            true => 8,
+            false => 4,
+        }
+    }
+}
+
+/// A parsed thin Mach-O image: its format, header fields, and the location
+/// of each load command within the image.
+pub(crate) struct Thin<'a> {
+    pub bytes: &'a [u8],
+    pub format: Format,
+    pub cputype: u32,
+    pub cpusubtype: u32,
+    pub filetype: u32,
+    /// The size of all load commands, from the header.
+    pub sizeofcmds: usize,
+    /// `(cmd, range)` of each load command, with `range` covering the whole
+    /// command including its `cmd` and `cmdsize` fields.
+    pub commands: Vec<(u32, Range<usize>)>,
+}
+
+impl<'a> Thin<'a> {
+    /// Parse the header and load commands of a thin image.  `base` is the
+    /// offset of the image within the file, for error messages.
+    pub fn parse(bytes: &'a [u8], base: usize) -> Result<Self, Error> {
+        let err = |offset: usize, message: &str| malformed(base + offset, message);
+        let magic = bytes
+            .get(..4)
+            .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+            .ok_or(Error::NotMachO)?;
+        let format = match magic {
+            MH_MAGIC => Format {
+                big_endian: true,
+                is_64: false,
+            },
+            MH_MAGIC_64 => Format {
+                big_endian: true,
+                is_64: true,
+            },
+            m if m.swap_bytes() == MH_MAGIC => Format {
+                big_endian: false,
+                is_64: false,
+            },
+            m if m.swap_bytes() == MH_MAGIC_64 => Format {
+                big_endian: false,
+                is_64: true,
+            },
+            _ => return Err(Error::NotMachO),
+        };
+        if bytes.len() < format.header_size() {
+            return Err(err(0, "truncated header"));
+        }
+        let ncmds = format.u32(bytes, 16)? as usize;
+        let sizeofcmds = format.u32(bytes, 20)? as usize;
+        let end = format.header_size() + sizeofcmds;
+        if end > bytes.len() {
+            return Err(err(20, "load commands extend past end of file"));
+        }
+        let mut commands = Vec::with_capacity(ncmds.min(sizeofcmds / 8));
+        let mut offset = format.header_size();
+        for _ in 0..ncmds {
+            if offset + 8 > end {
+                return Err(err(offset, "load command extends past sizeofcmds"));
+            }
+            let cmd = format.u32(bytes, offset)?;
+            let cmdsize = format.u32(bytes, offset + 4)? as usize;
+            if cmdsize < 8 || !cmdsize.is_multiple_of(4) || cmdsize > end - offset {
+                return Err(err(offset + 4, "invalid load command size"));
+            }
+            commands.push((cmd, offset..offset + cmdsize));
+            offset += cmdsize;
+        }
+        Ok(Thin {
+            bytes,
+            format,
+            cputype: format.u32(bytes, 4)?,
+            cpusubtype: format.u32(bytes, 8)?,
+            filetype: format.u32(bytes, 12)?,
+            sizeofcmds,
+            commands,
+        })
+    }
+
+    /// Read the `lc_str` whose offset is stored at `at` within the command
+    /// occupying `range`.
+    pub fn lc_str(&self, range: &Range<usize>, at: usize) -> Result<String, Error> {
+        let command = &self.bytes[range.clone()];
+        let offset = self.format.u32(command, at)? as usize;
+        let tail = command
+            .get(offset..)
+            .filter(|_| offset >= at + 4)
+            .ok_or_else(|| malformed(range.start + at, "string offset out of range"))?;
+        let len = tail.iter().position(|&b| b == 0).unwrap_or(tail.len());
+        String::from_utf8(tail[..len].to_vec())
+            .map_err(|_| malformed(range.start + offset, "string is not UTF-8"))
+    }
+
+    /// The dynamic libraries this image links against.
+    pub fn dylibs(&self) -> Result<Vec<Dylib>, Error> {
+        self.commands
+            .iter()
+            .filter_map(|(cmd, range)| Some((DylibKind::from_cmd(*cmd)?, range)))
+            .map(|(kind, range)| {
+                Ok(Dylib {
+                    name: self.lc_str(range, 8)?,
+                    kind,
+                })
+            })
+            .collect()
+    }
+
+    /// The `LC_RPATH` search paths, in order.
+    pub fn rpaths(&self) -> Result<Vec<String>, Error> {
+        self.commands
+            .iter()
+            .filter(|(cmd, _)| *cmd == LC_RPATH)
+            .map(|(_, range)| self.lc_str(range, 8))
+            .collect()
+    }
+
+    /// The install name of a dynamic library, from `LC_ID_DYLIB`.
+    pub fn install_name(&self) -> Result<Option<String>, Error> {
+        self.commands
+            .iter()
+            .find(|(cmd, _)| *cmd == LC_ID_DYLIB)
+            .map(|(_, range)| self.lc_str(range, 8))
+            .transpose()
+    }
+
+    /// The target platform and OS versions, from `LC_BUILD_VERSION` or the
+    /// older `LC_VERSION_MIN_MACOSX`.
+    pub fn build_version(&self) -> Result<Option<BuildVersion>, Error> {
+        let f = &self.format;
+        for (cmd, range) in &self.commands {
+            let c = &self.bytes[range.clone()];
+            let (platform, minos, sdk) = match *cmd {
+                LC_BUILD_VERSION => (f.u32(c, 8)?, f.u32(c, 12)?, f.u32(c, 16)?),
+                LC_VERSION_MIN_MACOSX => (PLATFORM_MACOS, f.u32(c, 8)?, f.u32(c, 12)?),
+                _ => continue,
+            };
+            return Ok(Some(BuildVersion {
+                platform: Platform::from_raw(platform),
+                minos: OsVersion::from_raw(minos),
+                sdk: OsVersion::from_raw(sdk),
+            }));
+        }
+        Ok(None)
+    }
+
+    /// The offset of the first byte of section or segment data, which bounds
+    /// how far the load commands may grow.
+    fn data_start(&self) -> Result<usize, Error> {
+        let f = &self.format;
+        let mut start = self.bytes.len();
+        for (cmd, range) in &self.commands {
+            let c = &self.bytes[range.clone()];
+            // Offsets of fields within segment and section commands.
+            let (fileoff, filesize, nsects, header, section, sect_offset, sect_flags) = match *cmd {
+                LC_SEGMENT_64 => (f.u64(c, 40)?, f.u64(c, 48)?, f.u32(c, 64)?, 72, 80, 48, 64),
+                LC_SEGMENT => (
+                    f.u32(c, 32)? as u64,
+                    f.u32(c, 36)? as u64,
+                    f.u32(c, 48)?,
+                    56,
+                    68,
+                    40,
+                    56,
+                ),
+                _ => continue,
+            };
+            if fileoff > 0 && filesize > 0 {
+                start = start.min(fileoff as usize);
+            }
+            for i in 0..nsects as usize {
+                let s = header + i * section;
+                let offset = f.u32(c, s + sect_offset)? as usize;
+                let kind = f.u32(c, s + sect_flags)? & 0xff;
+                if offset > 0
+                    && !matches!(kind, S_ZEROFILL | S_GB_ZEROFILL | S_THREAD_LOCAL_ZEROFILL)
+                {
+                    start = start.min(offset);
+                }
+            }
+        }
+        Ok(start)
+    }
+}
+
+/// An architecture entry in the table of a fat file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct FatArch {
+    pub cputype: u32,
+    pub cpusubtype: u32,
+    pub offset: usize,
+    pub size: usize,
+    pub align: u32,
+}
+
+/// Parse the architecture table of a fat file, or return `None` for a thin
+/// file.
+pub(crate) fn fat_archs(bytes: &[u8]) -> Result<Option<Vec<FatArch>>, Error> {
+    let be = Format {
+        big_endian: true,
+        is_64: false,
+    };
+    let magic = match bytes.get(..4) {
+        Some(_) => be.u32(bytes, 0)?,
+        None => return Err(Error::NotMachO),
+    };
+    let is_64 = match magic {
+        FAT_MAGIC => false,
+        FAT_MAGIC_64 => true,
+        _ => return Ok(None),
+    };
+    let nfat = be.u32(bytes, 4)? as usize;
+    let entry = if is_64 { 32 } else { 20 };
+    if 8 + nfat * entry > bytes.len() {
+        return Err(malformed(4, "architecture table extends past end of file"));
+    }
+    let mut archs = Vec::with_capacity(nfat);
+    for i in 0..nfat {
+        let at = 8 + i * entry;
+        let (offset, size, align) = match is_64 {
+            true => (
+                be.u64(bytes, at + 8)?,
+                be.u64(bytes, at + 16)?,
+                be.u32(bytes, at + 24)?,
+            ),
+            false => (
+                be.u32(bytes, at + 8)? as u64,
+                be.u32(bytes, at + 12)? as u64,
+                be.u32(bytes, at + 16)?,
+            ),
+        };
+        let (offset, size) = (offset as usize, size as usize);
+        if offset.checked_add(size).is_none_or(|end| end > bytes.len()) {
+            return Err(malformed(at, "architecture extends past end of file"));
+        }
+        archs.push(FatArch {
+            cputype: be.u32(bytes, at)?,
+            cpusubtype: be.u32(bytes, at + 4)?,
+            offset,
+            size,
+            align,
+        });
+    }
+    Ok(Some(archs))
+}
+
+/// The byte range of each thin image in a file: every architecture of a fat
+/// file, or the whole of a thin file.
+pub(crate) fn image_ranges(bytes: &[u8]) -> Result<Vec<Range<usize>>, Error> {
+    Ok(match fat_archs(bytes)? {
+        Some(archs) => archs.iter().map(|a| a.offset..a.offset + a.size).collect(),
+        None => std::iter::once(0..bytes.len()).collect(),
+    })
+}
+
+/// Parse each thin image of a (possibly fat) file.
+pub(crate) fn images(bytes: &[u8]) -> Result<Vec<Thin<'_>>, Error> {
+    image_ranges(bytes)?
+        .into_iter()
+        .map(|range| Thin::parse(&bytes[range.clone()], range.start))
+        .collect()
+}
+
+/// Whether `bytes` begins with a Mach-O or fat magic number.
+pub fn is_macho(bytes: &[u8]) -> bool {
+    match bytes.get(..4) {
+        Some(magic) => {
+            let magic = u32::from_be_bytes(magic.try_into().unwrap());
+            [MH_MAGIC, MH_MAGIC_64, FAT_MAGIC, FAT_MAGIC_64]
+                .iter()
+                .any(|&m| magic == m || magic == m.swap_bytes())
+        }
+        None => false,
+    }
+}
+
+/// Append to `into` the items of `from` not already present.
+fn union<T: PartialEq>(into: &mut Vec<T>, from: Vec<T>) {
+    for item in from {
+        if !into.contains(&item) {
+            into.push(item);
+        }
+    }
+}
+
+/// The dynamic libraries linked by any architecture of the file.
+pub fn dylibs(bytes: &[u8]) -> Result<Vec<Dylib>, Error> {
+    let mut dylibs = Vec::new();
+    for image in images(bytes)? {
+        union(&mut dylibs, image.dylibs()?);
+    }
+    Ok(dylibs)
+}
+
+/// The `LC_RPATH` search paths of any architecture of the file.
+pub fn rpaths(bytes: &[u8]) -> Result<Vec<String>, Error> {
+    let mut rpaths = Vec::new();
+    for image in images(bytes)? {
+        union(&mut rpaths, image.rpaths()?);
+    }
+    Ok(rpaths)
+}
+
+/// The install name of a dynamic library, or `None` for an executable.
+pub fn install_name(bytes: &[u8]) -> Result<Option<String>, Error> {
+    for image in images(bytes)? {
+        if let Some(name) = image.install_name()? {
+            return Ok(Some(name));
+        }
+    }
+    Ok(None)
+}
+
+const CPU_ARCH_ABI64: u32 = 0x0100_0000;
+const CPU_TYPE_X86: u32 = 7;
+const CPU_TYPE_ARM: u32 = 12;
+const CPU_TYPE_POWERPC: u32 = 18;
+const CPU_SUBTYPE_MASK: u32 = 0x00ff_ffff;
+const CPU_SUBTYPE_ARM64E: u32 = 2;
+
+/// A CPU architecture, from the `cputype` and `cpusubtype` of a Mach-O
+/// header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Arch {
+    X86_64,
+    Arm64,
+    Arm64e,
+    I386,
+    PowerPC,
+    PowerPC64,
+    Other { cputype: u32, cpusubtype: u32 },
+}
+
+impl Arch {
+    pub fn from_raw(cputype: u32, cpusubtype: u32) -> Self {
+        match (cputype, cpusubtype & CPU_SUBTYPE_MASK) {
+            (CPU_TYPE_X86, _) => Arch::I386,
+            (t, _) if t == CPU_TYPE_X86 | CPU_ARCH_ABI64 => Arch::X86_64,
+            (t, CPU_SUBTYPE_ARM64E) if t == CPU_TYPE_ARM | CPU_ARCH_ABI64 => Arch::Arm64e,
+            (t, _) if t == CPU_TYPE_ARM | CPU_ARCH_ABI64 => Arch::Arm64,
+            (CPU_TYPE_POWERPC, _) => Arch::PowerPC,
+            (t, _) if t == CPU_TYPE_POWERPC | CPU_ARCH_ABI64 => Arch::PowerPC64,
+            _ => Arch::Other {
+                cputype,
+                cpusubtype,
+            },
+        }
+    }
+
+    /// The name used by `lipo`, `arch`, and `LSArchitecturePriority`, or
+    /// `None` for an unrecognized architecture.
+    pub fn name(&self) -> Option<&'static str> {
+        match self {
+            Arch::X86_64 => Some("x86_64"),
+            Arch::Arm64 => Some("arm64"),
+            Arch::Arm64e => Some("arm64e"),
+            Arch::I386 => Some("i386"),
+            Arch::PowerPC => Some("ppc"),
+            Arch::PowerPC64 => Some("ppc64"),
+            Arch::Other { .. } => None,
+        }
+    }
+}
+
+impl fmt::Display for Arch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.name(), self) {
+            (Some(name), _) => f.write_str(name),
+            (
+                None,
+                Arch::Other {
+                    cputype,
+                    cpusubtype,
+                },
+            ) => {
+                write!(f, "cputype {:#x} subtype {:#x}", cputype, cpusubtype)
+            }
+            (None, _) => unreachable!(),
+        }
+    }
+}
+
+/// The kind of Mach-O file, from the `filetype` of its header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileType {
+    Object,
+    Executable,
+    Dylib,
+    Bundle,
+    Other(u32),
+}
+
+impl FileType {
+    pub fn from_raw(filetype: u32) -> Self {
+        match filetype {
+            0x1 => FileType::Object,
+            0x2 => FileType::Executable,
+            0x6 => FileType::Dylib,
+            0x8 => FileType::Bundle,
+            other => FileType::Other(other),
+        }
+    }
+}
+
+const PLATFORM_MACOS: u32 = 1;
+
+/// The platform a Mach-O image was built for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Platform {
+    MacOS,
+    IOS,
+    TvOS,
+    WatchOS,
+    MacCatalyst,
+    VisionOS,
+    Other(u32),
+}
+
+impl Platform {
+    pub fn from_raw(platform: u32) -> Self {
+        match platform {
+            PLATFORM_MACOS => Platform::MacOS,
+            2 => Platform::IOS,
+            3 => Platform::TvOS,
+            4 => Platform::WatchOS,
+            6 => Platform::MacCatalyst,
+            11 => Platform::VisionOS,
+            other => Platform::Other(other),
+        }
+    }
+}
+
+/// An OS version, encoded in load commands as `xxxx.yy.zz` nibbles.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OsVersion {
+    pub major: u16,
+    pub minor: u8,
+    pub patch: u8,
+}
+
+impl OsVersion {
+    pub fn new(major: u16, minor: u8, patch: u8) -> Self {
+        OsVersion {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    pub fn from_raw(version: u32) -> Self {
+        OsVersion::new((version >> 16) as u16, (version >> 8) as u8, version as u8)
+    }
+}
+
+/// Formats as `major.minor`, with `.patch` only if it is non-zero, which is
+/// the form Xcode writes to `LSMinimumSystemVersion`.
+impl fmt::Display for OsVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)?;
+        if self.patch != 0 {
+            write!(f, ".{}", self.patch)?;
+        }
+        Ok(())
+    }
+}
+
+/// The target of a Mach-O image, from `LC_BUILD_VERSION` or
+/// `LC_VERSION_MIN_MACOSX`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BuildVersion {
+    pub platform: Platform,
+    /// The minimum OS version, i.e. the deployment target.
+    pub minos: OsVersion,
+    /// The SDK version built against.
+    pub sdk: OsVersion,
+}
+
+/// A summary of one architecture of a Mach-O file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Image {
+    pub arch: Arch,
+    pub filetype: FileType,
+    /// `None` if the image has no build version load command, as with some
+    /// very old or hand-assembled binaries.
+    pub build_version: Option<BuildVersion>,
+    /// The install name of a dynamic library.
+    pub install_name: Option<String>,
+    pub dylibs: Vec<Dylib>,
+    pub rpaths: Vec<String>,
+    /// Whether the image has an `LC_CODE_SIGNATURE` load command.  The
+    /// signature itself is not verified.
+    pub code_signature: bool,
+}
+
+/// A summary of a thin or fat Mach-O file.
+///
+/// ```no_run
+/// use relaunch::macho::MachO;
+///
+/// let macho = MachO::read("target/release/my-app")?;
+/// for image in &macho.images {
+///     println!("{}: {:?}", image.arch, image.build_version);
+/// }
+/// # Ok::<(), relaunch::Error>(())
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MachO {
+    /// Whether the file is a fat (universal) file, even if it contains only
+    /// one architecture.
+    pub fat: bool,
+    /// The architectures, in file order.
+    pub images: Vec<Image>,
+}
+
+impl MachO {
+    /// Parse a thin or fat Mach-O file.
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        let images = images(bytes)?
+            .iter()
+            .map(|thin| {
+                Ok(Image {
+                    arch: Arch::from_raw(thin.cputype, thin.cpusubtype),
+                    filetype: FileType::from_raw(thin.filetype),
+                    build_version: thin.build_version()?,
+                    install_name: thin.install_name()?,
+                    dylibs: thin.dylibs()?,
+                    rpaths: thin.rpaths()?,
+                    code_signature: thin
+                        .commands
+                        .iter()
+                        .any(|(cmd, _)| *cmd == LC_CODE_SIGNATURE),
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+        Ok(MachO {
+            fat: fat_archs(bytes)?.is_some(),
+            images,
+        })
+    }
+
+    /// Read and parse the Mach-O file at `path`.
+    pub fn read<P: AsRef<std::path::Path>>(path: P) -> Result<Self, crate::Error> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|err| crate::Error::io(path, err))?;
+        MachO::parse(&bytes).map_err(|source| crate::Error::MachO {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    /// The architectures in the file, in file order.
+    pub fn archs(&self) -> Vec<Arch> {
+        self.images.iter().map(|image| image.arch).collect()
+    }
+
+    /// The oldest macOS version any architecture runs on, which is the
+    /// appropriate `LSMinimumSystemVersion`.  `None` if no architecture
+    /// records a macOS deployment target.
+    pub fn minimum_system_version(&self) -> Option<OsVersion> {
+        self.images
+            .iter()
+            .filter_map(|image| image.build_version)
+            .filter(|build| build.platform == Platform::MacOS)
+            .map(|build| build.minos)
+            .min()
+    }
+
+    /// The names of the architectures for `LSArchitecturePriority`, most
+    /// preferred first: Apple Silicon before Intel before PowerPC, and
+    /// 64-bit before 32-bit.  Unrecognized architectures are omitted.
+    pub fn architecture_priority(&self) -> Vec<&'static str> {
+        const PRIORITY: [Arch; 6] = [
+            Arch::Arm64e,
+            Arch::Arm64,
+            Arch::X86_64,
+            Arch::I386,
+            Arch::PowerPC64,
+            Arch::PowerPC,
+        ];
+        PRIORITY
+            .iter()
+            .filter(|arch| self.images.iter().any(|image| image.arch == **arch))
+            .filter_map(Arch::name)
+            .collect()
+    }
+
+    /// Whether every architecture has a code signature.
+    pub fn is_signed(&self) -> bool {
+        self.images.iter().all(|image| image.code_signature)
+    }
+}
+
+/// The alignment of an architecture within a fat file, as a power of two:
+/// the 16 KiB page size for ARM, and 4 KiB for everything else, matching
+/// `lipo`.
+fn fat_alignment(cputype: u32) -> u32 {
+    match cputype & !CPU_ARCH_ABI64 {
+        CPU_TYPE_ARM => 14,
+        _ => 12,
+    }
+}
+
+/// Combine per-architecture builds of the same executable or library into a
+/// fat (universal) file, as `lipo -create` does.  Each input may be thin or
+/// fat; the architectures of fat inputs are included individually.  The
+/// architectures must be distinct, and all be the same kind of file with
+/// the same install name.
+///
+/// The output is deterministic: architectures are ordered by alignment and
+/// then CPU type, regardless of the order of `files`, and each is placed at
+/// the next multiple of its alignment.  `fat64` selects the 64-bit fat
+/// format, which is only needed for files larger than 4 GiB and is not
+/// understood by macOS releases before 10.15.
+pub fn build_fat(files: &[&[u8]], fat64: bool) -> Result<Vec<u8>, Error> {
+    let mut slices = Vec::new();
+    for bytes in files {
+        for range in image_ranges(bytes)? {
+            let image = &bytes[range.clone()];
+            let thin = Thin::parse(image, range.start)?;
+            slices.push((thin.cputype, thin.cpusubtype, image, thin));
+        }
+    }
+    let first = match slices.first() {
+        Some((_, _, _, thin)) => (FileType::from_raw(thin.filetype), thin.install_name()?),
+        None => return Err(Error::NoArchitectures),
+    };
+    let mut seen = Vec::new();
+    for (cputype, cpusubtype, _, thin) in &slices {
+        let arch = Arch::from_raw(*cputype, *cpusubtype);
+        let key = (*cputype, cpusubtype & CPU_SUBTYPE_MASK);
+        if seen.contains(&key) {
+            return Err(Error::DuplicateArchitecture(arch));
+        }
+        seen.push(key);
+        let filetype = FileType::from_raw(thin.filetype);
+        if filetype != first.0 {
+            return Err(Error::FileTypeMismatch {
+                arch,
+                expected: first.0,
+                found: filetype,
+            });
+        }
+        let install_name = thin.install_name()?;
+        if install_name != first.1 {
+            return Err(Error::InstallNameMismatch {
+                arch,
+                expected: first.1.clone(),
+                found: install_name,
+            });
+        }
+    }
+    slices.sort_by_key(|(cputype, cpusubtype, _, _)| {
+        (fat_alignment(*cputype), *cputype, *cpusubtype)
+    });
+
+    let entry = if fat64 { 32 } else { 20 };
+    let mut offset = 8 + entry * slices.len();
+    let mut header = Vec::with_capacity(offset);
+    let magic = if fat64 { FAT_MAGIC_64 } else { FAT_MAGIC };
+    header.extend(magic.to_be_bytes());
+    header.extend((slices.len() as u32).to_be_bytes());
+    let mut layout = Vec::with_capacity(slices.len());
+    for (cputype, cpusubtype, image, _) in &slices {
+        let align = fat_alignment(*cputype);
+        offset = offset.next_multiple_of(1 << align);
+        header.extend(cputype.to_be_bytes());
+        header.extend(cpusubtype.to_be_bytes());
+        if fat64 {
+            header.extend((offset as u64).to_be_bytes());
+            header.extend((image.len() as u64).to_be_bytes());
+            header.extend(align.to_be_bytes());
+            header.extend(0u32.to_be_bytes());
+        } else {
+            if offset + image.len() > u32::MAX as usize {
+                return Err(Error::FatOverflow);
+            }
+            header.extend((offset as u32).to_be_bytes());
+            header.extend((image.len() as u32).to_be_bytes());
+            header.extend(align.to_be_bytes());
+        }
+        layout.push((offset, *image));
+        offset += image.len();
+    }
+
+    let mut bytes = header;
+    bytes.reserve_exact(offset - bytes.len());
+    for (offset, image) in layout {
+        bytes.resize(offset, 0);
+        bytes.extend_from_slice(image);
+    }
+    Ok(bytes)
+}
+
+/// Edits to the load commands of a Mach-O file, applied by [`rewrite()`].
+#[derive(Clone, Debug, Default)]
+pub struct Changes {
+    /// Replace the install name (`LC_ID_DYLIB`) of a dynamic library.
+    pub install_name: Option<String>,
+    /// Replace dependencies, mapping old install names to new ones.
+    pub dylibs: BTreeMap<String, String>,
+    /// Remove these `LC_RPATH` entries.
+    pub delete_rpaths: Vec<String>,
+    /// Append these `LC_RPATH` entries, unless already present.
+    pub add_rpaths: Vec<String>,
+}
+
+/// Build a load command consisting of the first `fixed` bytes of `template`
+/// (or of zeros), with the `lc_str` at offset 8 pointing to `string` placed
+/// immediately after.
+fn string_command(format: &Format, cmd: u32, fixed: &[u8], string: &str) -> Vec<u8> {
+    let len = fixed.len() + string.len() + 1;
+    let size = len.div_ceil(format.alignment()) * format.alignment();
+    let mut command = Vec::with_capacity(size);
+    command.extend_from_slice(&format.put_u32(cmd));
+    command.extend_from_slice(&format.put_u32(size as u32));
+    command.extend_from_slice(&format.put_u32(fixed.len() as u32));
+    command.extend_from_slice(&fixed[12..]);
+    command.extend_from_slice(string.as_bytes());
+    command.resize(size, 0);
+    command
+}
+
+/// Apply `changes` to every architecture of a (possibly fat) file, in
+/// place.  The file size does not change.
+pub fn rewrite(bytes: &mut [u8], changes: &Changes) -> Result<(), Error> {
+    for range in image_ranges(bytes)? {
+        let image = &mut bytes[range.clone()];
+        let (format, header, old_size, new) = {
+            let thin = Thin::parse(image, range.start)?;
+            let new = rewrite_commands(&thin, changes)?;
+            (thin.format, thin.format.header_size(), thin.sizeofcmds, new)
+        };
+        let (ncmds, commands) = new;
+        image[16..20].copy_from_slice(&format.put_u32(ncmds));
+        image[20..24].copy_from_slice(&format.put_u32(commands.len() as u32));
+        let end = header + old_size.max(commands.len());
+        image[header..header + commands.len()].copy_from_slice(&commands);
+        image[header + commands.len()..end].fill(0);
+    }
+    Ok(())
+}
+
+/// The rewritten load commands of one image, and their count.
+fn rewrite_commands(thin: &Thin, changes: &Changes) -> Result<(u32, Vec<u8>), Error> {
+    let f = &thin.format;
+    let mut commands = Vec::with_capacity(thin.sizeofcmds);
+    let mut ncmds = 0;
+    let mut rpaths = Vec::new();
+    for (cmd, range) in &thin.commands {
+        let original = &thin.bytes[range.clone()];
+        // Dylib commands have a 24 byte fixed part: cmd, cmdsize, the name
+        // offset, timestamp, current version, and compatibility version.
+        let replacement = match *cmd {
+            LC_ID_DYLIB => changes.install_name.clone(),
+            cmd if DylibKind::from_cmd(cmd).is_some() => {
+                changes.dylibs.get(&thin.lc_str(range, 8)?).cloned()
+            }
+            LC_RPATH => {
+                let rpath = thin.lc_str(range, 8)?;
+                if changes.delete_rpaths.contains(&rpath) {
+                    continue;
+                }
+                rpaths.push(rpath);
+                None
+            }
+            _ => None,
+        };
+        match replacement {
+            Some(name) => {
+                let fixed = original
+                    .get(..24)
+                    .ok_or_else(|| malformed(range.start, "truncated dylib command"))?;
+                commands.extend(string_command(f, *cmd, fixed, &name));
+            }
+            None => commands.extend_from_slice(original),
+        }
+        ncmds += 1;
+    }
+    for rpath in &changes.add_rpaths {
+        if !rpaths.contains(rpath) {
+            commands.extend(string_command(f, LC_RPATH, &[0; 12], rpath));
+            rpaths.push(rpath.clone());
+            ncmds += 1;
+        }
+    }
+
+    let available = thin.data_start()?.saturating_sub(f.header_size());
+    if commands.len() > available {
+        return Err(Error::HeaderPadding {
+            needed: commands.len(),
+            available,
+        });
+    }
+    Ok((ncmds, commands))
+}
+
+// End of File
@@ -0,0 +1,345 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The record of what went into a bundle, kept inside it so that the next
+//! build can skip the work whose inputs have not changed.  It is written to
+//! `Contents/_CodeSignature/relaunch-manifest.plist`, as code signing does
+//! not seal the contents of `_CodeSignature`, nor could it collide with a
+//! resource there.  It goes wherever the bundle goes, and records digests of
+//! the paths of the executables rather than the paths themselves, so that it
+//! does not reveal where the application was built.
+//!
+//! The manifest is only trusted while the bundled executable is the one it
+//! was written with.  Signing the bundle rewrites the executable, so the
+//! next build after signing starts from scratch, as does a build over a
+//! bundle modified in any other way that changes the executable.
+//!
+//! A bundle is written in three independent pieces: the executable, along
+//! with any libraries embedded in `Contents/Frameworks`; the contents of
+//! `Contents/Resources`; and `Info.plist`.  The manifest holds a digest of
+//! the inputs of each piece, and only the pieces whose digest differs are
+//! written again.  Resource files are identified by their size,
+//! modification time, and permissions, as reading every file on each
+//! launch would defeat the purpose.  The executable is identified by its
+//! contents, so that relinking an identical binary does not copy it again,
+//! but its contents are only read when its size or modification time
+//! differs from the last build.
+//!
+//! Digests are SHA-256, and are recorded in full along with the name of the
+//! algorithm.  Values are fed to it through their [`Hash`] implementations,
+//! with integers in little-endian byte order, so that the digests do not
+//! depend on the platform.  A manifest written by another version of
+//! relaunch, or with another algorithm, is ignored, and at worst a change in
+//! how the standard library hashes a type rebuilds the bundle once.
+
+use crate::{
+    plist::{self, Dictionary, Value},
+    Error,
+};
+use sha2::{Digest as _, Sha256};
+use std::{
+    fs,
+    hash::{Hash, Hasher},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+/// The name of the digest algorithm, as recorded in the manifest.
+const ALGORITHM: &str = "SHA-256";
+
+/// The path of the manifest within the bundle at `bundle_path`.
+pub(crate) fn path(bundle_path: &Path) -> PathBuf {
+    bundle_path
+        .join("Contents")
+        .join("_CodeSignature")
+        .join("relaunch-manifest.plist")
+}
+
+/// The size and modification time of a file, which change whenever it is
+/// written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct Stamp {
+    size: u64,
+    /// Nanoseconds since the Unix epoch.
+    modified: i64,
+}
+
+impl Stamp {
+    fn of(metadata: &fs::Metadata) -> Stamp {
+        let modified = metadata
+            .modified()
+            .unwrap_or(UNIX_EPOCH)
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_nanos() as i64);
+        Stamp {
+            size: metadata.len(),
+            modified,
+        }
+    }
+
+    /// The stamp of the file at `path`.
+    pub fn read(path: &Path) -> io::Result<Stamp> {
+        fs::metadata(path).map(|metadata| Stamp::of(&metadata))
+    }
+
+    fn to_dictionary(self) -> Dictionary {
+        let mut dict = Dictionary::new();
+        dict.insert("Size".into(), Value::Integer(self.size as i64));
+        dict.insert("Modified".into(), Value::Integer(self.modified));
+        dict
+    }
+
+    fn from_dictionary(dict: &Dictionary) -> Option<Stamp> {
+        Some(Stamp {
+            size: dict.get("Size")?.as_integer()? as u64,
+            modified: dict.get("Modified")?.as_integer()?,
+        })
+    }
+}
+
+/// An executable which went into the bundle, with the digest of its
+/// contents.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Source {
+    /// The digest of the path of the executable.
+    path: Sum,
+    stamp: Stamp,
+    pub digest: Sum,
+}
+
+impl Source {
+    /// Identify the executable at `path`, reusing its digest from the
+    /// `previous` build if its stamp is unchanged.
+    pub fn read(path: &Path, previous: &[Source]) -> io::Result<Source> {
+        let stamp = Stamp::read(path)?;
+        let path_digest = Digest::new().add(path).finish();
+        let known = previous
+            .iter()
+            .find(|source| source.path == path_digest && source.stamp == stamp);
+        let digest = match known {
+            Some(source) => source.digest,
+            None => {
+                let mut digest = Digest::new();
+                digest.contents(path)?;
+                digest.finish()
+            }
+        };
+        Ok(Source {
+            path: path_digest,
+            stamp,
+            digest,
+        })
+    }
+
+    fn to_dictionary(&self) -> Dictionary {
+        let mut dict = self.stamp.to_dictionary();
+        dict.insert("PathDigest".into(), to_hex(self.path));
+        dict.insert("Digest".into(), to_hex(self.digest));
+        dict
+    }
+
+    fn from_dictionary(dict: &Dictionary) -> Option<Source> {
+        Some(Source {
+            path: from_hex(dict.get("PathDigest")?)?,
+            stamp: Stamp::from_dictionary(dict)?,
+            digest: from_hex(dict.get("Digest")?)?,
+        })
+    }
+}
+
+/// A SHA-256 digest.
+pub(crate) type Sum = [u8; 32];
+
+/// Feeds the bytes of hashed values to SHA-256, encoding integers in
+/// little-endian byte order and `usize` as 64 bits.
+struct Sha256Hasher(Sha256);
+
+impl Hasher for Sha256Hasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    /// The first eight bytes of the digest.  Use [`Digest::finish()`]
+    /// instead.
+    fn finish(&self) -> u64 {
+        let sum = self.0.clone().finalize();
+        u64::from_le_bytes(sum[..8].try_into().unwrap())
+    }
+}
+
+/// Accumulates the inputs of a piece of the bundle into a single digest.
+pub(crate) struct Digest(Sha256Hasher);
+
+impl Digest {
+    pub fn new() -> Self {
+        Digest(Sha256Hasher(Sha256::new()))
+    }
+
+    /// Add a value, such as a setting or generated file contents.
+    pub fn add<T: Hash + ?Sized>(&mut self, value: &T) -> &mut Self {
+        value.hash(&mut self.0);
+        self
+    }
+
+    /// Add the contents of the file at `path`.
+    pub fn contents(&mut self, path: &Path) -> io::Result<&mut Self> {
+        let mut file = fs::File::open(path)?;
+        let mut buffer = vec![0; 1 << 20];
+        loop {
+            match file.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => self.0.write(&buffer[..n]),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(self)
+    }
+
+    /// Add the names, types, stamps, and permissions of the file or
+    /// directory tree at `path`, without following symbolic links.
+    pub fn tree(&mut self, path: &Path) -> Result<&mut Self, Error> {
+        let metadata = fs::symlink_metadata(path).map_err(|err| Error::io(path, err))?;
+        let file_type = metadata.file_type();
+        self.add(&(file_type.is_dir(), file_type.is_symlink()));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            self.add(&metadata.permissions().mode());
+        }
+        #[cfg(not(unix))]
+        self.add(&metadata.permissions().readonly());
+        if file_type.is_symlink() {
+            let target = fs::read_link(path).map_err(|err| Error::io(path, err))?;
+            self.add(&target);
+        } else if file_type.is_dir() {
+            let mut entries = fs::read_dir(path)
+                .and_then(|dir| dir.collect::<Result<Vec<_>, _>>())
+                .map_err(|err| Error::io(path, err))?;
+            entries.sort_by_key(|entry| entry.file_name());
+            self.add(&entries.len());
+            for entry in entries {
+                self.add(&entry.file_name()).tree(&entry.path())?;
+            }
+        } else {
+            self.add(&Stamp::of(&metadata));
+        }
+        Ok(self)
+    }
+
+    pub fn finish(&self) -> Sum {
+        self.0 .0.clone().finalize().into()
+    }
+}
+
+/// The inputs of an existing bundle, as recorded by the build which wrote
+/// it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Manifest {
+    /// The executables combined into the bundled executable.
+    pub sources: Vec<Source>,
+    /// The stamp of the bundled executable when it was written, to detect
+    /// changes made to it since.
+    pub bundled: Stamp,
+    /// The digest of the inputs of the executable and embedded libraries.
+    pub executable: Sum,
+    /// The digest of the inputs of `Contents/Resources`.
+    pub resources: Sum,
+    /// The digest of the contents of `Info.plist`.
+    pub info_plist: Sum,
+}
+
+fn to_hex(digest: Sum) -> Value {
+    Value::String(digest.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+fn from_hex(value: &Value) -> Option<Sum> {
+    let text = value.as_str()?;
+    if text.len() != 64 || !text.is_ascii() {
+        return None;
+    }
+    let mut digest = [0; 32];
+    for (byte, pair) in digest.iter_mut().zip(text.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+    Some(digest)
+}
+
+impl Manifest {
+    /// Read the manifest at `path`, or return `None` if there is none, or if
+    /// it was written by another version of relaunch or cannot be read, in
+    /// which case the bundle must be rebuilt from scratch.
+    pub fn load(path: &Path) -> Option<Manifest> {
+        let value = plist::from_slice(&fs::read(path).ok()?).ok()?;
+        let dict = value.as_dictionary()?;
+        if dict.get("Version")?.as_str()? != env!("CARGO_PKG_VERSION")
+            || dict.get("Algorithm")?.as_str()? != ALGORITHM
+        {
+            return None;
+        }
+        let sources = dict
+            .get("Sources")?
+            .as_array()?
+            .iter()
+            .map(|source| Source::from_dictionary(source.as_dictionary()?))
+            .collect::<Option<Vec<_>>>()?;
+        Some(Manifest {
+            sources,
+            bundled: Stamp::from_dictionary(dict.get("Bundled")?.as_dictionary()?)?,
+            executable: from_hex(dict.get("Executable")?)?,
+            resources: from_hex(dict.get("Resources")?)?,
+            info_plist: from_hex(dict.get("InfoPlist")?)?,
+        })
+    }
+
+    /// Write the manifest to `path`.
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let mut dict = Dictionary::new();
+        dict.insert("Version".into(), env!("CARGO_PKG_VERSION").into());
+        dict.insert("Algorithm".into(), ALGORITHM.into());
+        dict.insert(
+            "Sources".into(),
+            Value::Array(
+                self.sources
+                    .iter()
+                    .map(|source| Value::Dictionary(source.to_dictionary()))
+                    .collect(),
+            ),
+        );
+        dict.insert(
+            "Bundled".into(),
+            Value::Dictionary(self.bundled.to_dictionary()),
+        );
+        dict.insert("Executable".into(), to_hex(self.executable));
+        dict.insert("Resources".into(), to_hex(self.resources));
+        dict.insert("InfoPlist".into(), to_hex(self.info_plist));
+        plist::xml::to_string(&Value::Dictionary(dict))
+            .and_then(|text| {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                fs::write(path, text)
+            })
+            .map_err(|err| Error::io(path, err))
+    }
+}
+
+// End of File
@@ -0,0 +1,114 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! URLs and files which the operating system asks the application to open.
+//!
+//! When the trampoline relaunches into the bundle, the requests it found on
+//! its own command line are passed to the bundled executable after the
+//! [`OPEN_ARG`] sentinel, as pairs of a kind, `url` or `file`, and a value.
+//! Nothing else is forwarded, and the bundled executable only takes
+//! requests from arguments following the sentinel, so the application's own
+//! arguments are never mistaken for files to open.
+
+use std::{ffi::OsString, path::PathBuf};
+
+/// The argument which introduces the requests passed on by the trampoline.
+pub(crate) const OPEN_ARG: &str = "--relaunch-open";
+
+/// A request to open a URL or file, as delivered by
+/// [`Application::open_requests()`].
+///
+/// [`Application::open_requests()`]: crate::Application::open_requests
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OpenRequest {
+    /// A URL with one of the schemes registered with
+    /// [`Trampoline::url_scheme()`], e.g. `myapp://settings`.
+    ///
+    /// [`Trampoline::url_scheme()`]: crate::Trampoline::url_scheme
+    Url(String),
+    /// The absolute path of a file or document package, e.g. a document
+    /// double-clicked in the Finder.
+    File(PathBuf),
+}
+
+/// The scheme of `url`, if it has one.
+fn scheme(url: &str) -> Option<&str> {
+    let (scheme, _) = url.split_once(':')?;
+    crate::validate::validate_url_scheme(scheme)
+        .is_ok()
+        .then_some(scheme)
+}
+
+/// Whether `url` has one of the registered `schemes`, which are compared
+/// case-insensitively.
+fn is_registered(url: &str, schemes: &[String]) -> bool {
+    scheme(url).is_some_and(|scheme| schemes.iter().any(|s| s.eq_ignore_ascii_case(scheme)))
+}
+
+/// The requests among the trampoline's command line arguments `args`,
+/// excluding the program name: URLs with a registered scheme, and, if the
+/// application declares document types, paths of existing files or
+/// directories such as document packages.  Options, i.e. arguments
+/// beginning with `-`, and anything else are skipped.
+pub(crate) fn recognize<I>(args: I, schemes: &[String], documents: bool) -> Vec<OpenRequest>
+where
+    I: IntoIterator<Item = OsString>,
+{
+    let mut requests = Vec::new();
+    for arg in args {
+        let text = arg.to_string_lossy();
+        if text.starts_with('-') {
+            continue;
+        }
+        if is_registered(&text, schemes) {
+            requests.push(OpenRequest::Url(text.into_owned()));
+        } else if documents {
+            if let Ok(path) = std::fs::canonicalize(&arg) {
+                requests.push(OpenRequest::File(path));
+            }
+        }
+    }
+    requests
+}
+
+/// The arguments which pass `requests` on to the bundled executable, or
+/// none if there are no requests.
+pub(crate) fn to_args(requests: &[OpenRequest]) -> Vec<OsString> {
+    if requests.is_empty() {
+        return Vec::new();
+    }
+    let mut args = vec![OsString::from(OPEN_ARG)];
+    for request in requests {
+        match request {
+            OpenRequest::Url(url) => args.extend(["url".into(), url.into()]),
+            OpenRequest::File(path) => args.extend(["file".into(), path.into()]),
+        }
+    }
+    args
+}
+
+/// The requests passed on by the trampoline with [`to_args()`], given the
+/// command line arguments `args`, excluding the program name.  Returns
+/// nothing unless the first argument is the [`OPEN_ARG`] sentinel.
+pub(crate) fn from_args<I>(args: I) -> Vec<OpenRequest>
+where
+    I: IntoIterator<Item = OsString>,
+{
+    let mut args = args.into_iter();
+    if args.next().is_none_or(|arg| arg != OPEN_ARG) {
+        return Vec::new();
+    }
+    let mut requests = Vec::new();
+    while let (Some(kind), Some(value)) = (args.next(), args.next()) {
+        match kind.to_str() {
+            Some("url") => requests.push(OpenRequest::Url(value.to_string_lossy().into_owned())),
+            Some("file") => requests.push(OpenRequest::File(value.into())),
+            _ => break,
+        }
+    }
+    requests
+}
+
+// End of File
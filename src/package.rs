@@ -0,0 +1,241 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Metadata about the crate which builds the application, as opposed to the
+//! `relaunch` crate itself.
+
+use crate::{
+    plist::{self, Dictionary, Value},
+    Error,
+};
+use std::path::PathBuf;
+
+/// Package metadata of the application's crate, as captured from Cargo's
+/// `CARGO_PKG_*` environment variables by the [`trampoline!`] macro.
+///
+/// [`trampoline!`]: crate::trampoline
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PackageInfo {
+    /// The package name, from `CARGO_PKG_NAME`.
+    pub name: String,
+    /// The package version, from `CARGO_PKG_VERSION`.
+    pub version: String,
+    /// The package authors, from the colon-separated `CARGO_PKG_AUTHORS`.
+    pub authors: Vec<String>,
+    /// The package description, from `CARGO_PKG_DESCRIPTION`, or `None` if
+    /// the manifest has no description.
+    pub description: Option<String>,
+}
+
+impl PackageInfo {
+    /// Build from the raw values of the `CARGO_PKG_*` environment variables,
+    /// with `authors` separated by `:` as Cargo provides them.
+    pub fn new(name: &str, version: &str, authors: &str, description: &str) -> Self {
+        PackageInfo {
+            name: name.to_string(),
+            version: version.to_string(),
+            authors: authors
+                .split(':')
+                .map(str::trim)
+                .filter(|author| !author.is_empty())
+                .map(str::to_string)
+                .collect(),
+            description: match description.trim() {
+                "" => None,
+                description => Some(description.to_string()),
+            },
+        }
+    }
+}
+
+/// Create a [`Trampoline`] with the given name and bundle identifier, whose
+/// version and [`PackageInfo`] are taken from the crate invoking the macro.
+///
+/// `Trampoline::new()` can only see the environment `relaunch` itself was
+/// compiled in, so its default version is that of `relaunch`.  This macro
+/// expands in the calling crate instead, capturing its `CARGO_PKG_VERSION`,
+/// `CARGO_PKG_NAME`, `CARGO_PKG_AUTHORS`, and `CARGO_PKG_DESCRIPTION`.  The
+/// crate's `CARGO_MANIFEST_DIR` becomes the [`Trampoline::source_dir()`],
+/// so that resources can be found in the source tree during development.
+///
+/// ```no_run
+/// let app = relaunch::trampoline!("My App", "org.example.MyApp")
+///     .bundle(relaunch::InstallDir::Temp);
+/// ```
+///
+/// [`Trampoline`]: crate::Trampoline
+/// [`Trampoline::source_dir()`]: crate::Trampoline::source_dir
+#[macro_export]
+macro_rules! trampoline {
+    ($name:expr, $ident:expr $(,)?) => {{
+        let mut trampoline = $crate::Trampoline::new($name, $ident);
+        trampoline
+            .package($crate::PackageInfo::new(
+                ::core::env!("CARGO_PKG_NAME"),
+                ::core::env!("CARGO_PKG_VERSION"),
+                ::core::env!("CARGO_PKG_AUTHORS"),
+                ::core::env!("CARGO_PKG_DESCRIPTION"),
+            ))
+            .source_dir(::core::env!("CARGO_MANIFEST_DIR"));
+        trampoline
+    }};
+}
+
+/// Expands to the `[package.metadata.relaunch]` metadata embedded by
+/// [`build::embed_metadata()`], for passing to
+/// [`Trampoline::from_cargo_metadata()`].  Fails to compile if the build
+/// script did not call [`build::embed_metadata()`].
+///
+/// [`build::embed_metadata()`]: https://docs.rs/relaunch/latest/relaunch/build/fn.embed_metadata.html
+/// [`Trampoline::from_cargo_metadata()`]: crate::Trampoline::from_cargo_metadata
+#[macro_export]
+macro_rules! cargo_metadata {
+    () => {
+        ::core::include_str!(::core::env!("RELAUNCH_METADATA"))
+    };
+}
+
+// Keys of the `[package.metadata.relaunch]` table.
+pub(crate) const NAME: &str = "name";
+pub(crate) const IDENTIFIER: &str = "identifier";
+pub(crate) const VERSION: &str = "version";
+pub(crate) const CATEGORY: &str = "category";
+pub(crate) const INFO_PLIST: &str = "info-plist";
+pub(crate) const ICON: &str = "icon";
+pub(crate) const PLIST: &str = "plist";
+
+/// The embedded form of `[package.metadata.relaunch]`, together with the
+/// package metadata Cargo provided to the build script.  It is stored as a
+/// property list dictionary with a `package` and a `relaunch` entry, so that
+/// decoding it does not require a TOML parser at runtime.
+pub(crate) struct CargoMetadata {
+    pub package: PackageInfo,
+    pub manifest_dir: Option<PathBuf>,
+    pub name: Option<String>,
+    pub ident: String,
+    pub version: Option<String>,
+    pub category: Option<String>,
+    pub info_plist: Option<Vec<u8>>,
+    pub icons: Vec<Vec<u8>>,
+    pub plist: Dictionary,
+}
+
+impl CargoMetadata {
+    /// Combine the package metadata, the directory containing the manifest,
+    /// and the already checked `[package.metadata.relaunch]` table into the
+    /// embedded form.
+    #[cfg(feature = "build")]
+    pub fn to_dictionary(
+        package: &PackageInfo,
+        manifest_dir: &std::path::Path,
+        relaunch: Dictionary,
+    ) -> Dictionary {
+        let mut info = Dictionary::new();
+        info.insert(
+            "manifest-dir".into(),
+            manifest_dir.to_string_lossy().into_owned().into(),
+        );
+        info.insert("name".into(), package.name.as_str().into());
+        info.insert("version".into(), package.version.as_str().into());
+        info.insert(
+            "authors".into(),
+            Value::Array(package.authors.iter().map(|a| a.as_str().into()).collect()),
+        );
+        if let Some(description) = &package.description {
+            info.insert("description".into(), description.as_str().into());
+        }
+        let mut metadata = Dictionary::new();
+        metadata.insert("package".into(), info.into());
+        metadata.insert("relaunch".into(), relaunch.into());
+        metadata
+    }
+
+    /// Decode the output of [`cargo_metadata!`].
+    pub fn from_str(text: &str) -> Result<Self, Error> {
+        match plist::from_slice(text.as_bytes()) {
+            Ok(Value::Dictionary(metadata)) => Self::from_dictionary(metadata),
+            Ok(_) => Err(invalid("not a dictionary".to_string())),
+            Err(err) => Err(invalid(err.to_string())),
+        }
+    }
+
+    pub fn from_dictionary(mut metadata: Dictionary) -> Result<Self, Error> {
+        let mut package = match metadata.remove("package") {
+            Some(Value::Dictionary(package)) => package,
+            _ => return Err(invalid("missing package metadata".to_string())),
+        };
+        let mut relaunch = match metadata.remove("relaunch") {
+            Some(Value::Dictionary(relaunch)) => relaunch,
+            _ => return Err(invalid("missing [package.metadata.relaunch]".to_string())),
+        };
+        let string = |dict: &mut Dictionary, key: &str| match dict.remove(key) {
+            None => Ok(None),
+            Some(Value::String(s)) => Ok(Some(s)),
+            Some(_) => Err(invalid(format!("`{}` must be a string", key))),
+        };
+
+        let authors = match package.remove("authors") {
+            Some(Value::Array(authors)) => authors
+                .into_iter()
+                .map(|author| match author {
+                    Value::String(author) => Ok(author),
+                    _ => Err(invalid("`authors` must be strings".to_string())),
+                })
+                .collect::<Result<_, _>>()?,
+            _ => Vec::new(),
+        };
+        let manifest_dir = string(&mut package, "manifest-dir")?.map(PathBuf::from);
+        let package = PackageInfo {
+            name: string(&mut package, "name")?.unwrap_or_default(),
+            version: string(&mut package, "version")?.unwrap_or_default(),
+            authors,
+            description: string(&mut package, "description")?,
+        };
+
+        let metadata = CargoMetadata {
+            package,
+            manifest_dir,
+            name: string(&mut relaunch, NAME)?,
+            ident: string(&mut relaunch, IDENTIFIER)?
+                .ok_or_else(|| invalid("`identifier` is required".to_string()))?,
+            version: string(&mut relaunch, VERSION)?,
+            category: string(&mut relaunch, CATEGORY)?,
+            info_plist: match relaunch.remove(INFO_PLIST) {
+                None => None,
+                Some(Value::Data(template)) => Some(template),
+                Some(_) => return Err(invalid("`info-plist` must be data".to_string())),
+            },
+            icons: match relaunch.remove(ICON) {
+                None => Vec::new(),
+                Some(Value::Array(icons)) => icons
+                    .into_iter()
+                    .map(|icon| match icon {
+                        Value::Data(image) => Ok(image),
+                        _ => Err(invalid("`icon` must be data".to_string())),
+                    })
+                    .collect::<Result<_, _>>()?,
+                Some(_) => return Err(invalid("`icon` must be an array".to_string())),
+            },
+            plist: match relaunch.remove(PLIST) {
+                None => Dictionary::new(),
+                Some(Value::Dictionary(plist)) => plist,
+                Some(_) => return Err(invalid("`plist` must be a table".to_string())),
+            },
+        };
+        if let Some(key) = relaunch.keys().next() {
+            return Err(invalid(format!("unknown key `{}`", key)));
+        }
+        Ok(metadata)
+    }
+}
+
+fn invalid(message: String) -> Error {
+    Error::CargoMetadata {
+        path: None,
+        message,
+    }
+}
+
+// End of File
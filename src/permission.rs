@@ -0,0 +1,209 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The privacy-protected resources an application can ask the user for
+//! access to, and the `Info.plist` usage descriptions shown when it does.
+//!
+//! macOS terminates an application which accesses the camera, microphone,
+//! location, or other protected resource without the matching
+//! `NS*UsageDescription` key in its `Info.plist`, rather than prompting the
+//! user, so the description must be present before the first access.
+
+use crate::{entitlements::Entitlements, plist::Dictionary, Warning};
+use std::fmt;
+
+/// A privacy-protected resource, identified by the `Info.plist` key of its
+/// usage description.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Permission {
+    /// `NSCameraUsageDescription`
+    Camera,
+    /// `NSMicrophoneUsageDescription`
+    Microphone,
+    /// `NSLocationUsageDescription`
+    Location,
+    /// `NSLocationWhenInUseUsageDescription`
+    LocationWhenInUse,
+    /// `NSLocationAlwaysAndWhenInUseUsageDescription`
+    LocationAlways,
+    /// `NSContactsUsageDescription`
+    Contacts,
+    /// `NSCalendarsUsageDescription`, for macOS 13 and earlier.
+    Calendars,
+    /// `NSCalendarsFullAccessUsageDescription`
+    CalendarsFullAccess,
+    /// `NSCalendarsWriteOnlyAccessUsageDescription`
+    CalendarsWriteOnly,
+    /// `NSRemindersUsageDescription`, for macOS 13 and earlier.
+    Reminders,
+    /// `NSRemindersFullAccessUsageDescription`
+    RemindersFullAccess,
+    /// `NSPhotoLibraryUsageDescription`
+    PhotoLibrary,
+    /// `NSPhotoLibraryAddUsageDescription`
+    PhotoLibraryAdd,
+    /// `NSAppleEventsUsageDescription`, for sending Apple Events to other
+    /// applications.
+    AppleEvents,
+    /// `NSAppleMusicUsageDescription`, for the media library.
+    AppleMusic,
+    /// `NSBluetoothAlwaysUsageDescription`
+    Bluetooth,
+    /// `NSLocalNetworkUsageDescription`
+    LocalNetwork,
+    /// `NSSpeechRecognitionUsageDescription`
+    SpeechRecognition,
+    /// `NSMotionUsageDescription`
+    Motion,
+    /// `NSHomeKitUsageDescription`
+    HomeKit,
+    /// `NSSiriUsageDescription`
+    Siri,
+    /// `NSFocusStatusUsageDescription`
+    FocusStatus,
+    /// `NSUserTrackingUsageDescription`
+    UserTracking,
+    /// `NSSystemAdministrationUsageDescription`, for changing system
+    /// settings through the authorization services.
+    SystemAdministration,
+    /// `NSDesktopFolderUsageDescription`
+    DesktopFolder,
+    /// `NSDocumentsFolderUsageDescription`
+    DocumentsFolder,
+    /// `NSDownloadsFolderUsageDescription`
+    DownloadsFolder,
+    /// `NSNetworkVolumesUsageDescription`
+    NetworkVolumes,
+    /// `NSRemovableVolumesUsageDescription`
+    RemovableVolumes,
+    /// `NSFileProviderDomainUsageDescription`, for files managed by a file
+    /// provider such as a cloud storage service.
+    FileProviderDomain,
+}
+
+impl Permission {
+    /// Every permission, in declaration order.
+    pub const ALL: &'static [Permission] = &[
+        Permission::Camera,
+        Permission::Microphone,
+        Permission::Location,
+        Permission::LocationWhenInUse,
+        Permission::LocationAlways,
+        Permission::Contacts,
+        Permission::Calendars,
+        Permission::CalendarsFullAccess,
+        Permission::CalendarsWriteOnly,
+        Permission::Reminders,
+        Permission::RemindersFullAccess,
+        Permission::PhotoLibrary,
+        Permission::PhotoLibraryAdd,
+        Permission::AppleEvents,
+        Permission::AppleMusic,
+        Permission::Bluetooth,
+        Permission::LocalNetwork,
+        Permission::SpeechRecognition,
+        Permission::Motion,
+        Permission::HomeKit,
+        Permission::Siri,
+        Permission::FocusStatus,
+        Permission::UserTracking,
+        Permission::SystemAdministration,
+        Permission::DesktopFolder,
+        Permission::DocumentsFolder,
+        Permission::DownloadsFolder,
+        Permission::NetworkVolumes,
+        Permission::RemovableVolumes,
+        Permission::FileProviderDomain,
+    ];
+
+    /// The `Info.plist` key of the usage description.
+    pub fn key(&self) -> &'static str {
+        match self {
+            Permission::Camera => "NSCameraUsageDescription",
+            Permission::Microphone => "NSMicrophoneUsageDescription",
+            Permission::Location => "NSLocationUsageDescription",
+            Permission::LocationWhenInUse => "NSLocationWhenInUseUsageDescription",
+            Permission::LocationAlways => "NSLocationAlwaysAndWhenInUseUsageDescription",
+            Permission::Contacts => "NSContactsUsageDescription",
+            Permission::Calendars => "NSCalendarsUsageDescription",
+            Permission::CalendarsFullAccess => "NSCalendarsFullAccessUsageDescription",
+            Permission::CalendarsWriteOnly => "NSCalendarsWriteOnlyAccessUsageDescription",
+            Permission::Reminders => "NSRemindersUsageDescription",
+            Permission::RemindersFullAccess => "NSRemindersFullAccessUsageDescription",
+            Permission::PhotoLibrary => "NSPhotoLibraryUsageDescription",
+            Permission::PhotoLibraryAdd => "NSPhotoLibraryAddUsageDescription",
+            Permission::AppleEvents => "NSAppleEventsUsageDescription",
+            Permission::AppleMusic => "NSAppleMusicUsageDescription",
+            Permission::Bluetooth => "NSBluetoothAlwaysUsageDescription",
+            Permission::LocalNetwork => "NSLocalNetworkUsageDescription",
+            Permission::SpeechRecognition => "NSSpeechRecognitionUsageDescription",
+            Permission::Motion => "NSMotionUsageDescription",
+            Permission::HomeKit => "NSHomeKitUsageDescription",
+            Permission::Siri => "NSSiriUsageDescription",
+            Permission::FocusStatus => "NSFocusStatusUsageDescription",
+            Permission::UserTracking => "NSUserTrackingUsageDescription",
+            Permission::SystemAdministration => "NSSystemAdministrationUsageDescription",
+            Permission::DesktopFolder => "NSDesktopFolderUsageDescription",
+            Permission::DocumentsFolder => "NSDocumentsFolderUsageDescription",
+            Permission::DownloadsFolder => "NSDownloadsFolderUsageDescription",
+            Permission::NetworkVolumes => "NSNetworkVolumesUsageDescription",
+            Permission::RemovableVolumes => "NSRemovableVolumesUsageDescription",
+            Permission::FileProviderDomain => "NSFileProviderDomainUsageDescription",
+        }
+    }
+
+    /// The permissions which the entitlement `key` declares the application
+    /// will use, any one of whose descriptions suffices.
+    fn declared_by(key: &str) -> &'static [Permission] {
+        match key {
+            "com.apple.security.device.camera" => &[Permission::Camera],
+            "com.apple.security.device.audio-input" => &[Permission::Microphone],
+            "com.apple.security.personal-information.location" => &[
+                Permission::Location,
+                Permission::LocationWhenInUse,
+                Permission::LocationAlways,
+            ],
+            "com.apple.security.personal-information.addressbook" => &[Permission::Contacts],
+            "com.apple.security.personal-information.calendars" => &[
+                Permission::Calendars,
+                Permission::CalendarsFullAccess,
+                Permission::CalendarsWriteOnly,
+            ],
+            "com.apple.security.personal-information.photos-library" => {
+                &[Permission::PhotoLibrary, Permission::PhotoLibraryAdd]
+            }
+            "com.apple.security.automation.apple-events" => &[Permission::AppleEvents],
+            "com.apple.security.device.bluetooth" => &[Permission::Bluetooth],
+            _ => &[],
+        }
+    }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.key())
+    }
+}
+
+/// Check that each permission declared by `entitlements` has a usage
+/// description in the `Info.plist` keys `plist`.
+pub(crate) fn check(plist: &Dictionary, entitlements: &Entitlements) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    for (key, value) in entitlements.as_dictionary() {
+        let permissions = Permission::declared_by(key);
+        if permissions.is_empty() || value.as_bool() != Some(true) {
+            continue;
+        }
+        if !permissions.iter().any(|p| plist.contains_key(p.key())) {
+            warnings.push(Warning::MissingUsageDescription {
+                permission: permissions[0],
+                entitlement: key.clone(),
+            });
+        }
+    }
+    warnings
+}
+
+// End of File
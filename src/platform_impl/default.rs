@@ -3,11 +3,13 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use crate::{Application, InstallDir, Trampoline};
+use crate::{open, Application, Error, InstallDir, OpenRequest, Presentation, Trampoline};
 use std::{
-    io::Error as IOError,
     ops::Deref,
-    sync::atomic::{AtomicBool, Ordering},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+    },
 };
 
 static IS_BUNDLED: AtomicBool = AtomicBool::new(false);
@@ -57,13 +59,40 @@ impl MainThreadMarker {
     }
 }
 
-pub fn bundle(trampoline: &Trampoline, _location: InstallDir) -> Result<Application, IOError> {
+pub fn bundle(trampoline: &Trampoline, _location: InstallDir) -> Result<Application, Error> {
     IS_BUNDLED.store(true, Ordering::Relaxed);
-    Ok(Application::new(
-        trampoline.name.clone(),
-        trampoline.ident.clone(),
-        NSBundle::mainBundle(),
-    ))
+    // There is no relaunch, so take the requests the trampoline would have
+    // passed on from our own command line, going through the same arguments
+    // as the relaunch would.
+    let requests = open::recognize(
+        std::env::args_os().skip(1),
+        &trampoline.url_schemes,
+        !trampoline.document_types.is_empty(),
+    );
+    let requests = open::from_args(open::to_args(&requests));
+    Application::new(trampoline, NSBundle::mainBundle(), requests)
+}
+
+/// There are no activation policies to set.
+pub fn set_presentation(_app: &NSApplication, _presentation: Presentation) {}
+
+/// There is nothing to configure.
+#[cfg(feature = "winit")]
+pub fn configure_event_loop(
+    _builder: &mut winit::event_loop::EventLoopBuilder<()>,
+    _presentation: Presentation,
+) {
+}
+
+/// There is no way to be asked to open URLs and files, other than on the
+/// command line.
+pub struct OpenHandlers;
+
+impl OpenHandlers {
+    pub fn new(_mtm: MainThreadMarker, _sender: Sender<OpenRequest>) -> Self {
+        OpenHandlers
+    }
+    pub fn install(&self, _mtm: MainThreadMarker) {}
 }
 
 // End of File
@@ -3,11 +3,13 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use crate::{Application, InstallDir, Trampoline};
-use std::{
-    io::{Error as IOError, Write},
-    path::{Path, PathBuf},
+use crate::{
+    open, Application, BundleBuilder, Error, InstallDir, OpenRequest, Presentation, Trampoline,
 };
+use objc2::{declare_class, msg_send_id, mutability, sel, ClassType, DeclaredClass};
+use objc2_app_kit::NSApplicationActivationPolicy;
+use objc2_foundation::{NSAppleEventDescriptor, NSAppleEventManager, NSObject};
+use std::{path::PathBuf, sync::mpsc::Sender};
 
 pub use objc2::rc::Retained;
 pub use objc2_app_kit::NSApplication;
@@ -25,84 +27,34 @@ extern "C" {}
 #[link(name = "Foundation", kind = "framework")] // For NSBundle
 extern "C" {}
 
-pub fn bundle(trampoline: &Trampoline, location: InstallDir) -> Result<Application, IOError> {
+pub fn bundle(trampoline: &Trampoline, location: InstallDir) -> Result<Application, Error> {
     if let Some(bundle) = Trampoline::get_bundle() {
-        return Ok(Application::new(
-            trampoline.name.clone(),
-            trampoline.ident.clone(),
-            bundle,
-        ));
+        let requests = open::from_args(std::env::args_os().skip(1));
+        return Application::new(trampoline, bundle, requests);
     }
 
-    let install_path = match location {
-        InstallDir::Temp => std::env::temp_dir(),
-        InstallDir::SystemApplications => PathBuf::from("/Applications"),
-        InstallDir::UserApplications => dirs::home_dir().unwrap().join("Applications"),
-        InstallDir::Custom(path) => std::fs::canonicalize(path)?,
-    };
-    let bundle_path = install_path.join(format!("{}.app", trampoline.name));
-    let contents_path = Path::new(&bundle_path).join("Contents");
-    let macos_path = contents_path.clone().join("MacOS");
-    let resources_path = contents_path.clone().join("Resources");
-    let plist = contents_path.clone().join("Info.plist");
-
-    let src_exe = std::env::current_exe()?;
-    let exe_name = src_exe
-        .file_name()
-        .expect("Could not determine executable name for current process.")
-        .to_str()
-        .expect("Could not convert executable name to string.");
-    let dst_exe = macos_path.clone().join(exe_name);
-
-    // Remove the app bundle if it already exists (e.g. from a previous run).
-    if bundle_path.try_exists()? {
-        std::fs::remove_dir_all(&bundle_path)?;
+    let install_path = location.resolve()?;
+    let src_exe = std::env::current_exe().map_err(Error::CurrentExe)?;
+    let bundle = BundleBuilder::new(trampoline, src_exe, install_path).build()?;
+    for warning in &bundle.warnings {
+        eprintln!("warning: {}", warning);
     }
-    // Create the bundle directory structure.
-    std::fs::create_dir_all(&macos_path)?;
-    std::fs::create_dir_all(&resources_path)?;
-    // Copy the executable to the MacOS directory.
-    std::fs::copy(&src_exe, &dst_exe)?;
-
-    // Write Info.plist
-    let mut f = std::fs::File::create(&plist)?;
-    writeln!(&mut f, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
-    writeln!(&mut f, "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">")?;
-    writeln!(&mut f, "<plist version=\"1.0\">")?;
-    writeln!(&mut f, "<dict>")?;
-    writeln!(&mut f, "\t<key>CFBundleName</key>")?;
-    writeln!(&mut f, "\t<string>{}</string>", trampoline.name)?;
-    writeln!(&mut f, "\t<key>CFBundleDisplayName</key>")?;
-    writeln!(&mut f, "\t<string>{}</string>", trampoline.name)?;
-    writeln!(&mut f, "\t<key>CFBundleIdentifier</key>")?;
-    writeln!(&mut f, "\t<string>{}</string>", trampoline.ident)?;
-    writeln!(&mut f, "\t<key>CFBundleExecutable</key>")?;
-    writeln!(&mut f, "\t<string>{}</string>", exe_name)?;
-    writeln!(&mut f, "\t<key>CFBundleShortVersionString</key>")?;
-    writeln!(&mut f, "\t<string>{}</string>", trampoline.version)?;
-    writeln!(&mut f, "\t<key>CFBundleSupportedPlatforms</key>")?;
-    writeln!(&mut f, "\t<array>")?;
-    writeln!(&mut f, "\t\t<string>MacOSX</string>")?;
-    writeln!(&mut f, "\t</array>")?;
-    writeln!(&mut f, "\t<key>CFBundleVersion</key>")?;
-    writeln!(&mut f, "\t<string>{}</string>", trampoline.version)?;
-    writeln!(&mut f, "\t<key>NSPrincipalClass</key>")?;
-    writeln!(&mut f, "\t<string>NSApplication</string>")?;
-    writeln!(&mut f, "\t<key>NSHighResolutionCapable</key>")?;
-    writeln!(&mut f, "\t<true/>")?;
-    writeln!(&mut f, "\t<key>CFBundleInfoDictionaryVersion</key>")?;
-    writeln!(&mut f, "\t<string>6.0</string>")?;
-    writeln!(&mut f, "\t<key>CFBundlePackageType</key>")?;
-    writeln!(&mut f, "\t<string>APPL</string>")?;
-    writeln!(&mut f, "\t<key>CFBundleSignature</key>")?;
-    writeln!(&mut f, "\t<string>????</string>")?;
-    writeln!(&mut f, "\t<key>LSMinimumSystemVersion</key>")?;
-    writeln!(&mut f, "\t<string>10.10.0</string>")?;
-    writeln!(&mut f, "</dict>")?;
-    writeln!(&mut f, "</plist>")?;
-
-    // Launch newly created bundle
-    let status = std::process::Command::new(dst_exe).spawn()?.wait()?;
+
+    // Launch newly created bundle, passing on the URLs and files to open
+    // given on our command line.  No other arguments are forwarded.
+    let requests = open::recognize(
+        std::env::args_os().skip(1),
+        &trampoline.url_schemes,
+        !trampoline.document_types.is_empty(),
+    );
+    let status = std::process::Command::new(&bundle.executable)
+        .args(open::to_args(&requests))
+        .spawn()
+        .and_then(|mut child| child.wait())
+        .map_err(|source| Error::Launch {
+            path: bundle.executable.clone(),
+            source,
+        })?;
     match status.code() {
         // If the app exited with exit code, return that code.
         Some(code) => std::process::exit(code),
@@ -114,4 +66,142 @@ pub fn bundle(trampoline: &Trampoline, location: InstallDir) -> Result<Applicati
     }
 }
 
+/// Set the activation policy of `app` to match `presentation`, which
+/// decides whether it has a Dock icon and menu bar.
+pub fn set_presentation(app: &NSApplication, presentation: Presentation) {
+    let policy = match presentation {
+        Presentation::Regular => NSApplicationActivationPolicy::Regular,
+        Presentation::Accessory => NSApplicationActivationPolicy::Accessory,
+        Presentation::BackgroundOnly => NSApplicationActivationPolicy::Prohibited,
+    };
+    app.setActivationPolicy(policy);
+}
+
+/// Have the event loop apply the activation policy for `presentation` as
+/// the application launches, instead of its default.
+#[cfg(feature = "winit")]
+pub fn configure_event_loop(
+    builder: &mut winit::event_loop::EventLoopBuilder<()>,
+    presentation: Presentation,
+) {
+    use winit::platform::macos::{ActivationPolicy, EventLoopBuilderExtMacOS};
+    builder.with_activation_policy(match presentation {
+        Presentation::Regular => ActivationPolicy::Regular,
+        Presentation::Accessory => ActivationPolicy::Accessory,
+        Presentation::BackgroundOnly => ActivationPolicy::Prohibited,
+    });
+}
+
+/// `kInternetEventClass` and `kAEGetURL`, both `'GURL'`.
+const GET_URL: u32 = u32::from_be_bytes(*b"GURL");
+/// `kCoreEventClass`, `'aevt'`.
+const CORE_EVENT_CLASS: u32 = u32::from_be_bytes(*b"aevt");
+/// `kAEOpenDocuments`, `'odoc'`.
+const OPEN_DOCUMENTS: u32 = u32::from_be_bytes(*b"odoc");
+/// `keyDirectObject`, `'----'`.
+const DIRECT_OBJECT: u32 = u32::from_be_bytes(*b"----");
+
+declare_class!(
+    /// Receives the Apple Events sent to open URLs and documents, and
+    /// forwards them to the [`Application`].
+    struct OpenHandler;
+
+    unsafe impl ClassType for OpenHandler {
+        type Super = NSObject;
+        type Mutability = mutability::InteriorMutable;
+        const NAME: &'static str = "RelaunchOpenHandler";
+    }
+
+    impl DeclaredClass for OpenHandler {
+        type Ivars = Sender<OpenRequest>;
+    }
+
+    unsafe impl OpenHandler {
+        #[method(handleGetURLEvent:withReplyEvent:)]
+        fn handle_get_url(&self, event: &NSAppleEventDescriptor, _reply: &NSAppleEventDescriptor) {
+            let url = unsafe { event.paramDescriptorForKeyword(DIRECT_OBJECT) }
+                .and_then(|url| unsafe { url.stringValue() });
+            if let Some(url) = url {
+                let _ = self.ivars().send(OpenRequest::Url(url.to_string()));
+            }
+        }
+
+        #[method(handleOpenDocumentsEvent:withReplyEvent:)]
+        fn handle_open_documents(
+            &self,
+            event: &NSAppleEventDescriptor,
+            _reply: &NSAppleEventDescriptor,
+        ) {
+            let Some(files) = (unsafe { event.paramDescriptorForKeyword(DIRECT_OBJECT) }) else {
+                return;
+            };
+            // A list of file URLs, indexed from 1.
+            for index in 1..=unsafe { files.numberOfItems() } {
+                let path = unsafe { files.descriptorAtIndex(index) }
+                    .and_then(|file| unsafe { file.fileURLValue() })
+                    .and_then(|url| unsafe { url.path() });
+                if let Some(path) = path {
+                    let path = PathBuf::from(path.to_string());
+                    let _ = self.ivars().send(OpenRequest::File(path));
+                }
+            }
+        }
+    }
+);
+
+impl OpenHandler {
+    fn new(sender: Sender<OpenRequest>) -> Retained<Self> {
+        let this = Self::alloc().set_ivars(sender);
+        unsafe { msg_send_id![super(this), init] }
+    }
+}
+
+/// The Apple Event handlers which deliver the URLs and documents the
+/// application is asked to open, owned by the [`Application`].  The event
+/// manager does not retain its handlers, so they are removed when this is
+/// dropped.
+pub struct OpenHandlers(Retained<OpenHandler>);
+
+impl OpenHandlers {
+    /// Create handlers delivering to `sender`, and install them.
+    pub fn new(mtm: MainThreadMarker, sender: Sender<OpenRequest>) -> Self {
+        let handlers = OpenHandlers(OpenHandler::new(sender));
+        handlers.install(mtm);
+        handlers
+    }
+
+    /// Install the handlers, replacing those of `NSApplication`, so its
+    /// delegate is no longer told of opened URLs and documents.
+    /// `NSApplication` installs its handlers as it finishes launching, so
+    /// this must be called again afterwards.  The same handlers are
+    /// installed each time.
+    pub fn install(&self, _mtm: MainThreadMarker) {
+        unsafe {
+            let manager = NSAppleEventManager::sharedAppleEventManager();
+            manager.setEventHandler_andSelector_forEventClass_andEventID(
+                &self.0,
+                sel!(handleGetURLEvent:withReplyEvent:),
+                GET_URL,
+                GET_URL,
+            );
+            manager.setEventHandler_andSelector_forEventClass_andEventID(
+                &self.0,
+                sel!(handleOpenDocumentsEvent:withReplyEvent:),
+                CORE_EVENT_CLASS,
+                OPEN_DOCUMENTS,
+            );
+        }
+    }
+}
+
+impl Drop for OpenHandlers {
+    fn drop(&mut self) {
+        unsafe {
+            let manager = NSAppleEventManager::sharedAppleEventManager();
+            manager.removeEventHandlerForEventClass_andEventID(GET_URL, GET_URL);
+            manager.removeEventHandlerForEventClass_andEventID(CORE_EVENT_CLASS, OPEN_DOCUMENTS);
+        }
+    }
+}
+
 // End of File
@@ -0,0 +1,541 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The binary `bplist00` property list format, as written by Xcode and
+//! `plutil -convert binary1`.
+//!
+//! A binary property list is laid out as:
+//!
+//! ```text
+//! "bplist00"
+//! object 0, object 1, ... object N-1
+//! offset table: N big-endian offsets, `offset_size` bytes each
+//! trailer (32 bytes):
+//!     6 unused bytes
+//!     offset_size: u8
+//!     object_ref_size: u8
+//!     num_objects: u64
+//!     top_object: u64
+//!     offset_table_offset: u64
+//! ```
+//!
+//! Containers refer to their elements by index into the offset table, using
+//! `object_ref_size` bytes per reference.  Identical scalars are written only
+//! once and shared between all references to them.
+
+use super::{Dictionary, Error, Value, MAX_DEPTH};
+use std::{
+    collections::HashMap,
+    io::{Error as IOError, Read, Write},
+    time::{Duration, UNIX_EPOCH},
+};
+
+/// The magic number and format version which begin every binary plist.
+pub(crate) const MAGIC: &[u8; 8] = b"bplist00";
+const TRAILER_SIZE: usize = 32;
+/// How many times larger than the input the decoded value may be.  Objects
+/// may be referenced more than once, so a small file could otherwise
+/// decode to an enormous value.
+const MAX_EXPANSION: usize = 64;
+
+/// Binary plist dates are seconds relative to 2001-01-01T00:00:00Z, which is
+/// this many seconds after the UNIX epoch.
+const APPLE_EPOCH: f64 = 978307200.0;
+
+/// Serialize `value` as a binary property list.
+pub fn to_writer<W: Write>(mut writer: W, value: &Value) -> Result<(), IOError> {
+    writer.write_all(&to_vec(value))
+}
+
+/// Serialize `value` as a binary property list, returned as a byte vector.
+pub fn to_vec(value: &Value) -> Vec<u8> {
+    let mut flat = Flattener::default();
+    flat.flatten(value);
+    flat.encode()
+}
+
+/// Parse a binary property list.
+pub fn from_slice(bytes: &[u8]) -> Result<Value, Error> {
+    Reader::new(bytes)?.read_document()
+}
+
+/// Read and parse a binary property list.
+pub fn from_reader<R: Read>(mut reader: R) -> Result<Value, Error> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    from_slice(&bytes)
+}
+
+/// An object in the flattened object table, with references to other
+/// objects replaced by their index.
+enum Object {
+    /// An already-encoded scalar.
+    Scalar(Vec<u8>),
+    Array(Vec<usize>),
+    Dictionary(Vec<usize>, Vec<usize>),
+}
+
+#[derive(Default)]
+struct Flattener {
+    objects: Vec<Object>,
+    /// The index of each scalar already in the table, keyed by its encoding.
+    scalars: HashMap<Vec<u8>, usize>,
+}
+
+impl Flattener {
+    /// Add `value` and its descendents to the object table, returning the
+    /// index of `value`.
+    fn flatten(&mut self, value: &Value) -> usize {
+        match value {
+            Value::Array(array) => {
+                let index = self.objects.len();
+                self.objects.push(Object::Array(Vec::new()));
+                let refs = array.iter().map(|item| self.flatten(item)).collect();
+                self.objects[index] = Object::Array(refs);
+                index
+            }
+            Value::Dictionary(dict) => {
+                let index = self.objects.len();
+                self.objects
+                    .push(Object::Dictionary(Vec::new(), Vec::new()));
+                let keys = dict
+                    .keys()
+                    .map(|key| self.scalar(encode_string(key)))
+                    .collect();
+                let values = dict.values().map(|value| self.flatten(value)).collect();
+                self.objects[index] = Object::Dictionary(keys, values);
+                index
+            }
+            scalar => self.scalar(encode_scalar(scalar)),
+        }
+    }
+
+    fn scalar(&mut self, encoded: Vec<u8>) -> usize {
+        if let Some(&index) = self.scalars.get(&encoded) {
+            return index;
+        }
+        let index = self.objects.len();
+        self.objects.push(Object::Scalar(encoded.clone()));
+        self.scalars.insert(encoded, index);
+        index
+    }
+
+    fn encode(self) -> Vec<u8> {
+        let num_objects = self.objects.len();
+        let ref_size = int_size(num_objects as u64);
+        let mut out = MAGIC.to_vec();
+        let mut offsets = Vec::with_capacity(num_objects);
+        for object in &self.objects {
+            offsets.push(out.len() as u64);
+            match object {
+                Object::Scalar(bytes) => out.extend_from_slice(bytes),
+                Object::Array(refs) => {
+                    write_marker(&mut out, 0xA0, refs.len());
+                    for &r in refs {
+                        write_sized(&mut out, r as u64, ref_size);
+                    }
+                }
+                Object::Dictionary(keys, values) => {
+                    write_marker(&mut out, 0xD0, keys.len());
+                    for &r in keys.iter().chain(values) {
+                        write_sized(&mut out, r as u64, ref_size);
+                    }
+                }
+            }
+        }
+        let offset_table_offset = out.len() as u64;
+        let offset_size = int_size(offset_table_offset);
+        for offset in offsets {
+            write_sized(&mut out, offset, offset_size);
+        }
+        out.extend_from_slice(&[0; 6]);
+        out.push(offset_size as u8);
+        out.push(ref_size as u8);
+        out.extend_from_slice(&(num_objects as u64).to_be_bytes());
+        out.extend_from_slice(&0u64.to_be_bytes());
+        out.extend_from_slice(&offset_table_offset.to_be_bytes());
+        out
+    }
+}
+
+/// The smallest of 1, 2, 4, or 8 bytes which can hold `n`.
+fn int_size(n: u64) -> usize {
+    if n <= u8::MAX as u64 {
+        1
+    } else if n <= u16::MAX as u64 {
+        2
+    } else if n <= u32::MAX as u64 {
+        4
+    } else {
+        8
+    }
+}
+
+/// Write the low `size` bytes of `n` in big-endian order.
+fn write_sized(out: &mut Vec<u8>, n: u64, size: usize) {
+    out.extend_from_slice(&n.to_be_bytes()[8 - size..]);
+}
+
+fn write_integer(out: &mut Vec<u8>, i: i64) {
+    // Only 8-byte integers are signed; smaller sizes are unsigned.
+    let size = if i < 0 { 8 } else { int_size(i as u64) };
+    out.push(0x10 | size.trailing_zeros() as u8);
+    write_sized(out, i as u64, size);
+}
+
+/// Write an object marker with a length in the low nibble, or followed by an
+/// integer object if the length does not fit.
+fn write_marker(out: &mut Vec<u8>, marker: u8, len: usize) {
+    if len < 0xF {
+        out.push(marker | len as u8);
+    } else {
+        out.push(marker | 0xF);
+        write_integer(out, len as i64);
+    }
+}
+
+fn encode_string(s: &str) -> Vec<u8> {
+    let mut out = Vec::new();
+    if s.is_ascii() {
+        write_marker(&mut out, 0x50, s.len());
+        out.extend_from_slice(s.as_bytes());
+    } else {
+        let units: Vec<u16> = s.encode_utf16().collect();
+        write_marker(&mut out, 0x60, units.len());
+        for unit in units {
+            out.extend_from_slice(&unit.to_be_bytes());
+        }
+    }
+    out
+}
+
+fn encode_scalar(value: &Value) -> Vec<u8> {
+    let mut out = Vec::new();
+    match value {
+        Value::String(s) => return encode_string(s),
+        Value::Boolean(false) => out.push(0x08),
+        Value::Boolean(true) => out.push(0x09),
+        Value::Integer(i) => write_integer(&mut out, *i),
+        Value::Real(r) => {
+            out.push(0x23);
+            out.extend_from_slice(&r.to_be_bytes());
+        }
+        Value::Date(d) => {
+            let secs = match d.duration_since(UNIX_EPOCH) {
+                Ok(after) => after.as_secs_f64(),
+                Err(before) => -before.duration().as_secs_f64(),
+            };
+            out.push(0x33);
+            out.extend_from_slice(&(secs - APPLE_EPOCH).to_be_bytes());
+        }
+        Value::Data(d) => {
+            write_marker(&mut out, 0x40, d.len());
+            out.extend_from_slice(d);
+        }
+        Value::Array(_) | Value::Dictionary(_) => unreachable!("containers are not scalars"),
+    }
+    out
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset_size: usize,
+    ref_size: usize,
+    num_objects: usize,
+    top_object: usize,
+    offset_table_offset: usize,
+    /// Which container objects are currently being decoded, i.e. are
+    /// ancestors of the current object, to detect cycles.
+    on_stack: Vec<bool>,
+    /// The remaining size the decoded value may have, counting each object
+    /// as one unit plus the length of its contents.
+    budget: usize,
+    /// The nesting depth of the container currently being decoded.
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Result<Self, Error> {
+        if bytes.len() < MAGIC.len() + TRAILER_SIZE {
+            return error(0, "file is too short to be a binary property list");
+        }
+        if &bytes[..MAGIC.len()] != MAGIC {
+            return error(0, "missing `bplist00` header");
+        }
+        let trailer_offset = bytes.len() - TRAILER_SIZE;
+        let trailer = &bytes[trailer_offset..];
+        let offset_size = trailer[6] as usize;
+        let ref_size = trailer[7] as usize;
+        let read_u64 = |i: usize| u64::from_be_bytes(trailer[i..i + 8].try_into().unwrap());
+        let (num_objects, top_object, offset_table_offset) =
+            (read_u64(8), read_u64(16), read_u64(24));
+        if !(1..=8).contains(&offset_size) || !(1..=8).contains(&ref_size) {
+            return error(trailer_offset, "invalid offset or object reference size");
+        }
+        if num_objects == 0 || top_object >= num_objects {
+            return error(trailer_offset + 16, "top object is out of range");
+        }
+        let table_len = num_objects.checked_mul(offset_size as u64);
+        let table_end = table_len.and_then(|len| len.checked_add(offset_table_offset));
+        if offset_table_offset < MAGIC.len() as u64
+            || table_end.is_none_or(|end| end > trailer_offset as u64)
+        {
+            return error(trailer_offset + 24, "offset table is out of bounds");
+        }
+        Ok(Reader {
+            bytes,
+            offset_size,
+            ref_size,
+            num_objects: num_objects as usize,
+            top_object: top_object as usize,
+            offset_table_offset: offset_table_offset as usize,
+            on_stack: vec![false; num_objects as usize],
+            budget: bytes.len().saturating_mul(MAX_EXPANSION),
+            depth: 0,
+        })
+    }
+
+    fn read_document(&mut self) -> Result<Value, Error> {
+        self.read_object(self.top_object)
+    }
+
+    /// Read `size` bytes at `offset` as a big-endian unsigned integer.
+    fn read_sized(&self, offset: usize, size: usize) -> Result<u64, Error> {
+        let bytes = self.slice(offset, size)?;
+        Ok(bytes.iter().fold(0, |n, &b| n << 8 | b as u64))
+    }
+
+    /// The `len` bytes at `offset`, which must lie within the object area.
+    fn slice(&self, offset: usize, len: usize) -> Result<&'a [u8], Error> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.offset_table_offset => Ok(&self.bytes[offset..end]),
+            _ => error(offset, "object extends past the end of the object area"),
+        }
+    }
+
+    /// The file offset of object number `index`.
+    fn object_offset(&self, index: usize) -> Result<usize, Error> {
+        let entry = self.offset_table_offset + index * self.offset_size;
+        let offset = self.bytes[entry..entry + self.offset_size]
+            .iter()
+            .fold(0u64, |n, &b| n << 8 | b as u64);
+        if offset < MAGIC.len() as u64 || offset >= self.offset_table_offset as u64 {
+            return error(
+                entry,
+                format!("offset of object {} is out of bounds", index),
+            );
+        }
+        Ok(offset as usize)
+    }
+
+    /// Read the object reference at `offset`.
+    fn read_ref(&self, offset: usize) -> Result<usize, Error> {
+        let index = self.read_sized(offset, self.ref_size)?;
+        if index >= self.num_objects as u64 {
+            return error(
+                offset,
+                format!("object reference {} is out of range", index),
+            );
+        }
+        Ok(index as usize)
+    }
+
+    /// Read the length encoded in the low nibble of the marker at `offset`,
+    /// or in the integer object which follows it.  Returns the length and
+    /// the offset of the object's contents.
+    fn read_length(&self, offset: usize) -> Result<(usize, usize), Error> {
+        let nibble = self.bytes[offset] & 0xF;
+        if nibble != 0xF {
+            return Ok((nibble as usize, offset + 1));
+        }
+        let marker = self.slice(offset + 1, 1)?[0];
+        if marker & 0xF0 != 0x10 || marker & 0xF > 3 {
+            return error(offset + 1, "invalid length");
+        }
+        let size = 1 << (marker & 0xF);
+        let len = self.read_sized(offset + 2, size)?;
+        match usize::try_from(len) {
+            Ok(len) => Ok((len, offset + 2 + size)),
+            Err(_) => error(offset + 1, "invalid length"),
+        }
+    }
+
+    fn read_object(&mut self, index: usize) -> Result<Value, Error> {
+        let offset = self.object_offset(index)?;
+        let marker = self.bytes[offset];
+        self.charge(offset, 1)?;
+        match marker >> 4 {
+            0x0 => match marker {
+                0x08 => Ok(Value::Boolean(false)),
+                0x09 => Ok(Value::Boolean(true)),
+                _ => error(offset, format!("unsupported object type 0x{:02x}", marker)),
+            },
+            0x1 => {
+                let size = 1usize << (marker & 0xF);
+                let bytes = self.slice(offset + 1, size.min(16))?;
+                match size {
+                    // Smaller integers are unsigned, and 8-byte integers
+                    // are signed.
+                    1 | 2 | 4 => Ok(Value::Integer(self.read_sized(offset + 1, size)? as i64)),
+                    8 => Ok(Value::Integer(i64::from_be_bytes(
+                        bytes.try_into().unwrap(),
+                    ))),
+                    // 16-byte integers are used for unsigned 64-bit values,
+                    // which we can only represent if they fit in an i64.
+                    16 => {
+                        let n = i128::from_be_bytes(bytes.try_into().unwrap());
+                        match i64::try_from(n) {
+                            Ok(i) => Ok(Value::Integer(i)),
+                            Err(_) => error(offset, "integer is out of range"),
+                        }
+                    }
+                    _ => error(offset, "invalid integer size"),
+                }
+            }
+            0x2 => match marker & 0xF {
+                2 => {
+                    let bytes = self.slice(offset + 1, 4)?;
+                    Ok(Value::Real(
+                        f32::from_be_bytes(bytes.try_into().unwrap()) as f64
+                    ))
+                }
+                3 => {
+                    let bytes = self.slice(offset + 1, 8)?;
+                    Ok(Value::Real(f64::from_be_bytes(bytes.try_into().unwrap())))
+                }
+                _ => error(offset, "invalid real number size"),
+            },
+            0x3 if marker == 0x33 => {
+                let bytes = self.slice(offset + 1, 8)?;
+                let secs = f64::from_be_bytes(bytes.try_into().unwrap()) + APPLE_EPOCH;
+                let date = if secs >= 0.0 {
+                    Duration::try_from_secs_f64(secs).map(|d| UNIX_EPOCH.checked_add(d))
+                } else {
+                    Duration::try_from_secs_f64(-secs).map(|d| UNIX_EPOCH.checked_sub(d))
+                };
+                match date {
+                    Ok(Some(date)) => Ok(Value::Date(date)),
+                    _ => error(offset, "date is out of range"),
+                }
+            }
+            0x4 => {
+                let (len, start) = self.read_length(offset)?;
+                self.charge(offset, len)?;
+                Ok(Value::Data(self.slice(start, len)?.to_vec()))
+            }
+            0x5 => {
+                let (len, start) = self.read_length(offset)?;
+                self.charge(offset, len)?;
+                // Nominally ASCII, but decode any high bytes as Latin-1
+                // rather than rejecting the file outright.
+                let bytes = self.slice(start, len)?;
+                Ok(Value::String(bytes.iter().map(|&b| b as char).collect()))
+            }
+            0x6 => {
+                let (len, start) = self.read_length(offset)?;
+                self.charge(offset, len)?;
+                let bytes = match len.checked_mul(2) {
+                    Some(byte_len) => self.slice(start, byte_len)?,
+                    None => return error(offset, "invalid length"),
+                };
+                let units: Vec<u16> = bytes
+                    .chunks(2)
+                    .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                    .collect();
+                match String::from_utf16(&units) {
+                    Ok(s) => Ok(Value::String(s)),
+                    Err(_) => error(offset, "invalid UTF-16 string"),
+                }
+            }
+            0xA => {
+                let (len, start) = self.read_length(offset)?;
+                self.check_refs(start, len)?;
+                self.enter(index, offset, len)?;
+                let mut array = Vec::with_capacity(len);
+                for i in 0..len {
+                    let child = self.read_ref(start + i * self.ref_size)?;
+                    array.push(self.read_object(child)?);
+                }
+                self.leave(index);
+                Ok(Value::Array(array))
+            }
+            0xD => {
+                let (len, start) = self.read_length(offset)?;
+                self.check_refs(start, len.saturating_mul(2))?;
+                self.enter(index, offset, len)?;
+                let mut dict = Dictionary::new();
+                for i in 0..len {
+                    let key_ref = start + i * self.ref_size;
+                    let key = match self.read_object(self.read_ref(key_ref)?)? {
+                        Value::String(key) => key,
+                        _ => return error(key_ref, "dictionary key is not a string"),
+                    };
+                    let value_ref = self.read_ref(start + (len + i) * self.ref_size)?;
+                    let value = self.read_object(value_ref)?;
+                    if dict.insert(key.clone(), value).is_some() {
+                        return error(key_ref, format!("duplicate key `{}`", key));
+                    }
+                }
+                self.leave(index);
+                Ok(Value::Dictionary(dict))
+            }
+            _ => error(offset, format!("unsupported object type 0x{:02x}", marker)),
+        }
+    }
+
+    /// Ensure that `count` object references starting at `start` are all
+    /// within the object area.
+    fn check_refs(&self, start: usize, count: usize) -> Result<(), Error> {
+        match count.checked_mul(self.ref_size) {
+            Some(len) => self.slice(start, len).map(|_| ()),
+            None => error(start, "invalid length"),
+        }
+    }
+
+    /// Charge `cost` units of decoded size against the budget.
+    fn charge(&mut self, offset: usize, cost: usize) -> Result<(), Error> {
+        match self.budget.checked_sub(cost) {
+            Some(budget) => {
+                self.budget = budget;
+                Ok(())
+            }
+            None => error(offset, "decoded value is too large"),
+        }
+    }
+
+    /// Begin decoding container object `index` with `len` entries.  A
+    /// container may be shared, i.e. referenced more than once, as
+    /// CoreFoundation deduplicates identical values when writing, but must
+    /// not contain itself.
+    fn enter(&mut self, index: usize, offset: usize, len: usize) -> Result<(), Error> {
+        if self.depth >= MAX_DEPTH {
+            return error(offset, "containers are nested too deeply");
+        }
+        if std::mem::replace(&mut self.on_stack[index], true) {
+            return error(
+                offset,
+                format!("container object {} contains itself", index),
+            );
+        }
+        self.charge(offset, len)?;
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Finish decoding container object `index`.
+    fn leave(&mut self, index: usize) {
+        self.on_stack[index] = false;
+        self.depth -= 1;
+    }
+}
+
+fn error<T>(offset: usize, message: impl Into<String>) -> Result<T, Error> {
+    Err(Error::Binary {
+        offset,
+        message: message.into(),
+    })
+}
+
+// End of File
@@ -0,0 +1,467 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! A typed model of Apple property lists, which is what `Info.plist` and
+//! most other bundle metadata files are made of.
+//!
+//! A property list is a tree of [`Value`]s.  The root of an `Info.plist` is
+//! always a dictionary, which is wrapped by the [`InfoPlist`] type.  Both
+//! the [`xml`] and [`binary`] encodings can be read and written.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::{Error as IOError, Read, Write},
+    ops::{Deref, DerefMut},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+pub mod binary;
+pub mod xml;
+
+/// The maximum nesting depth of containers we are willing to decode, in
+/// either format, so that malicious input cannot overflow the stack.
+const MAX_DEPTH: usize = 512;
+
+/// The on-disk encodings of a property list.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    /// The human-readable XML format.
+    #[default]
+    Xml,
+    /// The compact `bplist00` binary format.
+    Binary,
+}
+
+impl Format {
+    /// Guess the format of an encoded property list from its first bytes.
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(binary::MAGIC) {
+            Format::Binary
+        } else {
+            Format::Xml
+        }
+    }
+}
+
+/// Parse a property list in either format, detecting which one is used.
+pub fn from_slice(bytes: &[u8]) -> Result<Value, Error> {
+    match Format::detect(bytes) {
+        Format::Xml => xml::from_slice(bytes),
+        Format::Binary => binary::from_slice(bytes),
+    }
+}
+
+/// Read and parse a property list in either format, detecting which one is
+/// used.
+pub fn from_reader<R: Read>(mut reader: R) -> Result<Value, Error> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    from_slice(&bytes)
+}
+
+/// Serialize `value` as a property list in the given format.
+pub fn to_writer<W: Write>(writer: W, value: &Value, format: Format) -> Result<(), IOError> {
+    match format {
+        Format::Xml => xml::to_writer(writer, value),
+        Format::Binary => binary::to_writer(writer, value),
+    }
+}
+
+/// A property list dictionary.  Keys are kept in sorted order, which is also
+/// the order in which Apple's own tools write them, so that the serialized
+/// form of a dictionary is deterministic.
+pub type Dictionary = BTreeMap<String, Value>;
+
+/// A single property list value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// A `<string>`.
+    String(String),
+    /// A `<true/>` or `<false/>`.
+    Boolean(bool),
+    /// An `<integer>`.
+    Integer(i64),
+    /// A `<real>`, i.e. a floating point number.
+    Real(f64),
+    /// A `<date>`.  Property list dates have a resolution of one second in
+    /// the XML format.
+    Date(SystemTime),
+    /// A `<data>` blob of arbitrary bytes.
+    Data(Vec<u8>),
+    /// An ordered `<array>` of values.
+    Array(Vec<Value>),
+    /// A `<dict>` mapping string keys to values.
+    Dictionary(Dictionary),
+}
+
+impl Value {
+    /// The string contents, if this is a [`Value::String`].
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+    /// The boolean value, if this is a [`Value::Boolean`].
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+    /// The integer value, if this is a [`Value::Integer`].
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Value::Integer(i) => Some(*i),
+            _ => None,
+        }
+    }
+    /// The floating point value, if this is a [`Value::Real`].
+    pub fn as_real(&self) -> Option<f64> {
+        match self {
+            Value::Real(r) => Some(*r),
+            _ => None,
+        }
+    }
+    /// The timestamp, if this is a [`Value::Date`].
+    pub fn as_date(&self) -> Option<SystemTime> {
+        match self {
+            Value::Date(d) => Some(*d),
+            _ => None,
+        }
+    }
+    /// The raw bytes, if this is a [`Value::Data`].
+    pub fn as_data(&self) -> Option<&[u8]> {
+        match self {
+            Value::Data(d) => Some(d),
+            _ => None,
+        }
+    }
+    /// The array elements, if this is a [`Value::Array`].
+    pub fn as_array(&self) -> Option<&Vec<Value>> {
+        match self {
+            Value::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+    /// The mutable array elements, if this is a [`Value::Array`].
+    pub fn as_array_mut(&mut self) -> Option<&mut Vec<Value>> {
+        match self {
+            Value::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+    /// The dictionary entries, if this is a [`Value::Dictionary`].
+    pub fn as_dictionary(&self) -> Option<&Dictionary> {
+        match self {
+            Value::Dictionary(d) => Some(d),
+            _ => None,
+        }
+    }
+    /// The mutable dictionary entries, if this is a [`Value::Dictionary`].
+    pub fn as_dictionary_mut(&mut self) -> Option<&mut Dictionary> {
+        match self {
+            Value::Dictionary(d) => Some(d),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Boolean(b)
+    }
+}
+impl From<i32> for Value {
+    fn from(i: i32) -> Self {
+        Value::Integer(i as i64)
+    }
+}
+impl From<u32> for Value {
+    fn from(i: u32) -> Self {
+        Value::Integer(i as i64)
+    }
+}
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
+        Value::Integer(i)
+    }
+}
+impl From<f64> for Value {
+    fn from(r: f64) -> Self {
+        Value::Real(r)
+    }
+}
+impl From<SystemTime> for Value {
+    fn from(d: SystemTime) -> Self {
+        Value::Date(d)
+    }
+}
+impl From<Vec<u8>> for Value {
+    fn from(d: Vec<u8>) -> Self {
+        Value::Data(d)
+    }
+}
+impl From<Vec<Value>> for Value {
+    fn from(a: Vec<Value>) -> Self {
+        Value::Array(a)
+    }
+}
+impl From<Dictionary> for Value {
+    fn from(d: Dictionary) -> Self {
+        Value::Dictionary(d)
+    }
+}
+
+/// Deep-merge the entries of `from` into `into`.  Where both contain a
+/// dictionary under the same key, the two dictionaries are merged
+/// recursively.  Otherwise the value from `from` replaces the value in
+/// `into`, including arrays, which are not concatenated.
+pub fn merge(into: &mut Dictionary, from: Dictionary) {
+    for (key, value) in from {
+        match (into.get_mut(&key), value) {
+            (Some(Value::Dictionary(existing)), Value::Dictionary(incoming)) => {
+                merge(existing, incoming)
+            }
+            (_, value) => {
+                into.insert(key, value);
+            }
+        }
+    }
+}
+
+/// A property list dictionary to be loaded later, either from a file or from
+/// bytes in memory (e.g. via `include_bytes!`).  Both the XML and binary
+/// formats are accepted.
+#[derive(Clone, Debug)]
+pub enum Template {
+    /// Read the property list from this file.
+    Path(PathBuf),
+    /// Parse the property list from these bytes.
+    Bytes(Vec<u8>),
+}
+
+impl Template {
+    /// Read and parse the template, whose root must be a dictionary.
+    pub fn load(&self) -> Result<Dictionary, Error> {
+        let value = match self {
+            Template::Path(path) => from_reader(std::fs::File::open(path)?)?,
+            Template::Bytes(bytes) => from_slice(bytes)?,
+        };
+        match value {
+            Value::Dictionary(dict) => Ok(dict),
+            _ => Err(Error::NotADictionary),
+        }
+    }
+}
+
+impl From<&Path> for Template {
+    fn from(path: &Path) -> Self {
+        Template::Path(path.to_path_buf())
+    }
+}
+impl From<PathBuf> for Template {
+    fn from(path: PathBuf) -> Self {
+        Template::Path(path)
+    }
+}
+impl From<&[u8]> for Template {
+    fn from(bytes: &[u8]) -> Self {
+        Template::Bytes(bytes.to_vec())
+    }
+}
+impl<const N: usize> From<&[u8; N]> for Template {
+    fn from(bytes: &[u8; N]) -> Self {
+        Template::Bytes(bytes.to_vec())
+    }
+}
+impl From<Vec<u8>> for Template {
+    fn from(bytes: Vec<u8>) -> Self {
+        Template::Bytes(bytes)
+    }
+}
+
+/// An error encountered while reading a property list.
+#[derive(Debug)]
+pub enum Error {
+    /// The underlying reader failed.
+    Io(IOError),
+    /// The XML property list is malformed.  `line` and `column` are 1-based,
+    /// with columns counted in characters.
+    Xml {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// The binary property list is malformed.  `offset` is the byte offset
+    /// into the file at which the problem was found.
+    Binary { offset: usize, message: String },
+    /// The document is a well-formed property list, but its root is not a
+    /// dictionary as is required for an `Info.plist`.
+    NotADictionary,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "error reading property list: {}", err),
+            Error::Xml {
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "invalid XML property list at {}:{}: {}",
+                line, column, message
+            ),
+            Error::Binary { offset, message } => write!(
+                f,
+                "invalid binary property list at offset {}: {}",
+                offset, message
+            ),
+            Error::NotADictionary => write!(f, "property list root is not a dictionary"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<IOError> for Error {
+    fn from(err: IOError) -> Self {
+        Error::Io(err)
+    }
+}
+
+/// The contents of an app bundle's `Info.plist` file: a dictionary of
+/// metadata keys such as `CFBundleIdentifier` and `CFBundleExecutable`.
+///
+/// `InfoPlist` dereferences to its underlying [`Dictionary`], so the usual
+/// map operations can be used to inspect or modify it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InfoPlist(Dictionary);
+
+impl InfoPlist {
+    /// Create an empty `Info.plist` dictionary.
+    pub fn new() -> Self {
+        InfoPlist(Dictionary::new())
+    }
+
+    /// Unwrap the underlying dictionary.
+    pub fn into_inner(self) -> Dictionary {
+        self.0
+    }
+
+    /// Parse an XML property list, whose root must be a dictionary.
+    pub fn from_xml(text: &str) -> Result<Self, Error> {
+        Self::try_from(xml::from_str(text)?)
+    }
+
+    /// Read and parse an XML property list, whose root must be a
+    /// dictionary.  This is how the `Info.plist` of an existing bundle would
+    /// be loaded for inspection.
+    pub fn read_xml<R: Read>(reader: R) -> Result<Self, Error> {
+        Self::try_from(xml::from_reader(reader)?)
+    }
+
+    /// Parse a property list in either the XML or binary format, whose root
+    /// must be a dictionary.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        Self::try_from(from_slice(bytes)?)
+    }
+
+    /// Read and parse a property list in either the XML or binary format,
+    /// whose root must be a dictionary.
+    pub fn read<R: Read>(reader: R) -> Result<Self, Error> {
+        Self::try_from(from_reader(reader)?)
+    }
+
+    /// Serialize in the given format.
+    pub fn write<W: Write>(&self, writer: W, format: Format) -> Result<(), IOError> {
+        match format {
+            Format::Xml => self.write_xml(writer),
+            Format::Binary => self.write_binary(writer),
+        }
+    }
+
+    /// Serialize as a binary property list.
+    pub fn write_binary<W: Write>(&self, mut writer: W) -> Result<(), IOError> {
+        writer.write_all(&self.to_binary())
+    }
+
+    /// Serialize as a binary property list, returned as a byte vector.
+    pub fn to_binary(&self) -> Vec<u8> {
+        // The binary format has no special case for a root dictionary, so
+        // this unfortunately requires a copy.
+        binary::to_vec(&Value::Dictionary(self.0.clone()))
+    }
+
+    /// Serialize as an XML property list.
+    pub fn write_xml<W: Write>(&self, writer: W) -> Result<(), IOError> {
+        xml::dictionary_to_writer(writer, &self.0)
+    }
+
+    /// Serialize as an XML property list, returned as a string.  Fails only
+    /// if a string contains a character which XML cannot represent; see
+    /// [`xml::to_writer()`].
+    pub fn to_xml(&self) -> Result<String, IOError> {
+        let mut buf = Vec::new();
+        self.write_xml(&mut buf)?;
+        // The XML writer only emits UTF-8.
+        Ok(String::from_utf8(buf).unwrap())
+    }
+}
+
+impl Deref for InfoPlist {
+    type Target = Dictionary;
+    fn deref(&self) -> &Dictionary {
+        &self.0
+    }
+}
+impl DerefMut for InfoPlist {
+    fn deref_mut(&mut self) -> &mut Dictionary {
+        &mut self.0
+    }
+}
+
+impl From<Dictionary> for InfoPlist {
+    fn from(d: Dictionary) -> Self {
+        InfoPlist(d)
+    }
+}
+impl TryFrom<Value> for InfoPlist {
+    type Error = Error;
+    fn try_from(value: Value) -> Result<Self, Error> {
+        match value {
+            Value::Dictionary(d) => Ok(InfoPlist(d)),
+            _ => Err(Error::NotADictionary),
+        }
+    }
+}
+impl From<InfoPlist> for Value {
+    fn from(plist: InfoPlist) -> Self {
+        Value::Dictionary(plist.0)
+    }
+}
+
+// End of File
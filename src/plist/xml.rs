@@ -0,0 +1,804 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The XML property list format, as written by Xcode and `plutil -convert
+//! xml1`.
+
+use super::{Dictionary, Error, Value, MAX_DEPTH};
+use std::{
+    io::{Error as IOError, Read, Write},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const HEADER: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+<plist version=\"1.0\">\n";
+const FOOTER: &str = "</plist>\n";
+
+/// The number of base64 characters per line within a `<data>` element.
+const DATA_LINE_LENGTH: usize = 68;
+
+/// Serialize `value` as a complete XML property list document.  XML cannot
+/// represent control characters other than tab, line feed, and carriage
+/// return, even escaped, so a string or key containing one is an error of
+/// kind [`InvalidData`](std::io::ErrorKind::InvalidData).  The binary format
+/// has no such restriction.
+pub fn to_writer<W: Write>(mut writer: W, value: &Value) -> Result<(), IOError> {
+    writer.write_all(HEADER.as_bytes())?;
+    write_value(&mut writer, value, 0)?;
+    writer.write_all(FOOTER.as_bytes())
+}
+
+/// Serialize `value` as a complete XML property list document, returned as a
+/// string.  Fails only if a string contains a character which XML cannot
+/// represent; see [`to_writer()`].
+pub fn to_string(value: &Value) -> Result<String, IOError> {
+    let mut buf = Vec::new();
+    to_writer(&mut buf, value)?;
+    // We only ever emit UTF-8.
+    Ok(String::from_utf8(buf).unwrap())
+}
+
+/// Serialize a dictionary as the root of an XML property list document,
+/// without first having to wrap it in a [`Value`].
+pub(crate) fn dictionary_to_writer<W: Write>(
+    mut writer: W,
+    dict: &Dictionary,
+) -> Result<(), IOError> {
+    writer.write_all(HEADER.as_bytes())?;
+    write_dictionary(&mut writer, dict, 0)?;
+    writer.write_all(FOOTER.as_bytes())
+}
+
+/// Parse a complete XML property list document.
+pub fn from_str(text: &str) -> Result<Value, Error> {
+    Parser::new(text).parse_document()
+}
+
+/// Parse a complete XML property list document from raw bytes, which must be
+/// UTF-8 encoded.
+pub fn from_slice(bytes: &[u8]) -> Result<Value, Error> {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => from_str(text),
+        Err(err) => {
+            // Report the position of the first invalid byte.
+            let valid = std::str::from_utf8(&bytes[..err.valid_up_to()]).unwrap();
+            let (line, column) = line_column(valid, valid.len());
+            Err(Error::Xml {
+                line,
+                column,
+                message: "invalid UTF-8".to_string(),
+            })
+        }
+    }
+}
+
+/// Read and parse a complete XML property list document.
+pub fn from_reader<R: Read>(mut reader: R) -> Result<Value, Error> {
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    from_slice(&bytes)
+}
+
+fn indent<W: Write>(w: &mut W, depth: usize) -> Result<(), IOError> {
+    for _ in 0..depth {
+        w.write_all(b"\t")?;
+    }
+    Ok(())
+}
+
+fn write_value<W: Write>(w: &mut W, value: &Value, depth: usize) -> Result<(), IOError> {
+    match value {
+        Value::String(s) => {
+            indent(w, depth)?;
+            writeln!(w, "<string>{}</string>", escape(s)?)
+        }
+        Value::Boolean(true) => {
+            indent(w, depth)?;
+            writeln!(w, "<true/>")
+        }
+        Value::Boolean(false) => {
+            indent(w, depth)?;
+            writeln!(w, "<false/>")
+        }
+        Value::Integer(i) => {
+            indent(w, depth)?;
+            writeln!(w, "<integer>{}</integer>", i)
+        }
+        Value::Real(r) => {
+            indent(w, depth)?;
+            writeln!(w, "<real>{}</real>", format_real(*r))
+        }
+        Value::Date(d) => {
+            indent(w, depth)?;
+            writeln!(w, "<date>{}</date>", format_date(*d))
+        }
+        Value::Data(d) => {
+            indent(w, depth)?;
+            writeln!(w, "<data>")?;
+            let encoded = base64_encode(d);
+            for line in encoded.as_bytes().chunks(DATA_LINE_LENGTH) {
+                indent(w, depth)?;
+                w.write_all(line)?;
+                writeln!(w)?;
+            }
+            indent(w, depth)?;
+            writeln!(w, "</data>")
+        }
+        Value::Array(a) => {
+            indent(w, depth)?;
+            if a.is_empty() {
+                return writeln!(w, "<array/>");
+            }
+            writeln!(w, "<array>")?;
+            for item in a {
+                write_value(w, item, depth + 1)?;
+            }
+            indent(w, depth)?;
+            writeln!(w, "</array>")
+        }
+        Value::Dictionary(d) => write_dictionary(w, d, depth),
+    }
+}
+
+fn write_dictionary<W: Write>(w: &mut W, dict: &Dictionary, depth: usize) -> Result<(), IOError> {
+    indent(w, depth)?;
+    if dict.is_empty() {
+        return writeln!(w, "<dict/>");
+    }
+    writeln!(w, "<dict>")?;
+    for (key, value) in dict {
+        indent(w, depth + 1)?;
+        writeln!(w, "<key>{}</key>", escape(key)?)?;
+        write_value(w, value, depth + 1)?;
+    }
+    indent(w, depth)?;
+    writeln!(w, "</dict>")
+}
+
+/// Whether `c` is allowed in an XML 1.0 document at all, literally or as a
+/// character reference.
+fn is_xml_char(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\r' | '\u{20}'..='\u{d7ff}' | '\u{e000}'..='\u{fffd}' | '\u{10000}'..)
+}
+
+/// Escape the characters which are not allowed to appear literally in XML
+/// character data, or fail if `s` contains a character which cannot appear
+/// at all.
+fn escape(s: &str) -> Result<String, IOError> {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            c if !is_xml_char(c) => {
+                return Err(IOError::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "character U+{:04X} cannot be written to an XML property list",
+                        c as u32
+                    ),
+                ))
+            }
+            _ => out.push(c),
+        }
+    }
+    Ok(out)
+}
+
+/// Format a real number the way CoreFoundation does, including the special
+/// spellings for infinities and NaN.
+fn format_real(r: f64) -> String {
+    if r.is_nan() {
+        "nan".to_string()
+    } else if r.is_infinite() {
+        if r > 0.0 { "+infinity" } else { "-infinity" }.to_string()
+    } else {
+        format!("{}", r)
+    }
+}
+
+/// Format a timestamp as an ISO 8601 UTC date, e.g. `2024-02-29T13:45:00Z`.
+/// Sub-second precision is truncated, as the XML format does not support it.
+fn format_date(d: SystemTime) -> String {
+    let secs = match d.duration_since(UNIX_EPOCH) {
+        Ok(after) => after.as_secs() as i64,
+        // Round towards negative infinity for dates before the epoch.
+        Err(before) => {
+            let before = before.duration();
+            -(before.as_secs() as i64) - i64::from(before.subsec_nanos() > 0)
+        }
+    };
+    let days = secs.div_euclid(86400);
+    let rem = secs.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Convert a count of days since 1970-01-01 into a (year, month, day) triple
+/// in the proleptic Gregorian calendar.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    // Algorithm from Howard Hinnant's "chrono-Compatible Low-Level Date
+    // Algorithms", shifting the epoch to 0000-03-01 so that leap days fall
+    // at the end of each year.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Parse an ISO 8601 UTC date of the form `2024-02-29T13:45:00Z`, which is
+/// the only form written by CoreFoundation.
+fn parse_date(s: &str) -> Option<SystemTime> {
+    let b = s.as_bytes();
+    if b.len() != 20
+        || b[4] != b'-'
+        || b[7] != b'-'
+        || b[10] != b'T'
+        || b[13] != b':'
+        || b[16] != b':'
+        || b[19] != b'Z'
+    {
+        return None;
+    }
+    let field = |range: std::ops::Range<usize>| -> Option<i64> {
+        let digits = &s[range];
+        if digits.bytes().all(|c| c.is_ascii_digit()) {
+            digits.parse().ok()
+        } else {
+            None
+        }
+    };
+    let (year, month, day) = (field(0..4)?, field(5..7)?, field(8..10)?);
+    let (hour, minute, second) = (field(11..13)?, field(14..16)?, field(17..19)?);
+    if !(1..=12).contains(&month)
+        || day < 1
+        || day > days_in_month(year, month as u32)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return None;
+    }
+    let days = days_from_civil(year, month as u32, day as u32);
+    let secs = days * 86400 + hour * 3600 + minute * 60 + second;
+    Some(if secs >= 0 {
+        UNIX_EPOCH + Duration::from_secs(secs as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs())
+    })
+}
+
+fn days_in_month(year: i64, month: u32) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// The inverse of [`civil_from_days()`].
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let y = year - i64::from(month <= 2);
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 } as i64;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decode base64, ignoring any interspersed whitespace.  Returns `None` if
+/// the input contains any other non-alphabet characters or is truncated.
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() / 4 * 3);
+    let mut acc = 0u32;
+    let mut bits = 0;
+    let mut padding = 0;
+    for c in text.bytes() {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => {
+                padding += 1;
+                continue;
+            }
+            c if c.is_ascii_whitespace() => continue,
+            _ => return None,
+        };
+        if padding > 0 {
+            // Data after padding.
+            return None;
+        }
+        acc = acc << 6 | v as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+            acc &= (1 << bits) - 1;
+        }
+    }
+    // Any leftover bits must be zero padding of a final partial group.
+    if bits >= 6 || acc != 0 || padding > 2 {
+        return None;
+    }
+    Some(out)
+}
+
+/// Convert a byte offset into `text` into a 1-based (line, column) pair,
+/// counting columns in characters.
+fn line_column(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let column = before[line_start..].chars().count() + 1;
+    (line, column)
+}
+
+/// A start or end tag.
+struct Tag<'a> {
+    /// The element name.
+    name: &'a str,
+    /// Whether this is an end tag (`</name>`).
+    end: bool,
+    /// Whether this is an empty-element tag (`<name/>`).
+    empty: bool,
+    /// The byte offset of the opening `<`.
+    offset: usize,
+}
+
+/// A recursive-descent parser for the subset of XML used by property lists.
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+    /// The nesting depth of the container currently being parsed.
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(text: &'a str) -> Self {
+        // Skip the byte-order mark, if present.
+        let pos = if text.starts_with('\u{feff}') { 3 } else { 0 };
+        Parser {
+            text,
+            pos,
+            depth: 0,
+        }
+    }
+
+    fn error_at<T>(&self, offset: usize, message: String) -> Result<T, Error> {
+        let (line, column) = line_column(self.text, offset);
+        Err(Error::Xml {
+            line,
+            column,
+            message,
+        })
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    /// Skip over the text up to and including `terminator`, starting at the
+    /// current position.  `what` names the construct for error messages.
+    fn skip_past(&mut self, terminator: &str, what: &str) -> Result<&'a str, Error> {
+        let start = self.pos;
+        match self.rest().find(terminator) {
+            Some(i) => {
+                self.pos += i + terminator.len();
+                Ok(&self.text[start..start + i])
+            }
+            None => self.error_at(start, format!("unterminated {}", what)),
+        }
+    }
+
+    /// Skip whitespace, comments, processing instructions, and document
+    /// type declarations, none of which carry any property list data.
+    fn skip_misc(&mut self) -> Result<(), Error> {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            if trimmed.starts_with("<!--") {
+                self.skip_past("-->", "comment")?;
+            } else if trimmed.starts_with("<?") {
+                self.skip_past("?>", "processing instruction")?;
+            } else if trimmed.starts_with("<!DOCTYPE") {
+                self.skip_doctype()?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn skip_doctype(&mut self) -> Result<(), Error> {
+        let start = self.pos;
+        // The declaration may contain an internal subset in brackets, which
+        // may itself contain '>' characters.
+        let mut depth = 0;
+        for (i, c) in self.rest().char_indices() {
+            match c {
+                '[' => depth += 1,
+                ']' => depth -= 1,
+                '>' if depth == 0 => {
+                    self.pos += i + 1;
+                    return Ok(());
+                }
+                _ => (),
+            }
+        }
+        self.error_at(start, "unterminated document type declaration".to_string())
+    }
+
+    /// Parse the next tag, which must start at the current position.
+    fn parse_tag(&mut self) -> Result<Tag<'a>, Error> {
+        let offset = self.pos;
+        let rest = self.rest();
+        if rest.is_empty() {
+            return self.error_at(offset, "unexpected end of input".to_string());
+        }
+        if !rest.starts_with('<') {
+            return self.error_at(offset, "expected a tag".to_string());
+        }
+        let body = self.skip_past(">", "tag")?;
+        let mut body = &body[1..];
+        let end = body.starts_with('/');
+        if end {
+            body = &body[1..];
+        }
+        let empty = body.ends_with('/');
+        if empty {
+            body = &body[..body.len() - 1];
+        }
+        // Attributes (e.g. `version="1.0"`) are not meaningful to us.
+        let name = body
+            .split(|c: char| c.is_ascii_whitespace())
+            .next()
+            .unwrap_or("");
+        if name.is_empty() || (end && empty) {
+            return self.error_at(
+                offset,
+                format!("malformed tag `<{}>`", &self.text[offset + 1..self.pos - 1]),
+            );
+        }
+        Ok(Tag {
+            name,
+            end,
+            empty,
+            offset,
+        })
+    }
+
+    fn expect_end(&mut self, name: &str) -> Result<(), Error> {
+        self.skip_misc()?;
+        let tag = self.parse_tag()?;
+        if !tag.end || tag.name != name {
+            return self.error_at(
+                tag.offset,
+                format!("expected `</{}>`, found {}", name, describe(&tag)),
+            );
+        }
+        Ok(())
+    }
+
+    fn parse_document(&mut self) -> Result<Value, Error> {
+        self.skip_misc()?;
+        let start = self.pos;
+        let tag = self.parse_tag()?;
+        let value = if !tag.end && !tag.empty && tag.name == "plist" {
+            self.skip_misc()?;
+            let value = self.parse_value()?;
+            self.expect_end("plist")?;
+            value
+        } else {
+            // Tolerate a bare value without the `<plist>` wrapper.
+            self.pos = start;
+            self.parse_value()?
+        };
+        self.skip_misc()?;
+        if !self.rest().is_empty() {
+            return self.error_at(
+                self.pos,
+                "unexpected content after end of property list".to_string(),
+            );
+        }
+        Ok(value)
+    }
+
+    /// Begin parsing the contents of the container opened by `tag`, so long
+    /// as that does not nest containers too deeply.
+    fn enter(&mut self, tag: &Tag) -> Result<(), Error> {
+        if self.depth >= MAX_DEPTH {
+            return self.error_at(tag.offset, "containers are nested too deeply".to_string());
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// Parse a value, whose start tag begins at the current position.
+    fn parse_value(&mut self) -> Result<Value, Error> {
+        let tag = self.parse_tag()?;
+        if tag.end {
+            return self.error_at(
+                tag.offset,
+                format!("expected a value, found {}", describe(&tag)),
+            );
+        }
+        match tag.name {
+            "true" | "false" => {
+                if !tag.empty {
+                    self.expect_end(tag.name)?;
+                }
+                Ok(Value::Boolean(tag.name == "true"))
+            }
+            "string" => Ok(Value::String(self.parse_text(&tag)?)),
+            "integer" => self
+                .parse_scalar(&tag, "integer", parse_integer)
+                .map(Value::Integer),
+            "real" => self
+                .parse_scalar(&tag, "real number", parse_real)
+                .map(Value::Real),
+            "date" => self.parse_scalar(&tag, "date", parse_date).map(Value::Date),
+            "data" => self
+                .parse_scalar(&tag, "base64 data", base64_decode)
+                .map(Value::Data),
+            "array" => self.parse_array(&tag),
+            "dict" => self.parse_dict(&tag),
+            name => self.error_at(tag.offset, format!("unknown element `<{}>`", name)),
+        }
+    }
+
+    /// Parse the contents of an array, whose start tag `tag` has been read.
+    fn parse_array(&mut self, tag: &Tag) -> Result<Value, Error> {
+        let mut array = Vec::new();
+        if tag.empty {
+            return Ok(Value::Array(array));
+        }
+        self.enter(tag)?;
+        loop {
+            self.skip_misc()?;
+            if self.rest().starts_with("</") {
+                self.expect_end("array")?;
+                self.depth -= 1;
+                return Ok(Value::Array(array));
+            }
+            array.push(self.parse_value()?);
+        }
+    }
+
+    /// Parse the contents of a dictionary, whose start tag `tag` has been
+    /// read.
+    fn parse_dict(&mut self, tag: &Tag) -> Result<Value, Error> {
+        let mut dict = Dictionary::new();
+        if tag.empty {
+            return Ok(Value::Dictionary(dict));
+        }
+        self.enter(tag)?;
+        loop {
+            self.skip_misc()?;
+            if self.rest().starts_with("</") {
+                self.expect_end("dict")?;
+                self.depth -= 1;
+                return Ok(Value::Dictionary(dict));
+            }
+            let key_tag = self.parse_tag()?;
+            if key_tag.end || key_tag.name != "key" {
+                return self.error_at(
+                    key_tag.offset,
+                    format!("expected `<key>`, found {}", describe(&key_tag)),
+                );
+            }
+            let key = self.parse_text(&key_tag)?;
+            self.skip_misc()?;
+            let value_offset = self.pos;
+            if self.rest().starts_with("</") {
+                return self.error_at(value_offset, format!("missing value for key `{}`", key));
+            }
+            let value = self.parse_value()?;
+            if dict.contains_key(&key) {
+                return self.error_at(key_tag.offset, format!("duplicate key `{}`", key));
+            }
+            dict.insert(key, value);
+        }
+    }
+
+    /// Parse the contents of a text-only element with `parse`, reporting an
+    /// error at the start of the contents if they are invalid.
+    fn parse_scalar<T>(
+        &mut self,
+        tag: &Tag,
+        what: &str,
+        parse: fn(&str) -> Option<T>,
+    ) -> Result<T, Error> {
+        let content_offset = self.pos;
+        let text = self.parse_text(tag)?;
+        match parse(text.trim()) {
+            Some(value) => Ok(value),
+            None if text.trim().len() > 40 => {
+                self.error_at(content_offset, format!("invalid {}", what))
+            }
+            None => self.error_at(
+                content_offset,
+                format!("invalid {} `{}`", what, text.trim()),
+            ),
+        }
+    }
+
+    /// Parse the character data contents of a text-only element, up to and
+    /// including its end tag, decoding entity and character references.
+    fn parse_text(&mut self, tag: &Tag) -> Result<String, Error> {
+        let mut out = String::new();
+        if tag.empty {
+            return Ok(out);
+        }
+        loop {
+            let rest = self.rest();
+            let next = rest.find(['<', '&']).unwrap_or(rest.len());
+            self.check_chars(&rest[..next], self.pos)?;
+            out.push_str(&rest[..next]);
+            self.pos += next;
+            let rest = self.rest();
+            if rest.is_empty() {
+                return self.error_at(tag.offset, format!("unterminated `<{}>` element", tag.name));
+            } else if rest.starts_with('&') {
+                out.push(self.parse_reference()?);
+            } else if rest.starts_with("<![CDATA[") {
+                self.pos += "<![CDATA[".len();
+                let start = self.pos;
+                let text = self.skip_past("]]>", "CDATA section")?;
+                self.check_chars(text, start)?;
+                out.push_str(text);
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->", "comment")?;
+            } else {
+                let end = self.parse_tag()?;
+                if !end.end || end.name != tag.name {
+                    return self.error_at(
+                        end.offset,
+                        format!("expected `</{}>`, found {}", tag.name, describe(&end)),
+                    );
+                }
+                return Ok(out);
+            }
+        }
+    }
+
+    /// Fail if `text`, found at `offset`, contains a character which is not
+    /// allowed in XML, such as most control characters.
+    fn check_chars(&self, text: &str, offset: usize) -> Result<(), Error> {
+        match text.char_indices().find(|&(_, c)| !is_xml_char(c)) {
+            Some((i, c)) => self.error_at(
+                offset + i,
+                format!("character U+{:04X} is not allowed in XML", c as u32),
+            ),
+            None => Ok(()),
+        }
+    }
+
+    /// Parse an entity or character reference starting at the current
+    /// position, e.g. `&amp;` or `&#x1F600;`.
+    fn parse_reference(&mut self) -> Result<char, Error> {
+        let offset = self.pos;
+        let rest = self.rest();
+        let end = match rest.find(';') {
+            Some(end) if end <= 10 => end,
+            _ => return self.error_at(offset, "unterminated entity reference".to_string()),
+        };
+        let name = &rest[1..end];
+        let c = match name {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => {
+                let code = if let Some(hex) = name.strip_prefix("#x") {
+                    u32::from_str_radix(hex, 16).ok()
+                } else if let Some(dec) = name.strip_prefix('#') {
+                    dec.parse().ok()
+                } else {
+                    None
+                };
+                code.and_then(char::from_u32).filter(|&c| is_xml_char(c))
+            }
+        };
+        match c {
+            Some(c) => {
+                self.pos += end + 1;
+                Ok(c)
+            }
+            None => self.error_at(offset, format!("unknown entity reference `&{};`", name)),
+        }
+    }
+}
+
+/// Describe a tag for use in error messages.
+fn describe(tag: &Tag) -> String {
+    if tag.end {
+        format!("`</{}>`", tag.name)
+    } else if tag.empty {
+        format!("`<{}/>`", tag.name)
+    } else {
+        format!("`<{}>`", tag.name)
+    }
+}
+
+/// Parse a decimal or `0x`-prefixed hexadecimal integer with optional sign.
+fn parse_integer(s: &str) -> Option<i64> {
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    if digits.starts_with(['+', '-']) {
+        return None;
+    }
+    let magnitude = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        // `from_str_radix()` would accept another sign after the prefix.
+        Some(hex) if hex.bytes().all(|c| c.is_ascii_hexdigit()) => {
+            i128::from_str_radix(hex, 16).ok()?
+        }
+        Some(_) => return None,
+        None => digits.parse::<i128>().ok()?,
+    };
+    i64::try_from(if negative { -magnitude } else { magnitude }).ok()
+}
+
+/// Parse a real number, accepting CoreFoundation's spellings of the special
+/// values.
+fn parse_real(s: &str) -> Option<f64> {
+    match s.to_ascii_lowercase().as_str() {
+        "nan" => Some(f64::NAN),
+        "inf" | "infinity" | "+inf" | "+infinity" => Some(f64::INFINITY),
+        "-inf" | "-infinity" => Some(f64::NEG_INFINITY),
+        _ => s.parse().ok(),
+    }
+}
+
+// End of File
@@ -0,0 +1,296 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Copying files and directory trees into `Contents/Resources`.
+//!
+//! Resources are declared on the [`Trampoline`] and copied when the bundle is
+//! generated.  Files keep their permissions, and symbolic links within a
+//! resource directory are recreated as links rather than followed, so a
+//! resource tree containing a link to one of its own ancestors is copied
+//! faithfully instead of recursing forever.  A resource which is itself a
+//! link, to a file or a directory, is followed, as a link out of the bundle
+//! would not survive it being moved or signed.  Directories keep their
+//! permissions too, except that they stay writable by their owner, so that
+//! the bundle can be replaced by the next build.
+//!
+//! [`Trampoline`]: crate::Trampoline
+
+use crate::{manifest::Digest, Error};
+use glob::{MatchOptions, Pattern};
+use std::{
+    fs,
+    io::Error as IOError,
+    path::{Component, Path, PathBuf},
+};
+
+/// Glob options shared by include and exclude patterns: `*` and `?` do not
+/// match `/`, while `**` matches any number of directories.
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Glob patterns selecting which files of a resource directory are copied,
+/// matched against paths relative to the source directory, e.g.
+/// `shaders/*.wgsl` or `**/*.png`.
+///
+/// A file is copied if it matches any include pattern, or if there are no
+/// include patterns, and it matches no exclude pattern.  A directory which
+/// matches an exclude pattern is skipped entirely, so `**/.git` keeps a
+/// repository's metadata out of the bundle.
+#[derive(Clone, Debug, Default)]
+pub struct ResourceFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl ResourceFilter {
+    pub fn new() -> Self {
+        ResourceFilter::default()
+    }
+
+    /// Copy only files matching `pattern` (or another include pattern).
+    pub fn include(&mut self, pattern: &str) -> &mut Self {
+        self.include.push(pattern.to_string());
+        self
+    }
+
+    /// Skip files and directories matching `pattern`.
+    pub fn exclude(&mut self, pattern: &str) -> &mut Self {
+        self.exclude.push(pattern.to_string());
+        self
+    }
+
+    fn compile(&self) -> Result<CompiledFilter, Error> {
+        let compile = |patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| {
+                    Pattern::new(pattern).map_err(|err| Error::ResourcePattern {
+                        pattern: pattern.clone(),
+                        message: err.msg.to_string(),
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(CompiledFilter {
+            include: compile(&self.include)?,
+            exclude: compile(&self.exclude)?,
+        })
+    }
+}
+
+struct CompiledFilter {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl CompiledFilter {
+    fn excluded(&self, path: &Path) -> bool {
+        self.exclude
+            .iter()
+            .any(|p| p.matches_path_with(path, MATCH_OPTIONS))
+    }
+
+    fn included(&self, path: &Path) -> bool {
+        (self.include.is_empty()
+            || self
+                .include
+                .iter()
+                .any(|p| p.matches_path_with(path, MATCH_OPTIONS)))
+            && !self.excluded(path)
+    }
+}
+
+/// A file or directory tree to copy into `Contents/Resources`.
+#[derive(Clone, Debug)]
+pub(crate) struct Resource {
+    /// The file or directory to copy.
+    pub source: PathBuf,
+    /// The destination, relative to `Contents/Resources`.
+    pub dest: PathBuf,
+    /// `None` for a single file, or the filter for a directory tree.
+    pub filter: Option<ResourceFilter>,
+}
+
+impl Resource {
+    /// Check the destination, filter patterns, and source before anything is
+    /// written, so that a bad resource does not leave a partial bundle.
+    pub fn check(&self) -> Result<(), Error> {
+        let escapes = self
+            .dest
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir));
+        let empty = self.dest.components().all(|c| c == Component::CurDir);
+        if escapes || (self.filter.is_none() && empty) {
+            return Err(Error::ResourceDestination {
+                path: self.dest.clone(),
+            });
+        }
+        if let Some(filter) = &self.filter {
+            filter.compile()?;
+        }
+        // The source itself may be a symbolic link, e.g. to a directory of
+        // assets elsewhere, which is followed.  Links within a directory
+        // tree are copied as links.
+        let metadata = fs::metadata(&self.source).map_err(|source| Error::Resource {
+            path: self.source.clone(),
+            source,
+        })?;
+        if self.filter.is_some() != metadata.is_dir() {
+            return Err(Error::Resource {
+                path: self.source.clone(),
+                source: IOError::other(match metadata.is_dir() {
+                    true => "is a directory; use Trampoline::resource_dir()",
+                    false => "is not a directory; use Trampoline::resource()",
+                }),
+            });
+        }
+        Ok(())
+    }
+
+    /// Add the destination, filter, and source tree to `digest`, which
+    /// changes whenever the result of [`Self::copy()`] would.
+    pub fn fingerprint(&self, digest: &mut Digest) -> Result<(), Error> {
+        digest.add(&self.dest).add(&self.filter.is_some());
+        if let Some(filter) = &self.filter {
+            digest.add(&filter.include).add(&filter.exclude);
+        }
+        // Follow a link to the source, as `check()` and `copy()` do.
+        let source = fs::canonicalize(&self.source).map_err(error(&self.source))?;
+        digest.tree(&source).map(|_| ())
+    }
+
+    /// Copy into the `Contents/Resources` directory at `resources`.
+    pub fn copy(&self, resources: &Path) -> Result<(), Error> {
+        let dest = resources.join(&self.dest);
+        match &self.filter {
+            None => {
+                create_parent(&dest)?;
+                remove_existing(&dest)?;
+                // fs::copy follows a link to the file, and copies the
+                // permission bits.
+                fs::copy(&self.source, &dest)
+                    .map(|_| ())
+                    .map_err(error(&self.source))
+            }
+            Some(filter) => {
+                let filter = filter.compile()?;
+                copy_tree(&self.source, &dest, Path::new(""), &filter)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Wrap an error copying `path`, which is a resource source file.
+fn error(path: &Path) -> impl FnOnce(IOError) -> Error + '_ {
+    move |source| Error::Resource {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+fn create_parent(dest: &Path) -> Result<(), Error> {
+    match dest.parent() {
+        Some(parent) => fs::create_dir_all(parent).map_err(|err| Error::io(parent, err)),
+        None => Ok(()),
+    }
+}
+
+/// Remove whatever is at `dest`, so that a resource can replace it.
+fn remove_existing(dest: &Path) -> Result<(), Error> {
+    if let Ok(existing) = fs::symlink_metadata(dest) {
+        match existing.is_dir() {
+            true => fs::remove_dir_all(dest),
+            false => fs::remove_file(dest),
+        }
+        .map_err(|err| Error::io(dest, err))?;
+    }
+    Ok(())
+}
+
+/// Copy a single file or symbolic link within a directory tree, replacing
+/// anything at `dest`.
+fn copy_entry(source: &Path, dest: &Path) -> Result<(), Error> {
+    remove_existing(dest)?;
+    #[cfg(unix)]
+    if fs::symlink_metadata(source)
+        .map_err(error(source))?
+        .file_type()
+        .is_symlink()
+    {
+        let target = fs::read_link(source).map_err(error(source))?;
+        return std::os::unix::fs::symlink(target, dest).map_err(error(source));
+    }
+    // fs::copy also copies the permission bits.  Without Unix symbolic
+    // links, this copies whatever a link points to instead.
+    fs::copy(source, dest).map(|_| ()).map_err(error(source))
+}
+
+/// Copy the whole directory tree `source` to `dest`, preserving permissions
+/// and symbolic links as for a resource directory.
+pub(crate) fn copy_dir(source: &Path, dest: &Path) -> Result<(), Error> {
+    let filter = CompiledFilter {
+        include: Vec::new(),
+        exclude: Vec::new(),
+    };
+    copy_tree(source, dest, Path::new(""), &filter).map(|_| ())
+}
+
+/// Recursively copy the directory `source` to `dest`, where `relative` is
+/// the path of `source` relative to the resource root, against which the
+/// filter is matched.  Returns whether anything was copied, so that
+/// directories left empty by an include filter are not created.
+fn copy_tree(
+    source: &Path,
+    dest: &Path,
+    relative: &Path,
+    filter: &CompiledFilter,
+) -> Result<bool, Error> {
+    let mut entries = fs::read_dir(source)
+        .and_then(|dir| dir.collect::<Result<Vec<_>, _>>())
+        .map_err(error(source))?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    let mut copied = filter.include.is_empty();
+    if copied {
+        fs::create_dir_all(dest).map_err(|err| Error::io(dest, err))?;
+    }
+    for entry in entries {
+        let path = entry.path();
+        let relative = relative.join(entry.file_name());
+        let target = dest.join(entry.file_name());
+        let file_type = entry.file_type().map_err(error(&path))?;
+        if file_type.is_dir() {
+            if !filter.excluded(&relative) {
+                copied |= copy_tree(&path, &target, &relative, filter)?;
+            }
+        } else if filter.included(&relative) {
+            fs::create_dir_all(dest).map_err(|err| Error::io(dest, err))?;
+            copy_entry(&path, &target)?;
+            copied = true;
+        }
+    }
+
+    // Apply the directory's permissions last, in case they forbid writing,
+    // but keep it writable by its owner, or the next build could not remove
+    // it.
+    if copied {
+        let mut permissions = fs::metadata(source).map_err(error(source))?.permissions();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            permissions.set_mode(permissions.mode() | 0o200);
+        }
+        #[cfg(not(unix))]
+        permissions.set_readonly(false);
+        fs::set_permissions(dest, permissions).map_err(|err| Error::io(dest, err))?;
+    }
+    Ok(copied)
+}
+
+// End of File
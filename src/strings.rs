@@ -0,0 +1,345 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Reading and writing `.strings` files, the key-value tables used for
+//! localization, such as the `<locale>.lproj/InfoPlist.strings` files which
+//! localize `Info.plist` keys like `CFBundleDisplayName`:
+//!
+//! ```text
+//! /* Localized versions of Info.plist keys */
+//! "CFBundleDisplayName" = "Mon application";
+//! "NSHumanReadableCopyright" = "\U00A9 2024 Exemple";
+//! ```
+//!
+//! [`to_bytes()`] writes UTF-16 with a byte order mark, as Apple recommends.
+//! [`from_bytes()`] reads UTF-16 in either byte order (with a byte order
+//! mark) or UTF-8, along with the whole legacy syntax: comments, unquoted
+//! keys and values, and the `"key";` shorthand for a value equal to its key.
+
+use std::{collections::BTreeMap, fmt, path::PathBuf};
+
+/// The entries of a `.strings` file, sorted by key.
+pub type Strings = BTreeMap<String, String>;
+
+/// An error encountered while reading a `.strings` file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The file is neither UTF-8 nor UTF-16 with a byte order mark.
+    Encoding,
+    /// The file is malformed.  `line` and `column` are 1-based, with columns
+    /// counted in characters.
+    Syntax {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Encoding => write!(f, "strings file is not UTF-8 or UTF-16"),
+            Error::Syntax {
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "malformed strings file at line {}, column {}: {}",
+                line, column, message
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// The `InfoPlist.strings` entries of one locale of a [`Trampoline`]:
+/// translation files to import, and strings set explicitly, which take
+/// precedence.
+///
+/// [`Trampoline`]: crate::Trampoline
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Localization {
+    pub files: Vec<PathBuf>,
+    pub strings: Strings,
+}
+
+impl Localization {
+    /// Read the files in order, then apply the explicit strings.
+    pub fn load(&self) -> Result<Strings, crate::Error> {
+        let mut strings = Strings::new();
+        for path in &self.files {
+            let bytes = std::fs::read(path).map_err(|err| crate::Error::io(path, err))?;
+            strings.extend(from_bytes(&bytes).map_err(|source| crate::Error::Strings {
+                path: path.clone(),
+                source,
+            })?);
+        }
+        strings.extend(self.strings.clone());
+        Ok(strings)
+    }
+}
+
+/// Format `strings` as the text of a `.strings` file, one entry per line.
+pub fn to_string(strings: &Strings) -> String {
+    let mut text = String::new();
+    for (key, value) in strings {
+        text.push_str(&quote(key));
+        text.push_str(" = ");
+        text.push_str(&quote(value));
+        text.push_str(";\n");
+    }
+    text
+}
+
+/// Encode `strings` as a `.strings` file in UTF-16LE with a byte order mark.
+pub fn to_bytes(strings: &Strings) -> Vec<u8> {
+    std::iter::once(0xfeff)
+        .chain(to_string(strings).encode_utf16())
+        .flat_map(u16::to_le_bytes)
+        .collect()
+}
+
+/// Quote and escape a string.  Characters other than controls are written
+/// as-is, since the file is Unicode.
+fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            c if c.is_control() => quoted.push_str(&format!("\\U{:04X}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Decode the text of a `.strings` file from UTF-16 with a byte order mark,
+/// or UTF-8 with or without one.
+fn decode(bytes: &[u8]) -> Result<String, Error> {
+    let utf16 = |bytes: &[u8], unit: fn([u8; 2]) -> u16| {
+        if !bytes.len().is_multiple_of(2) {
+            return Err(Error::Encoding);
+        }
+        let units = bytes.chunks_exact(2).map(|b| unit([b[0], b[1]]));
+        char::decode_utf16(units)
+            .collect::<Result<String, _>>()
+            .map_err(|_| Error::Encoding)
+    };
+    match bytes {
+        [0xff, 0xfe, rest @ ..] => utf16(rest, u16::from_le_bytes),
+        [0xfe, 0xff, rest @ ..] => utf16(rest, u16::from_be_bytes),
+        [0xef, 0xbb, 0xbf, rest @ ..] | rest => {
+            String::from_utf8(rest.to_vec()).map_err(|_| Error::Encoding)
+        }
+    }
+}
+
+/// Parse a `.strings` file.  If a key appears more than once, the last
+/// value wins.
+pub fn from_bytes(bytes: &[u8]) -> Result<Strings, Error> {
+    from_str(&decode(bytes)?)
+}
+
+/// Parse the text of a `.strings` file.
+pub fn from_str(text: &str) -> Result<Strings, Error> {
+    let mut parser = Parser {
+        chars: text.chars().collect(),
+        pos: 0,
+    };
+    let mut strings = Strings::new();
+    loop {
+        parser.skip()?;
+        if parser.peek().is_none() {
+            return Ok(strings);
+        }
+        let key = parser.token()?;
+        parser.skip()?;
+        let value = match parser.peek() {
+            Some('=') => {
+                parser.pos += 1;
+                parser.skip()?;
+                let value = parser.token()?;
+                parser.skip()?;
+                value
+            }
+            _ => key.clone(),
+        };
+        parser.expect(';')?;
+        strings.insert(key, value);
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+/// Characters allowed in unquoted keys and values.
+fn is_unquoted(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_$+/:.-".contains(c)
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn error(&self, message: &str) -> Error {
+        let before = &self.chars[..self.pos.min(self.chars.len())];
+        let line = before.iter().filter(|&&c| c == '\n').count() + 1;
+        let column = before.iter().rev().take_while(|&&c| c != '\n').count() + 1;
+        Error::Syntax {
+            line,
+            column,
+            message: message.to_string(),
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), Error> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(c) => Err(self.error(&format!("expected {:?}, found {:?}", expected, c))),
+            None => Err(self.error(&format!("expected {:?}, found end of file", expected))),
+        }
+    }
+
+    /// Skip whitespace and comments.
+    fn skip(&mut self) -> Result<(), Error> {
+        loop {
+            match (self.peek(), self.chars.get(self.pos + 1)) {
+                (Some(c), _) if c.is_whitespace() || c == '\u{feff}' => self.pos += 1,
+                (Some('/'), Some('/')) => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.pos += 1;
+                    }
+                }
+                (Some('/'), Some('*')) => {
+                    let start = self.pos;
+                    self.pos += 2;
+                    loop {
+                        match (self.peek(), self.chars.get(self.pos + 1)) {
+                            (Some('*'), Some('/')) => break self.pos += 2,
+                            (Some(_), _) => self.pos += 1,
+                            (None, _) => {
+                                self.pos = start;
+                                return Err(self.error("unterminated comment"));
+                            }
+                        }
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Parse a quoted or unquoted string.
+    fn token(&mut self) -> Result<String, Error> {
+        match self.peek() {
+            Some('"') => self.quoted(),
+            Some(c) if is_unquoted(c) => {
+                let start = self.pos;
+                while self.peek().is_some_and(is_unquoted) {
+                    self.pos += 1;
+                }
+                Ok(self.chars[start..self.pos].iter().collect())
+            }
+            Some(c) => Err(self.error(&format!("expected a string, found {:?}", c))),
+            None => Err(self.error("expected a string, found end of file")),
+        }
+    }
+
+    fn quoted(&mut self) -> Result<String, Error> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut s = String::new();
+        // A pending high surrogate from a \U escape, to be combined with a
+        // low surrogate in the next escape.
+        let mut high: Option<u16> = None;
+        loop {
+            let c = match self.peek() {
+                Some(c) => c,
+                None => {
+                    self.pos = start;
+                    return Err(self.error("unterminated string"));
+                }
+            };
+            self.pos += 1;
+            let unit = match c {
+                '"' if high.is_none() => return Ok(s),
+                '\\' => {
+                    let escape = self
+                        .peek()
+                        .ok_or_else(|| self.error("unterminated escape"))?;
+                    self.pos += 1;
+                    match escape {
+                        'n' => Ok('\n'),
+                        't' => Ok('\t'),
+                        'r' => Ok('\r'),
+                        'a' => Ok('\u{7}'),
+                        'b' => Ok('\u{8}'),
+                        'f' => Ok('\u{c}'),
+                        'v' => Ok('\u{b}'),
+                        'U' | 'u' => Err(self.digits(16, 4)?),
+                        '0'..='7' => {
+                            self.pos -= 1;
+                            Err(self.digits(8, 3)?)
+                        }
+                        c => Ok(c),
+                    }
+                }
+                c => Ok(c),
+            };
+            // Ok is a character, and Err a UTF-16 code unit from an escape.
+            match (high.take(), unit) {
+                (None, Ok(c)) => s.push(c),
+                (None, Err(unit @ 0xd800..=0xdbff)) => high = Some(unit),
+                (Some(first), Err(unit @ 0xdc00..=0xdfff)) => {
+                    let c = char::decode_utf16([first, unit]).next().unwrap().unwrap();
+                    s.push(c);
+                }
+                (None, Err(unit)) => match char::from_u32(unit as u32) {
+                    Some(c) => s.push(c),
+                    None => return Err(self.error("unpaired surrogate in escape")),
+                },
+                (Some(_), _) => {
+                    self.pos -= 1;
+                    return Err(self.error("unpaired surrogate in escape"));
+                }
+            }
+        }
+    }
+
+    /// Parse up to `max` digits in `radix`, at least one of which must be
+    /// present.
+    fn digits(&mut self, radix: u32, max: usize) -> Result<u16, Error> {
+        let mut value = 0u16;
+        let mut count = 0;
+        while count < max {
+            match self.peek().and_then(|c| c.to_digit(radix)) {
+                Some(digit) => value = value * radix as u16 + digit as u16,
+                None => break,
+            }
+            self.pos += 1;
+            count += 1;
+        }
+        match count {
+            0 => Err(self.error("expected digits in escape")),
+            _ => Ok(value),
+        }
+    }
+}
+
+// End of File
@@ -0,0 +1,382 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Validation of the application name and bundle identifier, which end up in
+//! both the filesystem path of the bundle and its `Info.plist`, and of the
+//! rest of the configuration of a trampoline, along with warnings about
+//! configuration which is valid but likely a mistake.
+
+use crate::{macho::OsVersion, Permission, VersionError};
+use std::fmt;
+use unicode_normalization::UnicodeNormalization;
+
+/// The longest file name, in bytes, supported by APFS and HFS+.  The bundle
+/// directory name is the application name plus the `.app` extension.
+const MAX_FILE_NAME: usize = 255;
+const BUNDLE_EXTENSION: &str = ".app";
+
+/// A problem with the configuration of a [`Trampoline`], detected before
+/// anything is written to the filesystem.
+///
+/// [`Trampoline`]: crate::Trampoline
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ValidationError {
+    /// The application name is empty.
+    EmptyName,
+    /// The application name contains `/`, or `:` which the Finder and
+    /// Carbon APIs treat as a path separator.
+    NameContainsSeparator { name: String, separator: char },
+    /// The application name contains NUL or another control character.
+    NameContainsControl { name: String, character: char },
+    /// The application name begins with `.`, which would make the bundle a
+    /// hidden file, or a traversal component such as `..`.
+    NameIsHidden { name: String },
+    /// The bundle directory name, `<name>.app`, would exceed the maximum file
+    /// name length of the filesystem.
+    NameTooLong {
+        name: String,
+        len: usize,
+        max: usize,
+    },
+    /// The bundle identifier is empty.
+    EmptyIdent,
+    /// The bundle identifier contains a character other than an ASCII
+    /// alphanumeric, `-`, or `.`.
+    IdentInvalidCharacter { ident: String, character: char },
+    /// The bundle identifier has an empty component, i.e. it begins or ends
+    /// with `.` or contains `..`.
+    IdentEmptyComponent { ident: String },
+    /// The version cannot be represented in `CFBundleVersion` and
+    /// `CFBundleShortVersionString`.
+    InvalidVersion(VersionError),
+    /// A locale is not a language code such as `en`, `pt-BR`, or `zh_Hans`,
+    /// which must contain only ASCII alphanumerics, `-`, and `_`.
+    InvalidLocale { locale: String },
+    /// A uniform type identifier is empty, has an empty component, or
+    /// contains a character other than an ASCII alphanumeric, `-`, or `.`.
+    InvalidUti { uti: String },
+    /// A file name extension is empty, or contains `.`, `/`, or whitespace.
+    InvalidExtension { extension: String },
+    /// A document type matches no files, as it has no content types,
+    /// extensions, or MIME types.
+    EmptyDocumentType { name: String },
+    /// The same uniform type identifier is declared twice.
+    DuplicateTypeDeclaration { uti: String },
+    /// Two document types, or two type declarations, claim the same file
+    /// name extension, so which applies to a file would be ambiguous.
+    DuplicateExtension {
+        extension: String,
+        first: String,
+        second: String,
+    },
+    /// A URL scheme does not begin with an ASCII letter followed by ASCII
+    /// alphanumerics, `+`, `-`, and `.`, as required by RFC 3986.
+    InvalidUrlScheme { scheme: String },
+    /// A macOS version is not of the form `major[.minor[.patch]]`.
+    InvalidSystemVersion { version: String },
+    /// A macOS version is earlier than 10.0, or later than 10.16 but
+    /// earlier than 11.0, so no such version exists.
+    SystemVersionOutOfRange { version: String },
+    /// A minimum macOS version for an architecture is earlier than the
+    /// first version to support that architecture, e.g. 11.0 for `arm64`.
+    ArchitectureSystemVersion {
+        arch: String,
+        version: String,
+        minimum: String,
+    },
+    /// An architecture has no name for use in `Info.plist`.
+    UnsupportedArchitecture { arch: String },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::EmptyName => write!(f, "application name is empty"),
+            ValidationError::NameContainsSeparator { name, separator } => write!(
+                f,
+                "application name {:?} contains path separator {:?}",
+                name, separator
+            ),
+            ValidationError::NameContainsControl { name, character } => write!(
+                f,
+                "application name {:?} contains control character {:?}",
+                name, character
+            ),
+            ValidationError::NameIsHidden { name } => {
+                write!(f, "application name {:?} must not begin with '.'", name)
+            }
+            ValidationError::NameTooLong { name, len, max } => write!(
+                f,
+                "application name {:?} is too long: the bundle name would be {} bytes, but the maximum is {}",
+                name, len, max
+            ),
+            ValidationError::EmptyIdent => write!(f, "bundle identifier is empty"),
+            ValidationError::IdentInvalidCharacter { ident, character } => write!(
+                f,
+                "bundle identifier {:?} contains {:?}, but may only contain alphanumerics, '-', and '.'",
+                ident, character
+            ),
+            ValidationError::IdentEmptyComponent { ident } => write!(
+                f,
+                "bundle identifier {:?} has an empty component",
+                ident
+            ),
+            ValidationError::InvalidVersion(err) => write!(f, "{}", err),
+            ValidationError::InvalidLocale { locale } => write!(
+                f,
+                "locale {:?} may only contain alphanumerics, '-', and '_'",
+                locale
+            ),
+            ValidationError::InvalidUti { uti } => write!(
+                f,
+                "uniform type identifier {:?} must be reverse-DNS, with only alphanumerics, '-', and '.'",
+                uti
+            ),
+            ValidationError::InvalidExtension { extension } => write!(
+                f,
+                "invalid file name extension {:?}: it must be non-empty, without '.', '/', or whitespace",
+                extension
+            ),
+            ValidationError::EmptyDocumentType { name } => write!(
+                f,
+                "document type {:?} has no content types, extensions, or MIME types",
+                name
+            ),
+            ValidationError::DuplicateTypeDeclaration { uti } => {
+                write!(f, "uniform type identifier {:?} is declared twice", uti)
+            }
+            ValidationError::DuplicateExtension {
+                extension,
+                first,
+                second,
+            } => write!(
+                f,
+                "file name extension {:?} is claimed by both {:?} and {:?}",
+                extension, first, second
+            ),
+            ValidationError::InvalidUrlScheme { scheme } => write!(
+                f,
+                "URL scheme {:?} must be a letter followed by alphanumerics, '+', '-', and '.'",
+                scheme
+            ),
+            ValidationError::InvalidSystemVersion { version } => write!(
+                f,
+                "macOS version {:?} must be of the form major[.minor[.patch]]",
+                version
+            ),
+            ValidationError::SystemVersionOutOfRange { version } => {
+                write!(f, "there is no macOS version {:?}", version)
+            }
+            ValidationError::ArchitectureSystemVersion {
+                arch,
+                version,
+                minimum,
+            } => write!(
+                f,
+                "minimum macOS version {:?} for {} is earlier than {}, the first to support it",
+                version, arch, minimum
+            ),
+            ValidationError::UnsupportedArchitecture { arch } => {
+                write!(f, "architecture {} has no Info.plist name", arch)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ValidationError::InvalidVersion(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// A likely mistake in the configuration of a [`Trampoline`], which does
+/// not prevent the bundle from being generated, but will probably cause
+/// the application to misbehave when run.
+///
+/// [`Trampoline`]: crate::Trampoline
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Warning {
+    /// The entitlement `entitlement` declares that the application uses
+    /// `permission`, but the `Info.plist` has no usage description for it,
+    /// so macOS will terminate the application when it asks for access.
+    MissingUsageDescription {
+        permission: Permission,
+        entitlement: String,
+    },
+    /// `LSMinimumSystemVersion` is earlier than the deployment target of
+    /// the executable, so on the versions in between macOS will launch the
+    /// application only for it to crash, rather than refusing with an
+    /// explanation.
+    DeploymentTarget {
+        minimum_system_version: String,
+        deployment_target: OsVersion,
+    },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warning::MissingUsageDescription {
+                permission,
+                entitlement,
+            } => write!(
+                f,
+                "entitlement {} is enabled, but Info.plist has no {}",
+                entitlement, permission
+            ),
+            Warning::DeploymentTarget {
+                minimum_system_version,
+                deployment_target,
+            } => write!(
+                f,
+                "LSMinimumSystemVersion {} is earlier than the executable's deployment target {}",
+                minimum_system_version, deployment_target
+            ),
+        }
+    }
+}
+
+/// Normalize an application name to Unicode Normalization Form C.  HFS+
+/// stores file names decomposed and APFS preserves whatever it is given, so
+/// picking one form up front keeps the bundle path, `CFBundleName`, and any
+/// comparison against an existing bundle consistent.
+pub(crate) fn normalize_name(name: &str) -> String {
+    name.nfc().collect()
+}
+
+/// Check that `name` is safe to use as the bundle directory name.
+pub(crate) fn validate_name(name: &str) -> Result<(), ValidationError> {
+    if name.is_empty() {
+        return Err(ValidationError::EmptyName);
+    }
+    if let Some(separator) = name.chars().find(|&c| c == '/' || c == ':') {
+        return Err(ValidationError::NameContainsSeparator {
+            name: name.to_string(),
+            separator,
+        });
+    }
+    if let Some(character) = name.chars().find(|c| c.is_control()) {
+        return Err(ValidationError::NameContainsControl {
+            name: name.to_string(),
+            character,
+        });
+    }
+    if name.starts_with('.') {
+        return Err(ValidationError::NameIsHidden {
+            name: name.to_string(),
+        });
+    }
+    let len = name.len() + BUNDLE_EXTENSION.len();
+    if len > MAX_FILE_NAME {
+        return Err(ValidationError::NameTooLong {
+            name: name.to_string(),
+            len,
+            max: MAX_FILE_NAME,
+        });
+    }
+    Ok(())
+}
+
+/// Check that `ident` is a well-formed reverse-DNS bundle identifier.
+pub(crate) fn validate_ident(ident: &str) -> Result<(), ValidationError> {
+    if ident.is_empty() {
+        return Err(ValidationError::EmptyIdent);
+    }
+    if let Some(character) = ident
+        .chars()
+        .find(|&c| !(c.is_ascii_alphanumeric() || c == '-' || c == '.'))
+    {
+        return Err(ValidationError::IdentInvalidCharacter {
+            ident: ident.to_string(),
+            character,
+        });
+    }
+    if ident.split('.').any(str::is_empty) {
+        return Err(ValidationError::IdentEmptyComponent {
+            ident: ident.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Check that `locale` is usable as the name of a `.lproj` directory.
+pub(crate) fn validate_locale(locale: &str) -> Result<(), ValidationError> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    if locale.is_empty() || !locale.chars().all(valid) {
+        return Err(ValidationError::InvalidLocale {
+            locale: locale.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Check that `uti` is a well-formed uniform type identifier.
+pub(crate) fn validate_uti(uti: &str) -> Result<(), ValidationError> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '.';
+    if !uti.chars().all(valid) || uti.split('.').any(str::is_empty) {
+        return Err(ValidationError::InvalidUti {
+            uti: uti.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Check that `extension` is a file name extension without the leading `.`.
+pub(crate) fn validate_extension(extension: &str) -> Result<(), ValidationError> {
+    let invalid = |c: char| c == '.' || c == '/' || c.is_whitespace() || c.is_control();
+    if extension.is_empty() || extension.chars().any(invalid) {
+        return Err(ValidationError::InvalidExtension {
+            extension: extension.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Check that `scheme` is a URL scheme, without the trailing `:`.
+pub(crate) fn validate_url_scheme(scheme: &str) -> Result<(), ValidationError> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.';
+    if !scheme.starts_with(|c: char| c.is_ascii_alphabetic()) || !scheme.chars().all(valid) {
+        return Err(ValidationError::InvalidUrlScheme {
+            scheme: scheme.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Parse `version` as a macOS version, `major[.minor[.patch]]`, e.g.
+/// `"10.13"` or `"14.2.1"`, and check that there is such a version.  Big
+/// Sur reports itself as 10.16 to software built for older versions, so
+/// that is accepted too.
+pub(crate) fn parse_system_version(version: &str) -> Result<OsVersion, ValidationError> {
+    let invalid = || ValidationError::InvalidSystemVersion {
+        version: version.to_string(),
+    };
+    let components: Vec<&str> = version.split('.').collect();
+    if components.len() > 3
+        || components
+            .iter()
+            .any(|c| c.is_empty() || !c.bytes().all(|b| b.is_ascii_digit()))
+    {
+        return Err(invalid());
+    }
+    let component = |index: usize| components.get(index).copied().unwrap_or("0");
+    let parsed = OsVersion::new(
+        component(0).parse().map_err(|_| invalid())?,
+        component(1).parse().map_err(|_| invalid())?,
+        component(2).parse().map_err(|_| invalid())?,
+    );
+    if parsed.major < 10 || (parsed.major == 10 && parsed.minor > 16) {
+        return Err(ValidationError::SystemVersionOutOfRange {
+            version: version.to_string(),
+        });
+    }
+    Ok(parsed)
+}
+
+// End of File
@@ -0,0 +1,351 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Conversion of Cargo-style semantic versions into the two version strings
+//! an app bundle carries in its `Info.plist`.
+//!
+//! * `CFBundleShortVersionString` is the user-visible release version, which
+//!   must be three period-separated integers, e.g. `1.2.3`.
+//! * `CFBundleVersion` is the build version, which must also be at most
+//!   three period-separated integers, as the App Store and notarization
+//!   reject anything else, but must additionally order pre-releases before
+//!   the release.  So the development stage is encoded numerically in the
+//!   last component, as `PATCH * 10000 + STAGE * 1000 + N`, where `STAGE` is
+//!   1 for development, 2 for alpha, 3 for beta, 4 for release candidates,
+//!   and 9 for releases, and `N` is the pre-release number, if any.  For
+//!   example, `1.2.3-beta.4` is `1.2.33004` and `1.2.3` is `1.2.39000`.
+//!
+//! The major version may have at most five digits, the minor and patch
+//! versions at most two, and the stage number must fit in a byte.
+
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+/// The largest representable major, minor, and patch versions.
+const MAX_MAJOR: u64 = 99999;
+const MAX_MINOR: u64 = 99;
+const MAX_PATCH: u64 = 99;
+
+/// The development stage of a version, ordered from least to most mature.
+/// The optional number is the pre-release iteration, e.g. the `2` in
+/// `1.0.0-beta.2`.  A missing number is encoded as 0, so `Some(0)` is not
+/// used: it is rejected when parsing, and replaced by `None` by
+/// [`BundleVersion::with_stage()`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage {
+    /// `-dev[.N]`, encoded as stage 1.
+    Development(Option<u8>),
+    /// `-alpha[.N]`, encoded as stage 2.
+    Alpha(Option<u8>),
+    /// `-beta[.N]`, encoded as stage 3.
+    Beta(Option<u8>),
+    /// `-rc[.N]`, encoded as stage 4.
+    ReleaseCandidate(Option<u8>),
+    /// No pre-release suffix, encoded as stage 9.
+    Release,
+}
+
+/// An application version which is representable in both
+/// `CFBundleShortVersionString` and `CFBundleVersion`.
+///
+/// Parsed from Cargo-style semantic versions such as `1.2.3`,
+/// `1.2.3-beta.4`, or `1.2.3-rc.1+20240229`.  Versions are ordered by
+/// release, then stage, then stage number; build metadata is ignored for
+/// ordering and equality, as in semver.
+///
+/// The components are only accessible through methods, so that every
+/// `BundleVersion` is within the limits checked by [`BundleVersion::new()`].
+#[derive(Clone, Debug)]
+pub struct BundleVersion {
+    major: u32,
+    minor: u8,
+    patch: u8,
+    stage: Stage,
+    /// Build metadata following a `+`, which is preserved but not written to
+    /// the bundle.
+    build: Option<String>,
+}
+
+/// Why a version string cannot be used as a [`BundleVersion`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VersionError {
+    /// The string is not of the form `MAJOR.MINOR.PATCH[-PRE][+BUILD]`.
+    Syntax { version: String },
+    /// A version component exceeds what Apple's version format can hold.
+    OutOfRange {
+        version: String,
+        component: &'static str,
+        value: u64,
+        max: u64,
+    },
+    /// The pre-release suffix is not one of `dev`, `alpha`, `beta`, or `rc`,
+    /// optionally followed by `.N` with `N` at least 1.
+    UnsupportedPrerelease { version: String, prerelease: String },
+}
+
+impl fmt::Display for VersionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VersionError::Syntax { version } => write!(
+                f,
+                "version {:?} is not of the form MAJOR.MINOR.PATCH[-PRERELEASE][+BUILD]",
+                version
+            ),
+            VersionError::OutOfRange {
+                version,
+                component,
+                value,
+                max,
+            } => write!(
+                f,
+                "{} version {} of {:?} exceeds the maximum of {}",
+                component, value, version, max
+            ),
+            VersionError::UnsupportedPrerelease {
+                version,
+                prerelease,
+            } => write!(
+                f,
+                "pre-release {:?} of {:?} cannot be represented; use dev, alpha, beta, or rc with an optional .N, where N is at least 1",
+                prerelease, version
+            ),
+        }
+    }
+}
+
+impl std::error::Error for VersionError {}
+
+impl BundleVersion {
+    /// Create a release version.
+    pub fn new(major: u32, minor: u8, patch: u8) -> Result<Self, VersionError> {
+        let version = BundleVersion {
+            major,
+            minor,
+            patch,
+            stage: Stage::Release,
+            build: None,
+        };
+        check_range(
+            &version.to_string(),
+            major as u64,
+            minor as u64,
+            patch as u64,
+        )?;
+        Ok(version)
+    }
+
+    /// Set the development stage.  A stage number of 0 is replaced by no
+    /// number, which is encoded the same way.
+    pub fn with_stage(mut self, stage: Stage) -> Self {
+        self.stage = match stage {
+            Stage::Development(Some(0)) => Stage::Development(None),
+            Stage::Alpha(Some(0)) => Stage::Alpha(None),
+            Stage::Beta(Some(0)) => Stage::Beta(None),
+            Stage::ReleaseCandidate(Some(0)) => Stage::ReleaseCandidate(None),
+            stage => stage,
+        };
+        self
+    }
+
+    pub fn major(&self) -> u32 {
+        self.major
+    }
+    pub fn minor(&self) -> u8 {
+        self.minor
+    }
+    pub fn patch(&self) -> u8 {
+        self.patch
+    }
+    pub fn stage(&self) -> Stage {
+        self.stage
+    }
+    /// Build metadata following a `+`, which is preserved but not written to
+    /// the bundle.
+    pub fn build(&self) -> Option<&str> {
+        self.build.as_deref()
+    }
+
+    /// The value for `CFBundleShortVersionString`, e.g. `1.2.3`.
+    pub fn short_version_string(&self) -> String {
+        format!("{}.{}.{}", self.major, self.minor, self.patch)
+    }
+
+    /// The value for `CFBundleVersion`, e.g. `1.2.33004` for
+    /// `1.2.3-beta.4`.  The last component is
+    /// `PATCH * 10000 + STAGE * 1000 + N`, where `STAGE` is the number given
+    /// for each [`Stage`] and `N` is the pre-release number, so that
+    /// pre-releases sort before the release.
+    /// A pre-release without a number, e.g. `1.2.3-beta`, is encoded with
+    /// `N` of 0.
+    pub fn bundle_version(&self) -> String {
+        let (stage, number) = match self.stage {
+            Stage::Development(n) => (1, n),
+            Stage::Alpha(n) => (2, n),
+            Stage::Beta(n) => (3, n),
+            Stage::ReleaseCandidate(n) => (4, n),
+            Stage::Release => (9, None),
+        };
+        let last = self.patch as u32 * 10000 + stage * 1000 + number.unwrap_or(0) as u32;
+        format!("{}.{}.{}", self.major, self.minor, last)
+    }
+}
+
+/// Check that each release component fits Apple's version format.
+fn check_range(version: &str, major: u64, minor: u64, patch: u64) -> Result<(), VersionError> {
+    for (component, value, max) in [
+        ("major", major, MAX_MAJOR),
+        ("minor", minor, MAX_MINOR),
+        ("patch", patch, MAX_PATCH),
+    ] {
+        if value > max {
+            return Err(VersionError::OutOfRange {
+                version: version.to_string(),
+                component,
+                value,
+                max,
+            });
+        }
+    }
+    Ok(())
+}
+
+impl FromStr for BundleVersion {
+    type Err = VersionError;
+
+    fn from_str(version: &str) -> Result<Self, VersionError> {
+        let syntax = || VersionError::Syntax {
+            version: version.to_string(),
+        };
+        let (rest, build) = match version.split_once('+') {
+            Some((rest, build)) => (rest, Some(build)),
+            None => (version, None),
+        };
+        let (release, prerelease) = match rest.split_once('-') {
+            Some((release, prerelease)) => (release, Some(prerelease)),
+            None => (rest, None),
+        };
+        if build.is_some_and(|b| b.is_empty() || b.contains('+'))
+            || prerelease.is_some_and(str::is_empty)
+        {
+            return Err(syntax());
+        }
+
+        // Semver forbids leading zeros, and so do we.
+        let number = |s: &str| -> Result<u64, VersionError> {
+            if s.is_empty()
+                || !s.bytes().all(|c| c.is_ascii_digit())
+                || (s.len() > 1 && s.starts_with('0'))
+            {
+                return Err(syntax());
+            }
+            s.parse().map_err(|_| syntax())
+        };
+        let parts: Vec<&str> = release.split('.').collect();
+        let [major, minor, patch] = parts[..] else {
+            return Err(syntax());
+        };
+        let (major, minor, patch) = (number(major)?, number(minor)?, number(patch)?);
+        check_range(version, major, minor, patch)?;
+
+        let stage = match prerelease {
+            None => Stage::Release,
+            Some(pre) => {
+                let unsupported = || VersionError::UnsupportedPrerelease {
+                    version: version.to_string(),
+                    prerelease: pre.to_string(),
+                };
+                let (label, n) = match pre.split_once('.') {
+                    Some((label, n)) => {
+                        // `.0` would be encoded like no number at all.
+                        let n = number(n).map_err(|_| unsupported())?;
+                        match u8::try_from(n) {
+                            Ok(0) => return Err(unsupported()),
+                            Ok(n) => (label, Some(n)),
+                            Err(_) => {
+                                return Err(VersionError::OutOfRange {
+                                    version: version.to_string(),
+                                    component: "pre-release",
+                                    value: n,
+                                    max: u8::MAX as u64,
+                                })
+                            }
+                        }
+                    }
+                    None => (pre, None),
+                };
+                match label {
+                    "dev" => Stage::Development(n),
+                    "alpha" => Stage::Alpha(n),
+                    "beta" => Stage::Beta(n),
+                    "rc" => Stage::ReleaseCandidate(n),
+                    _ => return Err(unsupported()),
+                }
+            }
+        };
+
+        Ok(BundleVersion {
+            major: major as u32,
+            minor: minor as u8,
+            patch: patch as u8,
+            stage,
+            build: build.map(str::to_string),
+        })
+    }
+}
+
+impl TryFrom<&str> for BundleVersion {
+    type Error = VersionError;
+    fn try_from(version: &str) -> Result<Self, VersionError> {
+        version.parse()
+    }
+}
+
+/// Formats as a semantic version, which parses back to the same value.
+impl fmt::Display for BundleVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        let (label, n) = match self.stage {
+            Stage::Development(n) => ("dev", n),
+            Stage::Alpha(n) => ("alpha", n),
+            Stage::Beta(n) => ("beta", n),
+            Stage::ReleaseCandidate(n) => ("rc", n),
+            Stage::Release => ("", None),
+        };
+        if !label.is_empty() {
+            write!(f, "-{}", label)?;
+        }
+        if let Some(n) = n {
+            write!(f, ".{}", n)?;
+        }
+        if let Some(build) = &self.build {
+            write!(f, "+{}", build)?;
+        }
+        Ok(())
+    }
+}
+
+impl PartialEq for BundleVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for BundleVersion {}
+
+impl PartialOrd for BundleVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for BundleVersion {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch, self.stage).cmp(&(
+            other.major,
+            other.minor,
+            other.patch,
+            other.stage,
+        ))
+    }
+}
+
+// End of File
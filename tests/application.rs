@@ -0,0 +1,89 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Look up resources from an `Application`, and check its presentation.  On
+//! macOS, `bundle()` relaunches the process, so these tests only run against
+//! the default backend, which shares the lookup logic.
+
+#![cfg(not(target_os = "macos"))]
+
+use relaunch::{InstallDir, Presentation, Trampoline};
+use std::path::Path;
+
+#[test]
+fn resource_lookup() {
+    let source =
+        std::env::temp_dir().join(format!("relaunch-test-application-{}", std::process::id()));
+    std::fs::create_dir_all(source.join("shaders")).unwrap();
+    std::fs::write(source.join("shaders/blit.wgsl"), "source").unwrap();
+
+    let app = Trampoline::new("Lookup", "org.example.Lookup")
+        .source_dir(&source)
+        .bundle(InstallDir::Temp)
+        .unwrap();
+    let exe_dir = std::env::current_exe()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .to_path_buf();
+    assert_eq!(app.executable_dir(), exe_dir);
+
+    // The test executable is not inside an app bundle.
+    assert_eq!(app.resource_dir(), None);
+    assert_eq!(
+        app.resource_search_path(),
+        [exe_dir.clone(), source.clone()]
+    );
+
+    assert_eq!(
+        app.resource_path("shaders/blit.wgsl"),
+        Some(source.join("shaders/blit.wgsl"))
+    );
+    assert_eq!(app.resource_path("shaders/missing.wgsl"), None);
+    assert_eq!(app.resource_path("../shaders/blit.wgsl"), None);
+    assert_eq!(app.resource_path(source.join("shaders/blit.wgsl")), None);
+
+    // A resource next to the executable takes precedence over the source
+    // directory.
+    let name = format!("relaunch-test-application-{}.txt", std::process::id());
+    std::fs::write(source.join(&name), "source").unwrap();
+    std::fs::write(exe_dir.join(&name), "executable").unwrap();
+    let found = app.resource_path(&name);
+    std::fs::remove_file(exe_dir.join(&name)).unwrap();
+    assert_eq!(found, Some(exe_dir.join(&name)));
+    assert_eq!(
+        app.resource_path(Path::new(".").join(&name)),
+        Some(source.join(".").join(&name))
+    );
+}
+
+#[test]
+fn presentation() {
+    let mut trampoline = Trampoline::new("Agent", "org.example.Agent");
+    let info = trampoline.info_plist("agent").unwrap();
+    assert!(!info.contains_key("LSUIElement"));
+    assert!(!info.contains_key("LSBackgroundOnly"));
+    let app = trampoline.bundle(InstallDir::Temp).unwrap();
+    assert_eq!(app.presentation(), Presentation::Regular);
+
+    trampoline.presentation(Presentation::Accessory);
+    let info = trampoline.info_plist("agent").unwrap();
+    assert_eq!(info["LSUIElement"].as_bool(), Some(true));
+    assert!(!info.contains_key("LSBackgroundOnly"));
+    let app = trampoline.bundle(InstallDir::Temp).unwrap();
+    assert_eq!(app.presentation(), Presentation::Accessory);
+
+    trampoline.presentation(Presentation::BackgroundOnly);
+    let info = trampoline.info_plist("agent").unwrap();
+    assert_eq!(info["LSBackgroundOnly"].as_bool(), Some(true));
+    assert!(!info.contains_key("LSUIElement"));
+
+    // A custom key takes precedence, as with the other generated keys.
+    trampoline.plist_key("LSBackgroundOnly", false);
+    let info = trampoline.info_plist("agent").unwrap();
+    assert_eq!(info["LSBackgroundOnly"].as_bool(), Some(false));
+}
+
+// End of File
@@ -0,0 +1,294 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Generate app bundles with `BundleBuilder` and inspect the result.  The
+//! builder does not launch anything, so these tests run on every platform.
+
+mod common;
+
+use common::scratch;
+use relaunch::{plist::Format, BundleBuilder, Error, InfoPlist, Trampoline, ValidationError};
+
+#[test]
+fn layout() {
+    let (dir, exe) = scratch("layout");
+    let trampoline = Trampoline::new("Layout Test", "org.example.LayoutTest");
+    let builder = BundleBuilder::new(&trampoline, &exe, &dir);
+    assert_eq!(builder.bundle_path(), dir.join("Layout Test.app"));
+
+    let bundle = builder.build().unwrap();
+    assert_eq!(bundle.path, dir.join("Layout Test.app"));
+    assert_eq!(
+        bundle.executable,
+        bundle.path.join("Contents").join("MacOS").join("my-app")
+    );
+    assert_eq!(
+        std::fs::read(&bundle.executable).unwrap(),
+        b"not really an executable"
+    );
+    assert!(bundle.path.join("Contents").join("Resources").is_dir());
+
+    let plist = std::fs::File::open(bundle.path.join("Contents").join("Info.plist")).unwrap();
+    assert_eq!(
+        InfoPlist::read_xml(plist).unwrap(),
+        trampoline.info_plist("my-app").unwrap()
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn binary_info_plist() {
+    let (dir, exe) = scratch("binary-info-plist");
+    let trampoline = Trampoline::new("Binary Test", "org.example.BinaryTest");
+    let bundle = BundleBuilder::new(&trampoline, &exe, &dir)
+        .plist_format(Format::Binary)
+        .build()
+        .unwrap();
+
+    let plist = std::fs::read(bundle.path.join("Contents").join("Info.plist")).unwrap();
+    assert!(plist.starts_with(b"bplist00"));
+    assert_eq!(
+        InfoPlist::from_bytes(&plist).unwrap(),
+        trampoline.info_plist("my-app").unwrap()
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn validation() {
+    let check = |name: &str, ident: &str| Trampoline::new(name, ident).validate();
+    assert_eq!(check("My App", "org.example.My-App-2"), Ok(()));
+    assert_eq!(check("", "org.example.A"), Err(ValidationError::EmptyName));
+    assert!(matches!(
+        check("../evil", "org.example.A"),
+        Err(ValidationError::NameContainsSeparator { separator: '/', .. })
+    ));
+    assert!(matches!(
+        check("Pro: Edition", "org.example.A"),
+        Err(ValidationError::NameContainsSeparator { separator: ':', .. })
+    ));
+    assert!(matches!(
+        check("nul\0", "org.example.A"),
+        Err(ValidationError::NameContainsControl {
+            character: '\0',
+            ..
+        })
+    ));
+    assert!(matches!(
+        check("..", "org.example.A"),
+        Err(ValidationError::NameIsHidden { .. })
+    ));
+    assert_eq!(check(&"x".repeat(251), "org.example.A"), Ok(()));
+    assert!(matches!(
+        check(&"é".repeat(126), "org.example.A"),
+        Err(ValidationError::NameTooLong { len: 256, .. })
+    ));
+    assert_eq!(check("A", ""), Err(ValidationError::EmptyIdent));
+    assert!(matches!(
+        check("A", "org.example.My_App"),
+        Err(ValidationError::IdentInvalidCharacter { character: '_', .. })
+    ));
+    assert!(matches!(
+        check("A", "org..example"),
+        Err(ValidationError::IdentEmptyComponent { .. })
+    ));
+    assert!(matches!(
+        check("A", "org.example."),
+        Err(ValidationError::IdentEmptyComponent { .. })
+    ));
+}
+
+#[test]
+fn invalid_name_touches_nothing() {
+    let (dir, exe) = scratch("invalid-name");
+    let trampoline = Trampoline::new("../Escaped", "org.example.Escaped");
+    let err = BundleBuilder::new(&trampoline, &exe, dir.join("sub"))
+        .build()
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        Error::InvalidMetadata(ValidationError::NameContainsSeparator { .. })
+    ));
+    assert!(!dir.join("Escaped.app").exists());
+    assert!(!dir.join("sub").exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn missing_executable() {
+    let (dir, exe) = scratch("missing-executable");
+    let missing = exe.with_file_name("missing");
+    let trampoline = Trampoline::new("Missing", "org.example.Missing");
+    let err = BundleBuilder::new(&trampoline, &missing, &dir)
+        .build()
+        .unwrap_err();
+    match &err {
+        Error::CopyExecutable { from, source, .. } => {
+            assert_eq!(from, &missing);
+            assert_eq!(source.kind(), std::io::ErrorKind::NotFound);
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+    assert!(std::error::Error::source(&err).is_some());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn normalized_name() {
+    let (dir, exe) = scratch("normalized-name");
+    // "Café" with a combining acute accent, which should be composed.
+    let trampoline = Trampoline::new("Cafe\u{301}", "org.example.Cafe");
+    let bundle = BundleBuilder::new(&trampoline, &exe, &dir).build().unwrap();
+    assert_eq!(bundle.path, dir.join("Caf\u{e9}.app"));
+    let info = trampoline.info_plist("my-app").unwrap();
+    assert_eq!(info["CFBundleName"].as_str(), Some("Caf\u{e9}"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn replaces_existing() {
+    let (dir, exe) = scratch("replaces-existing");
+    let mut trampoline = Trampoline::new("Replace Test", "org.example.ReplaceTest");
+    trampoline.force_rebuild(true);
+    let builder = BundleBuilder::new(&trampoline, &exe, &dir);
+    let bundle = builder.build().unwrap();
+
+    // Leave some debris behind, which should not survive a rebuild.
+    let stale = bundle.path.join("Contents").join("Resources").join("stale");
+    std::fs::write(&stale, b"stale").unwrap();
+    builder.build().unwrap();
+    assert!(!stale.exists());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn incremental() {
+    let (dir, exe) = scratch("incremental");
+    let resource = dir.join("data.txt");
+    std::fs::write(&resource, b"data").unwrap();
+    let mut trampoline = Trampoline::new("Incremental", "org.example.Incremental");
+    trampoline.resource(&resource, "data.txt");
+    let bundle = BundleBuilder::new(&trampoline, &exe, &dir).build().unwrap();
+    let contents = bundle.path.join("Contents");
+    let plist = contents.join("Info.plist");
+
+    // The manifest is kept where code signing does not seal it, and does
+    // not record where the executable was built.
+    let manifest = contents
+        .join("_CodeSignature")
+        .join("relaunch-manifest.plist");
+    let text = std::fs::read_to_string(&manifest).unwrap();
+    assert!(text.contains("<string>SHA-256</string>"), "{}", text);
+    assert!(!text.contains(dir.to_str().unwrap()), "{}", text);
+    assert_eq!(
+        std::fs::read_dir(contents.join("Resources"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>(),
+        ["data.txt"]
+    );
+
+    // Markers within each piece of the bundle show which were rewritten.
+    let markers = [
+        contents.join("MacOS").join("marker"),
+        contents.join("Resources").join("marker"),
+    ];
+    let mark = || {
+        for marker in &markers {
+            std::fs::write(marker, b"marker").unwrap();
+        }
+    };
+    let survived = || markers.each_ref().map(|marker| marker.exists());
+
+    // Nothing has changed.
+    mark();
+    let modified = std::fs::metadata(&plist).unwrap().modified().unwrap();
+    BundleBuilder::new(&trampoline, &exe, &dir).build().unwrap();
+    assert_eq!(survived(), [true, true]);
+    assert_eq!(
+        std::fs::metadata(&plist).unwrap().modified().unwrap(),
+        modified
+    );
+
+    // Relinking an identical executable does not copy it again.
+    std::fs::write(&exe, b"not really an executable").unwrap();
+    BundleBuilder::new(&trampoline, &exe, &dir).build().unwrap();
+    assert_eq!(survived(), [true, true]);
+
+    // A resource changed.
+    std::fs::write(&resource, b"new data").unwrap();
+    BundleBuilder::new(&trampoline, &exe, &dir).build().unwrap();
+    assert_eq!(survived(), [true, false]);
+    assert_eq!(
+        std::fs::read(contents.join("Resources").join("data.txt")).unwrap(),
+        b"new data"
+    );
+
+    // The executable changed.
+    mark();
+    std::fs::write(&exe, b"a different executable").unwrap();
+    BundleBuilder::new(&trampoline, &exe, &dir).build().unwrap();
+    assert_eq!(survived(), [false, true]);
+    assert_eq!(
+        std::fs::read(&bundle.executable).unwrap(),
+        b"a different executable"
+    );
+
+    // The bundled executable was modified since it was written, so the
+    // manifest is not trusted and the whole bundle is rebuilt.
+    mark();
+    std::fs::write(&bundle.executable, b"tampered").unwrap();
+    BundleBuilder::new(&trampoline, &exe, &dir).build().unwrap();
+    assert_eq!(survived(), [false, false]);
+    assert_eq!(
+        std::fs::read(&bundle.executable).unwrap(),
+        b"a different executable"
+    );
+
+    // Only Info.plist changed.
+    mark();
+    trampoline.version("2.0.0");
+    BundleBuilder::new(&trampoline, &exe, &dir).build().unwrap();
+    assert_eq!(survived(), [true, true]);
+    let info = InfoPlist::read_xml(std::fs::File::open(&plist).unwrap()).unwrap();
+    assert_eq!(info["CFBundleShortVersionString"].as_str(), Some("2.0.0"));
+
+    // Forcing a rebuild rewrites everything.
+    trampoline.force_rebuild(true);
+    BundleBuilder::new(&trampoline, &exe, &dir).build().unwrap();
+    assert_eq!(survived(), [false, false]);
+
+    // A bundle replaced by another, e.g. a copy of an older build, has a
+    // manifest whose stamp does not match its executable.
+    trampoline.force_rebuild(false);
+    let copy = dir.join("copy.plist");
+    std::fs::copy(&manifest, &copy).unwrap();
+    std::fs::remove_dir_all(&bundle.path).unwrap();
+    BundleBuilder::new(&trampoline, &exe, &dir).build().unwrap();
+    std::fs::File::options()
+        .write(true)
+        .open(&bundle.executable)
+        .unwrap()
+        .set_modified(std::time::UNIX_EPOCH)
+        .unwrap();
+    mark();
+    std::fs::copy(&copy, &manifest).unwrap();
+    BundleBuilder::new(&trampoline, &exe, &dir).build().unwrap();
+    assert_eq!(survived(), [false, false]);
+    assert_eq!(
+        std::fs::read(contents.join("Resources").join("data.txt")).unwrap(),
+        b"new data"
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// End of File
@@ -0,0 +1,302 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Helpers shared by the integration tests: scratch directories, and
+//! synthetic Mach-O fixtures, so that Mach-O handling can be tested without
+//! a macOS toolchain.  Each image has the requested load commands followed
+//! by a `__TEXT` segment whose `__text` section holds 16 bytes of `int3`.
+
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+
+/// Create an empty scratch directory unique to the test binary and the
+/// named test, removing whatever a previous run left behind.
+pub fn scratch_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "relaunch-test-{}-{}-{}",
+        env!("CARGO_CRATE_NAME"),
+        test,
+        std::process::id()
+    ));
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Create a scratch directory with [`scratch_dir()`], containing a stand-in
+/// executable file, and return the paths of both.
+pub fn scratch(test: &str) -> (PathBuf, PathBuf) {
+    let dir = scratch_dir(test);
+    let exe = dir.join("my-app");
+    std::fs::write(&exe, b"not really an executable").unwrap();
+    (dir, exe)
+}
+
+pub const CPU_TYPE_X86_64: u32 = 0x0100_0007;
+pub const CPU_TYPE_ARM64: u32 = 0x0100_000c;
+pub const CPU_TYPE_POWERPC: u32 = 0x12;
+pub const CPU_TYPE_I386: u32 = 0x7;
+
+pub const MH_EXECUTE: u32 = 0x2;
+pub const MH_DYLIB: u32 = 0x6;
+
+pub const LC_LOAD_DYLIB: u32 = 0xc;
+pub const LC_ID_DYLIB: u32 = 0xd;
+pub const LC_LOAD_WEAK_DYLIB: u32 = 0x8000_0018;
+pub const LC_RPATH: u32 = 0x8000_001c;
+pub const LC_CODE_SIGNATURE: u32 = 0x1d;
+pub const LC_VERSION_MIN_MACOSX: u32 = 0x24;
+pub const LC_BUILD_VERSION: u32 = 0x32;
+
+pub const PLATFORM_MACOS: u32 = 1;
+
+/// The contents of the `__text` section.
+pub const TEXT: [u8; 16] = [0xcc; 16];
+
+/// Builds a thin Mach-O image.
+#[derive(Clone)]
+pub struct MachO {
+    big_endian: bool,
+    is_64: bool,
+    cputype: u32,
+    cpusubtype: u32,
+    filetype: u32,
+    commands: Vec<Command>,
+    padding: usize,
+}
+
+/// A load command, encoded when the image is built.
+#[derive(Clone)]
+enum Command {
+    Dylib(u32, String),
+    Rpath(String),
+    Words(u32, Vec<u32>),
+}
+
+impl MachO {
+    /// A little-endian 64-bit x86_64 executable.
+    pub fn executable() -> Self {
+        MachO {
+            big_endian: false,
+            is_64: true,
+            cputype: CPU_TYPE_X86_64,
+            cpusubtype: 3,
+            filetype: MH_EXECUTE,
+            commands: Vec::new(),
+            padding: 256,
+        }
+    }
+
+    /// A dynamic library with the given install name.
+    pub fn dylib(install_name: &str) -> Self {
+        let mut macho = MachO::executable();
+        macho.filetype = MH_DYLIB;
+        macho
+            .commands
+            .push(Command::Dylib(LC_ID_DYLIB, install_name.to_string()));
+        macho
+    }
+
+    /// A big-endian 32-bit PowerPC image, the opposite of the default in
+    /// every respect.
+    pub fn powerpc(mut self) -> Self {
+        self.big_endian = true;
+        self.is_64 = false;
+        self.cputype = CPU_TYPE_POWERPC;
+        self.cpusubtype = 0;
+        self
+    }
+
+    pub fn cpu(mut self, cputype: u32, cpusubtype: u32) -> Self {
+        self.cputype = cputype;
+        self.cpusubtype = cpusubtype;
+        self
+    }
+
+    /// The number of zero bytes between the load commands and the section
+    /// data.
+    pub fn padding(mut self, padding: usize) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    fn u32(&self, value: u32) -> [u8; 4] {
+        match self.big_endian {
+            true => value.to_be_bytes(),
+            false => value.to_le_bytes(),
+        }
+    }
+
+    fn u64(&self, value: u64) -> [u8; 8] {
+        match self.big_endian {
+            true => value.to_be_bytes(),
+            false => value.to_le_bytes(),
+        }
+    }
+
+    /// Append a load command with the given 32-bit fields after `cmd` and
+    /// `cmdsize`.
+    pub fn command(mut self, cmd: u32, fields: &[u32]) -> Self {
+        self.commands.push(Command::Words(cmd, fields.to_vec()));
+        self
+    }
+
+    /// Append `LC_BUILD_VERSION` for macOS, with versions encoded as
+    /// `0xMMMMmmpp`.
+    pub fn build_version(self, minos: u32, sdk: u32) -> Self {
+        self.command(LC_BUILD_VERSION, &[PLATFORM_MACOS, minos, sdk, 0])
+    }
+
+    /// Append the older `LC_VERSION_MIN_MACOSX`.
+    pub fn version_min(self, minos: u32, sdk: u32) -> Self {
+        self.command(LC_VERSION_MIN_MACOSX, &[minos, sdk])
+    }
+
+    /// Append an `LC_CODE_SIGNATURE` pointing at an empty signature.
+    pub fn signed(self) -> Self {
+        self.command(LC_CODE_SIGNATURE, &[0, 0])
+    }
+
+    pub fn load(mut self, name: &str) -> Self {
+        self.commands
+            .push(Command::Dylib(LC_LOAD_DYLIB, name.to_string()));
+        self
+    }
+
+    pub fn weak(mut self, name: &str) -> Self {
+        self.commands
+            .push(Command::Dylib(LC_LOAD_WEAK_DYLIB, name.to_string()));
+        self
+    }
+
+    pub fn rpath(mut self, path: &str) -> Self {
+        self.commands.push(Command::Rpath(path.to_string()));
+        self
+    }
+
+    /// Encode a load command, padded to the word size.
+    fn encode(&self, command: &Command) -> Vec<u8> {
+        let (cmd, mut body) = match command {
+            Command::Dylib(cmd, name) => {
+                let mut body = Vec::new();
+                for value in [24, 2, 0x1_0203, 0x1_0000] {
+                    body.extend(self.u32(value));
+                }
+                body.extend(name.as_bytes());
+                (*cmd, body)
+            }
+            Command::Rpath(path) => {
+                let mut body = self.u32(12).to_vec();
+                body.extend(path.as_bytes());
+                (LC_RPATH, body)
+            }
+            Command::Words(cmd, fields) => {
+                let body = fields.iter().flat_map(|&field| self.u32(field)).collect();
+                (*cmd, body)
+            }
+        };
+        if !matches!(command, Command::Words(..)) {
+            body.push(0);
+        }
+        let align = if self.is_64 { 8 } else { 4 };
+        let size = (8 + body.len()).div_ceil(align) * align;
+        let mut encoded = Vec::with_capacity(size);
+        encoded.extend(self.u32(cmd));
+        encoded.extend(self.u32(size as u32));
+        encoded.append(&mut body);
+        encoded.resize(size, 0);
+        encoded
+    }
+
+    /// The encoded image.
+    pub fn build(&self) -> Vec<u8> {
+        let header = if self.is_64 { 32 } else { 28 };
+        let segment = if self.is_64 { 72 + 80 } else { 56 + 68 };
+        let commands: Vec<_> = self.commands.iter().map(|c| self.encode(c)).collect();
+        let sizeofcmds = commands.iter().map(Vec::len).sum::<usize>() + segment;
+        let text = header + sizeofcmds + self.padding;
+        let total = text + TEXT.len();
+
+        let mut bytes = Vec::with_capacity(total);
+        let magic: u32 = if self.is_64 { 0xfeed_facf } else { 0xfeed_face };
+        bytes.extend(self.u32(magic));
+        bytes.extend(self.u32(self.cputype));
+        bytes.extend(self.u32(self.cpusubtype));
+        bytes.extend(self.u32(self.filetype));
+        bytes.extend(self.u32(self.commands.len() as u32 + 1));
+        bytes.extend(self.u32(sizeofcmds as u32));
+        bytes.extend(self.u32(0));
+        if self.is_64 {
+            bytes.extend(self.u32(0));
+        }
+        for command in commands {
+            bytes.extend(command);
+        }
+
+        let name = |name: &str| {
+            let mut field = [0; 16];
+            field[..name.len()].copy_from_slice(name.as_bytes());
+            field
+        };
+        // The __TEXT segment maps the whole file, as on macOS, with the
+        // __text section at the end.
+        let word = |value: usize| match self.is_64 {
+            true => self.u64(value as u64).to_vec(),
+            false => self.u32(value as u32).to_vec(),
+        };
+        bytes.extend(self.u32(if self.is_64 { 0x19 } else { 0x1 }));
+        bytes.extend(self.u32(segment as u32));
+        bytes.extend(name("__TEXT"));
+        bytes.extend(word(0));
+        bytes.extend(word(total));
+        bytes.extend(word(0));
+        bytes.extend(word(total));
+        for value in [5, 5, 1, 0] {
+            bytes.extend(self.u32(value));
+        }
+        bytes.extend(name("__text"));
+        bytes.extend(name("__TEXT"));
+        bytes.extend(word(text));
+        bytes.extend(word(TEXT.len()));
+        for value in [text as u32, 4, 0, 0, 0x8000_0400, 0, 0] {
+            bytes.extend(self.u32(value));
+        }
+        if self.is_64 {
+            bytes.extend(self.u32(0));
+        }
+
+        bytes.resize(text, 0);
+        bytes.extend(TEXT);
+        bytes
+    }
+}
+
+/// Combine thin images, each with its CPU type and subtype, into a fat
+/// file with page-aligned architectures.
+pub fn fat(images: &[(u32, u32, Vec<u8>)]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend(0xcafe_babe_u32.to_be_bytes());
+    bytes.extend((images.len() as u32).to_be_bytes());
+    let mut offset = 8 + 20 * images.len();
+    let mut data = Vec::new();
+    for (cputype, cpusubtype, image) in images {
+        offset = offset.next_multiple_of(4096);
+        for value in [*cputype, *cpusubtype, offset as u32, image.len() as u32, 12] {
+            bytes.extend(value.to_be_bytes());
+        }
+        data.push((offset, image));
+        offset += image.len();
+    }
+    for (offset, image) in data {
+        bytes.resize(offset, 0);
+        bytes.extend(image);
+    }
+    bytes
+}
+
+// End of File
@@ -0,0 +1,207 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Declare document types and uniform type identifiers in `Info.plist`.
+
+use relaunch::{
+    document::{DocumentType, Rank, Role, TypeDeclaration},
+    plist::{self, Value},
+    Error, Trampoline, ValidationError,
+};
+
+fn drawing() -> TypeDeclaration {
+    let mut drawing = TypeDeclaration::new("org.example.editor.drawing");
+    drawing
+        .description("Editor Drawing")
+        .conforms_to("public.data")
+        .conforms_to("public.content")
+        .extension("drawing")
+        .mime_type("application/x-editor-drawing")
+        .icon("Drawing.icns");
+    drawing
+}
+
+#[test]
+fn info_plist() {
+    let mut document = DocumentType::new("Editor Drawing");
+    document
+        .role(Role::Editor)
+        .rank(Rank::Owner)
+        .content_type("org.example.editor.drawing")
+        .icon("Drawing.icns");
+    let mut markdown = DocumentType::new("Markdown");
+    markdown
+        .role(Role::Viewer)
+        .extension("md")
+        .extension("markdown")
+        .extension("md")
+        .mime_type("text/markdown");
+    let mut svg = TypeDeclaration::new("public.svg-image");
+    svg.conforms_to("public.image").extension("svg");
+
+    let mut trampoline = Trampoline::new("Editor", "org.example.Editor");
+    trampoline
+        .document_type(document)
+        .document_type(markdown)
+        .exported_type(drawing())
+        .imported_type(svg);
+    let info = trampoline.info_plist("editor").unwrap();
+
+    let types = info["CFBundleDocumentTypes"].as_array().unwrap();
+    assert_eq!(types.len(), 2);
+    let mut expected = plist::Dictionary::new();
+    expected.insert("CFBundleTypeName".into(), "Editor Drawing".into());
+    expected.insert("CFBundleTypeRole".into(), "Editor".into());
+    expected.insert("LSHandlerRank".into(), "Owner".into());
+    expected.insert(
+        "LSItemContentTypes".into(),
+        Value::Array(vec!["org.example.editor.drawing".into()]),
+    );
+    expected.insert("CFBundleTypeIconFile".into(), "Drawing.icns".into());
+    assert_eq!(types[0].as_dictionary(), Some(&expected));
+    let markdown = types[1].as_dictionary().unwrap();
+    assert_eq!(markdown["CFBundleTypeRole"].as_str(), Some("Viewer"));
+    assert!(!markdown.contains_key("LSHandlerRank"));
+    assert_eq!(
+        markdown["CFBundleTypeExtensions"].as_array().unwrap().len(),
+        2
+    );
+
+    let exported = info["UTExportedTypeDeclarations"].as_array().unwrap();
+    let exported = exported[0].as_dictionary().unwrap();
+    assert_eq!(
+        exported["UTTypeIdentifier"].as_str(),
+        Some("org.example.editor.drawing")
+    );
+    assert_eq!(
+        exported["UTTypeDescription"].as_str(),
+        Some("Editor Drawing")
+    );
+    assert_eq!(exported["UTTypeConformsTo"].as_array().unwrap().len(), 2);
+    let tags = exported["UTTypeTagSpecification"].as_dictionary().unwrap();
+    assert_eq!(
+        tags["public.filename-extension"],
+        Value::Array(vec!["drawing".into()])
+    );
+    assert_eq!(
+        tags["public.mime-type"],
+        Value::Array(vec!["application/x-editor-drawing".into()])
+    );
+    let imported = info["UTImportedTypeDeclarations"].as_array().unwrap();
+    assert_eq!(
+        imported[0].as_dictionary().unwrap()["UTTypeIdentifier"].as_str(),
+        Some("public.svg-image")
+    );
+
+    // None of the keys are written without declarations.
+    let info = Trampoline::new("Editor", "org.example.Editor")
+        .info_plist("editor")
+        .unwrap();
+    assert!(!info.contains_key("CFBundleDocumentTypes"));
+    assert!(!info.contains_key("UTExportedTypeDeclarations"));
+    assert!(!info.contains_key("UTImportedTypeDeclarations"));
+}
+
+#[test]
+fn validate() {
+    let check = |configure: &dyn Fn(&mut Trampoline)| {
+        let mut trampoline = Trampoline::new("Editor", "org.example.Editor");
+        configure(&mut trampoline);
+        let result = trampoline.validate();
+        // Generating the Info.plist validates too.
+        match trampoline.info_plist("editor") {
+            Err(Error::InvalidMetadata(err)) => assert_eq!(result, Err(err)),
+            Ok(_) => assert_eq!(result, Ok(())),
+            Err(err) => panic!("unexpected error {}", err),
+        }
+        result
+    };
+
+    let mut bad = DocumentType::new("Bad");
+    bad.content_type("org.example..bad");
+    assert_eq!(
+        check(&|t| {
+            t.document_type(bad.clone());
+        }),
+        Err(ValidationError::InvalidUti {
+            uti: "org.example..bad".to_string()
+        })
+    );
+    let mut bad = TypeDeclaration::new("org.example.bad type");
+    bad.extension("bad");
+    assert!(matches!(
+        check(&|t| {
+            t.exported_type(bad.clone());
+        }),
+        Err(ValidationError::InvalidUti { .. })
+    ));
+    let mut bad = drawing();
+    bad.conforms_to("public/data");
+    assert!(matches!(
+        check(&|t| {
+            t.exported_type(bad.clone());
+        }),
+        Err(ValidationError::InvalidUti { .. })
+    ));
+    let mut bad = DocumentType::new("Bad");
+    bad.extension(".bad");
+    assert_eq!(
+        check(&|t| {
+            t.document_type(bad.clone());
+        }),
+        Err(ValidationError::InvalidExtension {
+            extension: ".bad".to_string()
+        })
+    );
+    assert_eq!(
+        check(&|t| {
+            t.document_type(DocumentType::new("Empty"));
+        }),
+        Err(ValidationError::EmptyDocumentType {
+            name: "Empty".to_string()
+        })
+    );
+    assert_eq!(
+        check(&|t| {
+            t.exported_type(drawing()).imported_type(drawing());
+        }),
+        Err(ValidationError::DuplicateTypeDeclaration {
+            uti: "org.example.editor.drawing".to_string()
+        })
+    );
+
+    // Extensions are claimed case-insensitively, separately by document
+    // types and by type declarations.
+    let mut first = DocumentType::new("First");
+    first.extension("drawing");
+    let mut second = DocumentType::new("Second");
+    second.extension("DRAWING");
+    assert_eq!(
+        check(&|t| {
+            t.document_type(first.clone()).document_type(second.clone());
+        }),
+        Err(ValidationError::DuplicateExtension {
+            extension: "DRAWING".to_string(),
+            first: "First".to_string(),
+            second: "Second".to_string(),
+        })
+    );
+    let mut other = TypeDeclaration::new("org.example.other");
+    other.extension("drawing");
+    assert!(matches!(
+        check(&|t| {
+            t.exported_type(drawing()).imported_type(other.clone());
+        }),
+        Err(ValidationError::DuplicateExtension { .. })
+    ));
+    assert_eq!(
+        check(&|t| {
+            t.exported_type(drawing()).document_type(first.clone());
+        }),
+        Ok(())
+    );
+}
+
+// End of File
@@ -0,0 +1,161 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Build, validate, and serialize code signing entitlements.
+
+mod common;
+
+use common::scratch;
+use relaunch::{
+    entitlements::{self, Entitlements, FileAccess},
+    plist::{self, Value},
+    BundleBuilder, Error, Trampoline,
+};
+
+#[test]
+fn serialize() {
+    let mut entitlements = Entitlements::new();
+    entitlements
+        .app_sandbox(true)
+        .network_client(true)
+        .user_selected_files(FileAccess::ReadWrite)
+        .app_groups(["TEAMID.org.example.shared"])
+        .allow_jit(false);
+    assert_eq!(
+        entitlements.to_xml().unwrap(),
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" ",
+            "\"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n",
+            "<plist version=\"1.0\">\n",
+            "<dict>\n",
+            "\t<key>com.apple.security.app-sandbox</key>\n",
+            "\t<true/>\n",
+            "\t<key>com.apple.security.application-groups</key>\n",
+            "\t<array>\n",
+            "\t\t<string>TEAMID.org.example.shared</string>\n",
+            "\t</array>\n",
+            "\t<key>com.apple.security.cs.allow-jit</key>\n",
+            "\t<false/>\n",
+            "\t<key>com.apple.security.files.user-selected.read-write</key>\n",
+            "\t<true/>\n",
+            "\t<key>com.apple.security.network.client</key>\n",
+            "\t<true/>\n",
+            "</dict>\n",
+            "</plist>\n",
+        )
+    );
+    assert_eq!(entitlements.validate(), Ok(()));
+
+    // The output is a property list which round-trips.
+    let value: Value = plist::from_slice(entitlements.to_xml().unwrap().as_bytes()).unwrap();
+    assert_eq!(value.as_dictionary(), Some(entitlements.as_dictionary()));
+
+    // Groups accumulate without duplicates.
+    entitlements.app_groups(["TEAMID.org.example.shared", "TEAMID.org.example.other"]);
+    let groups = entitlements.get(entitlements::APPLICATION_GROUPS).unwrap();
+    assert_eq!(groups.as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn validate() {
+    // Sandbox keys require the sandbox, unless explicitly disabled.
+    let mut entitlements = Entitlements::new();
+    entitlements.network_server(true);
+    assert_eq!(
+        entitlements.validate(),
+        Err(entitlements::Error::RequiresAppSandbox {
+            key: "com.apple.security.network.server".to_string()
+        })
+    );
+    entitlements.app_sandbox(false);
+    assert!(entitlements.validate().is_err());
+    entitlements.network_server(false);
+    assert_eq!(entitlements.validate(), Ok(()));
+
+    // Resource access and hardened runtime keys do not.
+    let mut entitlements = Entitlements::new();
+    entitlements
+        .camera(true)
+        .audio_input(true)
+        .disable_library_validation(true);
+    assert_eq!(entitlements.validate(), Ok(()));
+
+    let mut entitlements = Entitlements::new();
+    entitlements.set("com.apple.security.device.camera", "yes");
+    assert_eq!(
+        entitlements.validate(),
+        Err(entitlements::Error::InvalidType {
+            key: "com.apple.security.device.camera".to_string(),
+            expected: "a boolean",
+        })
+    );
+
+    let mut entitlements = Entitlements::new();
+    entitlements.app_groups(["TEAMID.org.example/shared"]);
+    assert_eq!(
+        entitlements.validate(),
+        Err(entitlements::Error::InvalidAppGroup {
+            group: "TEAMID.org.example/shared".to_string()
+        })
+    );
+}
+
+#[test]
+fn escape_hatch() {
+    let mut entitlements = Entitlements::new();
+    entitlements
+        .app_sandbox(true)
+        .set(
+            "com.apple.security.temporary-exception.mach-lookup.global-name",
+            vec![Value::from("org.example.service")],
+        )
+        .set("com.example.custom", 42);
+    assert_eq!(entitlements.validate(), Ok(()));
+    assert_eq!(
+        entitlements
+            .get("com.example.custom")
+            .and_then(Value::as_integer),
+        Some(42)
+    );
+    entitlements.remove("com.example.custom");
+    assert_eq!(entitlements.get("com.example.custom"), None);
+}
+
+#[test]
+fn bundle() {
+    let (dir, exe) = scratch("bundle");
+    let mut trampoline = Trampoline::new("My App", "org.example.MyApp");
+    let bundle = BundleBuilder::new(&trampoline, &exe, &dir).build().unwrap();
+    assert_eq!(bundle.entitlements, None);
+
+    let mut entitlements = Entitlements::new();
+    entitlements.camera(true);
+    trampoline.entitlements(entitlements.clone());
+    let bundle = BundleBuilder::new(&trampoline, &exe, &dir).build().unwrap();
+    let path = bundle.entitlements.unwrap();
+    assert_eq!(path, dir.join("My App.entitlements"));
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        entitlements.to_xml().unwrap()
+    );
+
+    // Invalid entitlements are rejected before anything is written.
+    std::fs::remove_dir_all(&bundle.path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    entitlements.print(true);
+    trampoline.entitlements(entitlements);
+    match BundleBuilder::new(&trampoline, &exe, &dir).build() {
+        Err(Error::Entitlements(entitlements::Error::RequiresAppSandbox { key })) => {
+            assert_eq!(key, "com.apple.security.print")
+        }
+        other => panic!("unexpected result {:?}", other.map(|bundle| bundle.path)),
+    }
+    assert!(!path.exists());
+    assert!(!dir.join("My App.app").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// End of File
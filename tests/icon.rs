@@ -0,0 +1,207 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Encode application icons as `.icns` files and write them into bundles.
+
+use relaunch::{
+    icon::{self, IconSet, Image},
+    BundleBuilder, Error, InfoPlist, Trampoline,
+};
+
+/// A square test image of `size` pixels, with a gradient so that resized
+/// images differ from the source.
+fn image(size: u32) -> Image {
+    let rgba = (0..size * size)
+        .flat_map(|i| {
+            [
+                (i % size * 255 / size) as u8,
+                (i / size * 255 / size) as u8,
+                0x80,
+                0xff,
+            ]
+        })
+        .collect();
+    Image::from_rgba(size, size, rgba).unwrap()
+}
+
+fn types(icns: &[u8]) -> Vec<String> {
+    icon::read_icns(icns)
+        .unwrap()
+        .iter()
+        .map(|(ty, _)| String::from_utf8_lossy(ty).into_owned())
+        .collect()
+}
+
+#[test]
+fn icns_container() {
+    // A reference file, as laid out by `iconutil`.
+    let icns = icon::write_icns(&[(*b"icp4", vec![1, 2, 3]), (*b"ic11", vec![4])]);
+    #[rustfmt::skip]
+    let expected: &[u8] = &[
+        b'i', b'c', b'n', b's', 0, 0, 0, 52,
+        b'T', b'O', b'C', b' ', 0, 0, 0, 24,
+        b'i', b'c', b'p', b'4', 0, 0, 0, 11,
+        b'i', b'c', b'1', b'1', 0, 0, 0, 9,
+        b'i', b'c', b'p', b'4', 0, 0, 0, 11, 1, 2, 3,
+        b'i', b'c', b'1', b'1', 0, 0, 0, 9, 4,
+    ];
+    assert_eq!(icns, expected);
+    assert_eq!(
+        icon::read_icns(&icns).unwrap()[1..],
+        [(*b"icp4", vec![1, 2, 3]), (*b"ic11", vec![4])]
+    );
+
+    for (bytes, offset) in [
+        (&b"icnx\0\0\0\x08"[..], 0),
+        (&b"icns\0\0\0\x09"[..], 4),
+        (&b"icns\0\0\0\x0fTOC \0\0\0\x04"[..], 4),
+        (&b"icns\0\0\0\x10TOC \0\0\0\x04"[..], 12),
+        (&b"icns\0\0\0\x10TOC \0\0\0\x09"[..], 12),
+        (&b"icns\0\0\0\x0cTOC "[..], 8),
+    ] {
+        match icon::read_icns(bytes) {
+            Err(icon::Error::Icns { offset: o, .. }) => assert_eq!(o, offset, "{:?}", bytes),
+            other => panic!("{:?}: {:?}", bytes, other),
+        }
+    }
+}
+
+#[test]
+fn icon_sizes() {
+    let source = image(1024);
+    let mut set = IconSet::new();
+    set.add(source.clone()).unwrap();
+    let icns = set.to_icns().unwrap();
+    let entries = icon::read_icns(&icns).unwrap();
+    assert_eq!(
+        types(&icns),
+        [
+            "TOC ", "icp4", "icp5", "icp6", "ic07", "ic08", "ic09", "ic10", "ic11", "ic12", "ic13",
+            "ic14"
+        ]
+    );
+    for ((ty, data), (expected, points, scale)) in entries[1..].iter().zip(icon::ICON_TYPES) {
+        assert_eq!(*ty, expected);
+        let image = Image::from_png(data.clone()).unwrap();
+        assert_eq!(image.width(), points * scale);
+        assert_eq!(image.height(), points * scale);
+    }
+    // An exact match is stored as-is, without re-encoding.
+    assert_eq!(entries[7].1, source.png());
+
+    // Sizes larger than every source image are left out, and exact matches
+    // are preferred over downscaling.
+    let small = image(32);
+    let mut set = IconSet::new();
+    set.add(image(64)).unwrap().add(small.clone()).unwrap();
+    let icns = set.to_icns().unwrap();
+    assert_eq!(
+        types(&icns),
+        ["TOC ", "icp4", "icp5", "icp6", "ic11", "ic12"]
+    );
+    assert_eq!(icon::read_icns(&icns).unwrap()[2].1, small.png());
+
+    assert!(matches!(
+        IconSet::new().to_icns(),
+        Err(icon::Error::NoImages)
+    ));
+    let mut set = IconSet::new();
+    set.add(image(8)).unwrap();
+    assert!(matches!(
+        set.to_icns(),
+        Err(icon::Error::TooSmall { size: 8 })
+    ));
+    let wide = Image::from_rgba(2, 1, vec![0; 8]).unwrap();
+    assert!(matches!(
+        IconSet::new().add(wide),
+        Err(icon::Error::NotSquare {
+            width: 2,
+            height: 1
+        })
+    ));
+}
+
+#[test]
+fn resize() {
+    // Uniform color is preserved exactly.
+    let rgba = [10, 20, 30, 255].repeat(9);
+    let image = Image::from_rgba(3, 3, rgba).unwrap().resize(2, 2).unwrap();
+    assert_eq!(image.rgba(), [10, 20, 30, 255].repeat(4));
+
+    // Transparent pixels contribute coverage but not color.
+    let rgba = [
+        [255, 0, 0, 255],
+        [0, 0, 0, 0],
+        [255, 0, 0, 255],
+        [0, 0, 0, 0],
+    ]
+    .concat();
+    let image = Image::from_rgba(2, 2, rgba).unwrap().resize(1, 1).unwrap();
+    assert_eq!(image.rgba(), [255, 0, 0, 128]);
+
+    // The result round-trips through PNG.
+    let image = self::image(100).resize(37, 37).unwrap();
+    let decoded = Image::from_png(image.png().to_vec()).unwrap();
+    assert_eq!(decoded.rgba(), image.rgba());
+
+    assert!(matches!(
+        Image::from_png(b"not a png".to_vec()),
+        Err(icon::Error::Png(_))
+    ));
+    assert!(Image::from_rgba(2, 2, vec![0; 15]).is_none());
+
+    // Zero dimensions are rejected rather than encoded.
+    assert!(Image::from_rgba(0, 0, vec![]).is_none());
+    assert!(Image::from_rgba(0, 4, vec![]).is_none());
+    assert!(self::image(4).resize(0, 4).is_none());
+    assert!(self::image(4).resize(4, 0).is_none());
+}
+
+#[test]
+fn bundle_icon() {
+    let dir = std::env::temp_dir().join(format!("relaunch-test-icon-{}", std::process::id()));
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    std::fs::create_dir_all(&dir).unwrap();
+    let exe = dir.join("my-app");
+    std::fs::write(&exe, b"not really an executable").unwrap();
+    let png_path = dir.join("icon.png");
+    std::fs::write(&png_path, image(256).png()).unwrap();
+
+    let mut trampoline = Trampoline::new("Icon Test", "org.example.IconTest");
+    trampoline.icon(png_path.as_path()).icon(image(16).png());
+    let bundle = BundleBuilder::new(&trampoline, &exe, &dir).build().unwrap();
+    let resources = bundle.path.join("Contents").join("Resources");
+    let icns = std::fs::read(resources.join("AppIcon.icns")).unwrap();
+    assert_eq!(types(&icns).len(), 1 + 8);
+    let info = InfoPlist::read(
+        std::fs::File::open(bundle.path.join("Contents").join("Info.plist")).unwrap(),
+    )
+    .unwrap();
+    assert_eq!(info["CFBundleIconFile"].as_str(), Some("AppIcon"));
+
+    // A bad image is reported with its path, before anything is written.
+    std::fs::remove_dir_all(&bundle.path).unwrap();
+    let bad = dir.join("bad.png");
+    std::fs::write(&bad, b"GIF89a").unwrap();
+    trampoline.icon(bad.as_path());
+    match BundleBuilder::new(&trampoline, &exe, &dir).build() {
+        Err(Error::Icon {
+            path,
+            source: icon::Error::Png(_),
+        }) => assert_eq!(path, Some(bad)),
+        other => panic!("{:?}", other),
+    }
+    assert!(!bundle.path.exists());
+
+    // Without an icon there is neither an icon file nor the key.
+    let info = Trampoline::new("No Icon", "org.example.NoIcon")
+        .info_plist("my-app")
+        .unwrap();
+    assert!(!info.contains_key("CFBundleIconFile"));
+}
+
+// End of File
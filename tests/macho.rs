@@ -0,0 +1,708 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Read and rewrite Mach-O load commands, and embed dynamic libraries into
+//! generated bundles, using synthetic fixtures.
+
+mod common;
+
+use common::{
+    fat, scratch_dir, MachO, CPU_TYPE_ARM64, CPU_TYPE_I386, CPU_TYPE_POWERPC, CPU_TYPE_X86_64, TEXT,
+};
+use relaunch::{
+    macho::{self, Arch, BuildVersion, Changes, Dylib, DylibKind, FileType, OsVersion, Platform},
+    BundleBuilder, Error, InfoPlist, Trampoline, ValidationError, Warning,
+};
+use std::path::Path;
+
+fn names(dylibs: Vec<Dylib>) -> Vec<String> {
+    dylibs.into_iter().map(|dylib| dylib.name).collect()
+}
+
+fn executable() -> MachO {
+    MachO::executable()
+        .load("/usr/lib/libSystem.B.dylib")
+        .load("/opt/homebrew/lib/libpng16.16.dylib")
+        .weak("@rpath/libfoo.dylib")
+        .rpath("/opt/homebrew/lib")
+        .rpath("@executable_path/../lib")
+}
+
+#[test]
+fn read_load_commands() {
+    let x86 = executable().build();
+    let ppc = executable().powerpc().build();
+    let universal = fat(&[
+        (CPU_TYPE_X86_64, 3, x86.clone()),
+        (CPU_TYPE_POWERPC, 0, ppc.clone()),
+    ]);
+    for bytes in [&x86, &ppc, &universal] {
+        assert!(macho::is_macho(bytes));
+        assert_eq!(
+            macho::dylibs(bytes).unwrap(),
+            vec![
+                Dylib {
+                    name: "/usr/lib/libSystem.B.dylib".to_string(),
+                    kind: DylibKind::Load,
+                },
+                Dylib {
+                    name: "/opt/homebrew/lib/libpng16.16.dylib".to_string(),
+                    kind: DylibKind::Load,
+                },
+                Dylib {
+                    name: "@rpath/libfoo.dylib".to_string(),
+                    kind: DylibKind::Weak,
+                },
+            ]
+        );
+        assert_eq!(
+            macho::rpaths(bytes).unwrap(),
+            ["/opt/homebrew/lib", "@executable_path/../lib"]
+        );
+        assert_eq!(macho::install_name(bytes).unwrap(), None);
+    }
+
+    let dylib = MachO::dylib("@rpath/libfoo.dylib").build();
+    assert_eq!(
+        macho::install_name(&dylib).unwrap().as_deref(),
+        Some("@rpath/libfoo.dylib")
+    );
+    assert!(macho::dylibs(&dylib).unwrap().is_empty());
+}
+
+#[test]
+fn rewrite_load_commands() {
+    let mut changes = Changes::default();
+    changes.dylibs.insert(
+        "/opt/homebrew/lib/libpng16.16.dylib".to_string(),
+        "@rpath/libpng16.16.dylib".to_string(),
+    );
+    changes.delete_rpaths.push("/opt/homebrew/lib".to_string());
+    changes
+        .add_rpaths
+        .push("@executable_path/../Frameworks".to_string());
+    // Already present, so not added twice.
+    changes
+        .add_rpaths
+        .push("@executable_path/../lib".to_string());
+
+    for original in [executable().build(), executable().powerpc().build()] {
+        let mut bytes = original.clone();
+        macho::rewrite(&mut bytes, &changes).unwrap();
+        assert_eq!(bytes.len(), original.len());
+        assert!(bytes.ends_with(&TEXT));
+        assert_eq!(
+            names(macho::dylibs(&bytes).unwrap()),
+            [
+                "/usr/lib/libSystem.B.dylib",
+                "@rpath/libpng16.16.dylib",
+                "@rpath/libfoo.dylib"
+            ]
+        );
+        assert_eq!(
+            macho::rpaths(&bytes).unwrap(),
+            ["@executable_path/../lib", "@executable_path/../Frameworks"]
+        );
+        // Applying the same changes again is a no-op.
+        let rewritten = bytes.clone();
+        macho::rewrite(&mut bytes, &changes).unwrap();
+        assert_eq!(bytes, rewritten);
+    }
+
+    // Every architecture of a fat file is rewritten.
+    let mut bytes = fat(&[
+        (
+            CPU_TYPE_X86_64,
+            3,
+            MachO::dylib("/opt/lib/libbar.dylib").build(),
+        ),
+        (
+            CPU_TYPE_POWERPC,
+            0,
+            MachO::dylib("/opt/lib/libbar.dylib").powerpc().build(),
+        ),
+    ]);
+    let changes = Changes {
+        install_name: Some("@rpath/libbar.dylib".to_string()),
+        ..Changes::default()
+    };
+    macho::rewrite(&mut bytes, &changes).unwrap();
+    let (x86, ppc) = (&bytes[4096..], &bytes[8192..]);
+    for image in [x86, ppc] {
+        assert_eq!(
+            macho::install_name(image).unwrap().as_deref(),
+            Some("@rpath/libbar.dylib")
+        );
+    }
+}
+
+#[test]
+fn header_padding() {
+    let mut bytes = executable().padding(0).build();
+    let original = bytes.clone();
+    let changes = Changes {
+        add_rpaths: vec!["@executable_path/../Frameworks".to_string()],
+        ..Changes::default()
+    };
+    assert!(matches!(
+        macho::rewrite(&mut bytes, &changes),
+        Err(macho::Error::HeaderPadding { available, .. }) if available == original.len() - 32 - TEXT.len()
+    ));
+    assert_eq!(bytes, original);
+
+    // Removing a run path and shortening a name frees space for the new
+    // run path.
+    let mut changes = changes;
+    changes.delete_rpaths.push("/opt/homebrew/lib".to_string());
+    changes.dylibs.insert(
+        "/opt/homebrew/lib/libpng16.16.dylib".to_string(),
+        "@rpath/libpng.dylib".to_string(),
+    );
+    macho::rewrite(&mut bytes, &changes).unwrap();
+    assert!(bytes.ends_with(&TEXT));
+}
+
+#[test]
+fn malformed() {
+    let bytes = executable().build();
+    assert!(!macho::is_macho(b"#!/bin/sh"));
+    assert_eq!(macho::dylibs(b"#!/bin/sh"), Err(macho::Error::NotMachO));
+    assert!(matches!(
+        macho::dylibs(&bytes[..100]),
+        Err(macho::Error::Malformed { offset: 20, .. })
+    ));
+    // A load command claiming to be smaller than its own header.
+    let mut bad = bytes.clone();
+    bad[36..40].copy_from_slice(&4u32.to_le_bytes());
+    assert!(matches!(
+        macho::dylibs(&bad),
+        Err(macho::Error::Malformed { offset: 36, .. })
+    ));
+    // An architecture extending past the end of a fat file.
+    let universal = fat(&[(CPU_TYPE_X86_64, 3, bytes)]);
+    assert!(matches!(
+        macho::dylibs(&universal[..universal.len() - 1]),
+        Err(macho::Error::Malformed { offset: 8, .. })
+    ));
+}
+
+/// An Intel executable for macOS 10.13 and an Apple Silicon one for macOS
+/// 11, as built by `cargo build --target` for each.
+fn universal() -> Vec<u8> {
+    fat(&[
+        (
+            CPU_TYPE_X86_64,
+            3,
+            executable()
+                .version_min(0x000a_0d00, 0x000e_0200)
+                .signed()
+                .build(),
+        ),
+        (
+            CPU_TYPE_ARM64,
+            0,
+            MachO::executable()
+                .cpu(CPU_TYPE_ARM64, 0)
+                .build_version(0x000b_0000, 0x000e_0200)
+                .load("/usr/lib/libSystem.B.dylib")
+                .build(),
+        ),
+    ])
+}
+
+#[test]
+fn inspect() {
+    let macho = macho::MachO::parse(&universal()).unwrap();
+    assert!(macho.fat);
+    assert_eq!(macho.archs(), [Arch::X86_64, Arch::Arm64]);
+    let (x86, arm) = (&macho.images[0], &macho.images[1]);
+    assert_eq!(x86.filetype, FileType::Executable);
+    assert_eq!(
+        x86.build_version,
+        Some(BuildVersion {
+            platform: Platform::MacOS,
+            minos: OsVersion::new(10, 13, 0),
+            sdk: OsVersion::new(14, 2, 0),
+        })
+    );
+    assert_eq!(arm.build_version.unwrap().minos, OsVersion::new(11, 0, 0));
+    assert_eq!(x86.rpaths, ["/opt/homebrew/lib", "@executable_path/../lib"]);
+    assert_eq!(names(arm.dylibs.clone()), ["/usr/lib/libSystem.B.dylib"]);
+    assert!(x86.code_signature && !arm.code_signature);
+    assert!(!macho.is_signed());
+    assert_eq!(
+        macho.minimum_system_version(),
+        Some(OsVersion::new(10, 13, 0))
+    );
+    assert_eq!(macho.architecture_priority(), ["arm64", "x86_64"]);
+
+    let dylib = MachO::dylib("@rpath/libfoo.dylib")
+        .powerpc()
+        .cpu(CPU_TYPE_I386, 3)
+        .build();
+    let macho = macho::MachO::parse(&dylib).unwrap();
+    assert!(!macho.fat);
+    assert_eq!(macho.archs(), [Arch::I386]);
+    assert_eq!(macho.images[0].filetype, FileType::Dylib);
+    assert_eq!(
+        macho.images[0].install_name.as_deref(),
+        Some("@rpath/libfoo.dylib")
+    );
+    assert_eq!(macho.minimum_system_version(), None);
+
+    assert_eq!(Arch::from_raw(CPU_TYPE_ARM64, 0x8000_0002), Arch::Arm64e);
+    assert_eq!(Arch::from_raw(CPU_TYPE_POWERPC, 0).to_string(), "ppc");
+    assert_eq!(
+        Arch::from_raw(42, 1).to_string(),
+        "cputype 0x2a subtype 0x1"
+    );
+    assert_eq!(OsVersion::from_raw(0x000a_0f04).to_string(), "10.15.4");
+    assert_eq!(OsVersion::from_raw(0x000e_0000).to_string(), "14.0");
+}
+
+fn be32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+#[test]
+fn build_fat() {
+    let x86 = executable().build();
+    let arm = executable().cpu(CPU_TYPE_ARM64, 0).build();
+    let ppc = executable().powerpc().build();
+
+    // The layout does not depend on the order of the inputs.
+    let bytes = macho::build_fat(&[&arm, &x86], false).unwrap();
+    assert_eq!(bytes, macho::build_fat(&[&x86, &arm], false).unwrap());
+    assert_eq!(be32(&bytes, 0), 0xcafe_babe);
+    assert_eq!(be32(&bytes, 4), 2);
+    // x86_64 is page aligned at 4 KiB, arm64 at 16 KiB.
+    let entries: Vec<[u32; 5]> = (0..2)
+        .map(|i| std::array::from_fn(|field| be32(&bytes, 8 + 20 * i + 4 * field)))
+        .collect();
+    assert_eq!(
+        entries,
+        [
+            [CPU_TYPE_X86_64, 3, 4096, x86.len() as u32, 12],
+            [CPU_TYPE_ARM64, 0, 16384, arm.len() as u32, 14],
+        ]
+    );
+    assert_eq!(&bytes[4096..4096 + x86.len()], &x86[..]);
+    assert_eq!(&bytes[16384..], &arm[..]);
+    let macho = macho::MachO::parse(&bytes).unwrap();
+    assert!(macho.fat);
+    assert_eq!(macho.archs(), [Arch::X86_64, Arch::Arm64]);
+
+    // The architectures of fat inputs are included individually.
+    let universal = fat(&[
+        (CPU_TYPE_X86_64, 3, x86.clone()),
+        (CPU_TYPE_POWERPC, 0, ppc),
+    ]);
+    let bytes = macho::build_fat(&[&universal, &arm], true).unwrap();
+    assert_eq!(be32(&bytes, 0), 0xcafe_babf);
+    assert_eq!(be32(&bytes, 4), 3);
+    assert_eq!(be32(&bytes, 8 + 32 * 2 + 24), 14);
+    let macho = macho::MachO::parse(&bytes).unwrap();
+    assert_eq!(macho.archs(), [Arch::PowerPC, Arch::X86_64, Arch::Arm64]);
+    assert_eq!(macho.images[1].dylibs, macho::dylibs(&x86).unwrap());
+
+    // A thin file is wrapped in a fat header.
+    let bytes = macho::build_fat(&[&x86], false).unwrap();
+    assert_eq!(be32(&bytes, 4), 1);
+}
+
+#[test]
+fn build_fat_errors() {
+    let x86 = executable().build();
+    let arm = MachO::dylib("@rpath/libfoo.dylib").cpu(CPU_TYPE_ARM64, 0);
+    assert_eq!(
+        macho::build_fat(&[], false),
+        Err(macho::Error::NoArchitectures)
+    );
+    assert_eq!(
+        macho::build_fat(&[&x86, &executable().padding(8).build()], false),
+        Err(macho::Error::DuplicateArchitecture(Arch::X86_64))
+    );
+    assert_eq!(
+        macho::build_fat(&[&x86, &arm.build()], false),
+        Err(macho::Error::FileTypeMismatch {
+            arch: Arch::Arm64,
+            expected: FileType::Executable,
+            found: FileType::Dylib,
+        })
+    );
+    let other = MachO::dylib("@rpath/libbar.dylib").build();
+    assert_eq!(
+        macho::build_fat(&[&arm.build(), &other], false),
+        Err(macho::Error::InstallNameMismatch {
+            arch: Arch::X86_64,
+            expected: Some("@rpath/libfoo.dylib".to_string()),
+            found: Some("@rpath/libbar.dylib".to_string()),
+        })
+    );
+    assert_eq!(
+        macho::build_fat(&[&x86, b"#!/bin/sh"], false),
+        Err(macho::Error::NotMachO)
+    );
+}
+
+fn write(path: &Path, macho: MachO) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, macho.build()).unwrap();
+}
+
+#[test]
+fn embed_dylibs() {
+    let dir = scratch_dir("embed");
+    let lib = dir.join("lib");
+    let foo = lib.join("libfoo.1.dylib");
+    let baz = dir.join("Frameworks/Baz.framework");
+    // libfoo links against libbar through its own run path, and libbar
+    // links back against libfoo through the path it was installed at.
+    write(
+        &foo,
+        MachO::dylib(foo.to_str().unwrap())
+            .load("/usr/lib/libSystem.B.dylib")
+            .load("@rpath/libbar.dylib")
+            .rpath("@loader_path"),
+    );
+    write(
+        &lib.join("libbar.dylib"),
+        MachO::dylib("@rpath/libbar.dylib").load(foo.to_str().unwrap()),
+    );
+    write(
+        &baz.join("Versions/A/Baz"),
+        MachO::dylib("@rpath/Baz.framework/Versions/A/Baz").load("@rpath/libbar.dylib"),
+    );
+    std::fs::write(baz.join("Versions/A/Info.plist"), "plist").unwrap();
+    #[cfg(unix)]
+    std::os::unix::fs::symlink("libfoo.1.dylib", lib.join("libfoo.dylib")).unwrap();
+    #[cfg(not(unix))]
+    std::fs::copy(&foo, lib.join("libfoo.dylib")).unwrap();
+
+    let exe = dir.join("my-app");
+    let linked = lib.join("libfoo.dylib");
+    write(
+        &exe,
+        MachO::executable()
+            .load("/usr/lib/libSystem.B.dylib")
+            .load(linked.to_str().unwrap())
+            .load("@rpath/Baz.framework/Versions/A/Baz")
+            .weak("@rpath/libmissing.dylib")
+            .rpath(dir.join("Frameworks").to_str().unwrap())
+            .rpath("@executable_path/lib"),
+    );
+
+    let mut trampoline = Trampoline::new("Embed", "org.example.Embed");
+    trampoline.embed_dylibs(true);
+    let bundle = BundleBuilder::new(&trampoline, &exe, &dir).build().unwrap();
+    let frameworks = bundle.path.join("Contents/Frameworks");
+    let read = |path: &Path| std::fs::read(path).unwrap();
+
+    let bytes = read(&bundle.executable);
+    assert_eq!(
+        names(macho::dylibs(&bytes).unwrap()),
+        [
+            "/usr/lib/libSystem.B.dylib",
+            "@rpath/libfoo.dylib",
+            "@rpath/Baz.framework/Versions/A/Baz",
+            "@rpath/libmissing.dylib",
+        ]
+    );
+    assert_eq!(
+        macho::rpaths(&bytes).unwrap(),
+        ["@executable_path/lib", "@executable_path/../Frameworks"]
+    );
+    // The original is untouched.
+    assert!(macho::rpaths(&read(&exe))
+        .unwrap()
+        .contains(&dir.join("Frameworks").to_string_lossy().into_owned()));
+
+    let bytes = read(&frameworks.join("libfoo.dylib"));
+    assert_eq!(
+        macho::install_name(&bytes).unwrap().as_deref(),
+        Some("@rpath/libfoo.dylib")
+    );
+    assert_eq!(
+        names(macho::dylibs(&bytes).unwrap()),
+        ["/usr/lib/libSystem.B.dylib", "@rpath/libbar.dylib"]
+    );
+    assert_eq!(macho::rpaths(&bytes).unwrap(), ["@loader_path"]);
+
+    let bytes = read(&frameworks.join("libbar.dylib"));
+    assert_eq!(
+        names(macho::dylibs(&bytes).unwrap()),
+        ["@rpath/libfoo.dylib"]
+    );
+    assert_eq!(macho::rpaths(&bytes).unwrap(), ["@loader_path"]);
+
+    let binary = frameworks.join("Baz.framework/Versions/A/Baz");
+    let bytes = read(&binary);
+    assert_eq!(
+        macho::install_name(&bytes).unwrap().as_deref(),
+        Some("@rpath/Baz.framework/Versions/A/Baz")
+    );
+    assert_eq!(macho::rpaths(&bytes).unwrap(), ["@loader_path/../../.."]);
+    assert!(frameworks
+        .join("Baz.framework/Versions/A/Info.plist")
+        .is_file());
+    assert!(!frameworks.join("libmissing.dylib").exists());
+
+    // Nothing to embed without the option.
+    trampoline.embed_dylibs(false);
+    let bundle = BundleBuilder::new(&trampoline, &exe, &dir).build().unwrap();
+    assert!(!bundle.path.join("Contents/Frameworks").exists());
+    assert_eq!(read(&bundle.executable), read(&exe));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn inspect_executable() {
+    let dir = scratch_dir("inspect");
+    let exe = dir.join("my-app");
+    std::fs::write(&exe, universal()).unwrap();
+    let mut trampoline = Trampoline::new("Inspect", "org.example.Inspect");
+    let plist = dir.join("Inspect.app/Contents/Info.plist");
+    let build = |trampoline: &Trampoline| {
+        BundleBuilder::new(trampoline, &exe, &dir).build().unwrap();
+        InfoPlist::from_bytes(&std::fs::read(&plist).unwrap()).unwrap()
+    };
+
+    let info = build(&trampoline);
+    assert_eq!(info["LSMinimumSystemVersion"].as_str(), Some("10.10.0"));
+    assert!(!info.contains_key("LSArchitecturePriority"));
+
+    trampoline.inspect_executable(true);
+    let info = build(&trampoline);
+    assert_eq!(info["LSMinimumSystemVersion"].as_str(), Some("10.13"));
+    let archs: Vec<_> = info["LSArchitecturePriority"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|arch| arch.as_str().unwrap())
+        .collect();
+    assert_eq!(archs, ["arm64", "x86_64"]);
+
+    // Explicit keys take precedence over derived ones.
+    trampoline.plist_key("LSMinimumSystemVersion", "12.0");
+    let info = build(&trampoline);
+    assert_eq!(info["LSMinimumSystemVersion"].as_str(), Some("12.0"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn system_requirements() {
+    let dir = scratch_dir("requirements");
+    let exe = dir.join("my-app");
+    std::fs::write(&exe, universal()).unwrap();
+    let mut trampoline = Trampoline::new("Requirements", "org.example.Requirements");
+    trampoline
+        .minimum_system_version("12")
+        .minimum_system_version_for(Arch::X86_64, "10.15.7")
+        .minimum_system_version_for(Arch::Arm64, "11.0")
+        .minimum_system_version_for(Arch::Arm64, "12.1")
+        .architecture_priority([Arch::X86_64, Arch::Arm64, Arch::X86_64])
+        .requires_native_execution(true);
+    let info = trampoline.info_plist("my-app").unwrap();
+    assert_eq!(info["LSMinimumSystemVersion"].as_str(), Some("12.0"));
+    let by_arch = info["LSMinimumSystemVersionByArchitecture"]
+        .as_dictionary()
+        .unwrap();
+    assert_eq!(by_arch.len(), 2);
+    assert_eq!(by_arch["x86_64"].as_str(), Some("10.15.7"));
+    assert_eq!(by_arch["arm64"].as_str(), Some("12.1"));
+    let archs: Vec<_> = info["LSArchitecturePriority"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|arch| arch.as_str().unwrap())
+        .collect();
+    assert_eq!(archs, ["x86_64", "arm64"]);
+    assert_eq!(info["LSRequiresNativeExecution"].as_bool(), Some(true));
+
+    // The explicit settings take precedence over the inspected executable,
+    // whose deployment target of 10.13 is earlier.
+    trampoline.inspect_executable(true);
+    let bundle = BundleBuilder::new(&trampoline, &exe, &dir).build().unwrap();
+    assert_eq!(bundle.warnings, []);
+    let plist = bundle.path.join("Contents/Info.plist");
+    let info = InfoPlist::from_bytes(&std::fs::read(&plist).unwrap()).unwrap();
+    assert_eq!(info["LSMinimumSystemVersion"].as_str(), Some("12.0"));
+    assert_eq!(
+        info["LSArchitecturePriority"].as_array().unwrap()[0].as_str(),
+        Some("x86_64")
+    );
+
+    // A minimum earlier than the deployment target is allowed, but warned
+    // about, as the executable would crash on the versions in between.
+    trampoline.minimum_system_version("10.12");
+    let bundle = BundleBuilder::new(&trampoline, &exe, &dir).build().unwrap();
+    assert_eq!(
+        bundle.warnings,
+        [Warning::DeploymentTarget {
+            minimum_system_version: "10.12".to_string(),
+            deployment_target: OsVersion::new(10, 13, 0),
+        }]
+    );
+    // Without the executable, there is nothing to compare against.
+    assert_eq!(trampoline.warnings().unwrap(), []);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn invalid_system_requirements() {
+    let check = |configure: &dyn Fn(&mut Trampoline)| {
+        let mut trampoline = Trampoline::new("Requirements", "org.example.Requirements");
+        configure(&mut trampoline);
+        trampoline.validate()
+    };
+    for version in ["", "11.", "a.b", "11.0.0.0", "10.256", "+11", "65536"] {
+        assert_eq!(
+            check(&|t| {
+                t.minimum_system_version(version);
+            }),
+            Err(ValidationError::InvalidSystemVersion {
+                version: version.to_string()
+            })
+        );
+    }
+    for version in ["9.2", "10.17", "0"] {
+        assert_eq!(
+            check(&|t| {
+                t.minimum_system_version(version);
+            }),
+            Err(ValidationError::SystemVersionOutOfRange {
+                version: version.to_string()
+            })
+        );
+    }
+    for version in ["10.16", "11", "14.2.1"] {
+        assert_eq!(
+            check(&|t| {
+                t.minimum_system_version(version);
+            }),
+            Ok(())
+        );
+    }
+    assert_eq!(
+        check(&|t| {
+            t.minimum_system_version_for(Arch::Arm64, "10.15");
+        }),
+        Err(ValidationError::ArchitectureSystemVersion {
+            arch: "arm64".to_string(),
+            version: "10.15".to_string(),
+            minimum: "11.0".to_string(),
+        })
+    );
+    let other = Arch::Other {
+        cputype: 7,
+        cpusubtype: 9,
+    };
+    assert!(matches!(
+        check(&|t| {
+            t.architecture_priority([Arch::Arm64, other]);
+        }),
+        Err(ValidationError::UnsupportedArchitecture { .. })
+    ));
+    assert!(matches!(
+        check(&|t| {
+            t.minimum_system_version_for(other, "11.0");
+        }),
+        Err(ValidationError::UnsupportedArchitecture { .. })
+    ));
+}
+
+#[test]
+fn universal_binary() {
+    let dir = scratch_dir("universal");
+    let (x86, arm) = (dir.join("x86_64/my-app"), dir.join("arm64/my-app"));
+    write(&x86, executable().version_min(0x000a_0d00, 0x000e_0200));
+    write(
+        &arm,
+        executable()
+            .cpu(CPU_TYPE_ARM64, 0)
+            .build_version(0x000b_0000, 0x000e_0200),
+    );
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        // The permissions come from the first build given.
+        std::fs::set_permissions(&arm, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    let mut trampoline = Trampoline::new("Universal", "org.example.Universal");
+    trampoline
+        .universal_binary([&arm, &x86])
+        .inspect_executable(true);
+    let bundle = BundleBuilder::new(&trampoline, dir.join("my-app"), &dir)
+        .build()
+        .unwrap();
+    assert_eq!(bundle.executable, bundle.path.join("Contents/MacOS/my-app"));
+    let bytes = std::fs::read(&bundle.executable).unwrap();
+    assert_eq!(
+        bytes,
+        macho::build_fat(
+            &[&std::fs::read(&x86).unwrap(), &std::fs::read(&arm).unwrap()],
+            false
+        )
+        .unwrap()
+    );
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&bundle.executable)
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o755);
+    }
+    let info =
+        InfoPlist::from_bytes(&std::fs::read(bundle.path.join("Contents/Info.plist")).unwrap())
+            .unwrap();
+    assert_eq!(info["LSMinimumSystemVersion"].as_str(), Some("10.13"));
+
+    // Two builds of the same architecture.
+    std::fs::remove_dir_all(&bundle.path).unwrap();
+    trampoline.universal_binary([&x86, &x86]);
+    match BundleBuilder::new(&trampoline, dir.join("my-app"), &dir).build() {
+        Err(Error::UniversalBinary { paths, source }) => {
+            assert_eq!(paths, [x86.clone(), x86.clone()]);
+            assert_eq!(source, macho::Error::DuplicateArchitecture(Arch::X86_64));
+        }
+        other => panic!("unexpected result {:?}", other.map(|bundle| bundle.path)),
+    }
+    assert!(!bundle.path.exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn missing_dylib() {
+    let dir = scratch_dir("missing");
+    let exe = dir.join("my-app");
+    write(&exe, MachO::executable().load("@rpath/libmissing.dylib"));
+    let mut trampoline = Trampoline::new("Missing", "org.example.Missing");
+    trampoline.embed_dylibs(true);
+    let builder = BundleBuilder::new(&trampoline, &exe, &dir);
+    match builder.build() {
+        Err(Error::MissingDylib { name, loader }) => {
+            assert_eq!(name, "@rpath/libmissing.dylib");
+            assert_eq!(loader, std::fs::canonicalize(&exe).unwrap());
+        }
+        other => panic!("unexpected result {:?}", other.map(|bundle| bundle.path)),
+    }
+    assert!(!builder.bundle_path().exists());
+
+    // Not a Mach-O file at all.
+    std::fs::write(&exe, "#!/bin/sh").unwrap();
+    assert!(matches!(
+        builder.build(),
+        Err(Error::MachO {
+            source: macho::Error::NotMachO,
+            ..
+        })
+    ));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// End of File
@@ -0,0 +1,208 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Configure a `Trampoline` from `[package.metadata.relaunch]`, going
+//! through the same embedded form the build-script helper produces.  Run
+//! with `--features build`.
+
+#![cfg(feature = "build")]
+
+use relaunch::{build, plist, Error, PackageInfo, Trampoline};
+use std::path::{Path, PathBuf};
+
+/// Write `manifest` to a scratch directory unique to the named test, and
+/// return its path.
+fn manifest(test: &str, manifest: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "relaunch-test-metadata-{}-{}",
+        test,
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("Cargo.toml");
+    std::fs::write(&path, manifest).unwrap();
+    path
+}
+
+fn package() -> PackageInfo {
+    PackageInfo::new(
+        "my-app",
+        "2.1.0-beta.3",
+        "A. Author <a@example.org>",
+        "An app.",
+    )
+}
+
+/// An `Info.plist` template setting `key` to true.
+fn template(key: &str) -> String {
+    let mut info = relaunch::InfoPlist::new();
+    info.insert(key.into(), true.into());
+    info.to_xml().unwrap()
+}
+
+/// Read the manifest as a build script would, and decode the result as the
+/// application would.
+fn trampoline(path: &Path) -> Result<Trampoline, Error> {
+    let metadata = build::read_metadata(path, package())?;
+    Trampoline::from_cargo_metadata(&plist::xml::to_string(&metadata.into()).unwrap())
+}
+
+#[test]
+fn full_manifest() {
+    let path = manifest(
+        "full",
+        r#"
+[package]
+name = "my-app"
+version = "2.1.0-beta.3"
+
+[package.metadata.relaunch]
+name = "My App"
+identifier = "org.example.MyApp"
+category = "public.app-category.developer-tools"
+info-plist = "Info.plist"
+icon = "icon.png"
+
+[package.metadata.relaunch.plist]
+NSHumanReadableCopyright = "Copyright (c) 2024 Example Corp."
+LSEnvironment = { RUST_LOG = "debug" }
+"#,
+    );
+    std::fs::write(
+        path.with_file_name("Info.plist"),
+        template("NSSupportsAutomaticTermination"),
+    )
+    .unwrap();
+    std::fs::write(path.with_file_name("icon.png"), b"icon image").unwrap();
+
+    // The files are embedded, so the application does not need them.
+    let metadata = build::read_metadata(&path, package()).unwrap();
+    let relaunch = metadata["relaunch"].as_dictionary().unwrap();
+    assert_eq!(
+        relaunch["info-plist"].as_data(),
+        Some(template("NSSupportsAutomaticTermination").as_bytes())
+    );
+    assert_eq!(
+        relaunch["icon"].as_array().unwrap()[0].as_data(),
+        Some(&b"icon image"[..])
+    );
+    std::fs::remove_file(path.with_file_name("Info.plist")).unwrap();
+    std::fs::remove_file(path.with_file_name("icon.png")).unwrap();
+
+    let trampoline =
+        Trampoline::from_cargo_metadata(&plist::xml::to_string(&metadata.into()).unwrap()).unwrap();
+    assert_eq!(trampoline.package_info(), Some(&package()));
+    let info = trampoline.info_plist("my-app").unwrap();
+    assert_eq!(info["CFBundleName"].as_str(), Some("My App"));
+    assert_eq!(
+        info["CFBundleIdentifier"].as_str(),
+        Some("org.example.MyApp")
+    );
+    assert_eq!(info["CFBundleVersion"].as_str(), Some("2.1.3003"));
+    assert_eq!(
+        info["LSApplicationCategoryType"].as_str(),
+        Some("public.app-category.developer-tools")
+    );
+    assert_eq!(
+        info["NSHumanReadableCopyright"].as_str(),
+        Some("Copyright (c) 2024 Example Corp.")
+    );
+    assert_eq!(
+        info["LSEnvironment"].as_dictionary().unwrap()["RUST_LOG"].as_str(),
+        Some("debug")
+    );
+    assert_eq!(info["NSSupportsAutomaticTermination"].as_bool(), Some(true));
+    assert_eq!(info["CFBundleIconFile"].as_str(), Some("AppIcon"));
+}
+
+#[test]
+fn builder_overrides_manifest() {
+    let path = manifest(
+        "overrides",
+        r#"
+[package.metadata.relaunch]
+identifier = "org.example.FromManifest"
+version = "1.0.0"
+"#,
+    );
+    let mut trampoline = trampoline(&path).unwrap();
+    let info = trampoline.info_plist("my-app").unwrap();
+    // The name defaults to the package name.
+    assert_eq!(info["CFBundleName"].as_str(), Some("my-app"));
+    assert_eq!(info["CFBundleVersion"].as_str(), Some("1.0.9000"));
+
+    trampoline
+        .name("Renamed")
+        .ident("org.example.FromCode")
+        .version("3.0.0");
+    let info = trampoline.info_plist("my-app").unwrap();
+    assert_eq!(info["CFBundleName"].as_str(), Some("Renamed"));
+    assert_eq!(
+        info["CFBundleIdentifier"].as_str(),
+        Some("org.example.FromCode")
+    );
+    assert_eq!(info["CFBundleVersion"].as_str(), Some("3.0.9000"));
+}
+
+#[test]
+fn invalid_manifest() {
+    for (test, text, message) in [
+        (
+            "missing",
+            "[package]\nname = \"x\"\n",
+            "no [package.metadata.relaunch]",
+        ),
+        (
+            "noident",
+            "[package.metadata.relaunch]\nname = \"X\"\n",
+            "`identifier` is required",
+        ),
+        (
+            "unknown",
+            "[package.metadata.relaunch]\nidentifier = \"a.b\"\nicno = \"x.png\"\n",
+            "unknown key `icno`",
+        ),
+        (
+            "type",
+            "[package.metadata.relaunch]\nidentifier = 7\n",
+            "`identifier` must be a string",
+        ),
+        (
+            "date",
+            "[package.metadata.relaunch]\nidentifier = \"a.b\"\nplist = { When = 1979-05-27 }\n",
+            "dates are not supported",
+        ),
+        ("syntax", "[package.metadata.relaunch\n", ""),
+    ] {
+        let path = manifest(test, text);
+        match build::read_metadata(&path, package()) {
+            Err(Error::CargoMetadata {
+                path: Some(p),
+                message: m,
+            }) => {
+                assert_eq!(p, path);
+                assert!(m.contains(message), "{}: {}", test, m);
+            }
+            other => panic!("{}: {:?}", test, other),
+        }
+    }
+
+    // Missing files are reported at build time.
+    let path = manifest(
+        "noicon",
+        "[package.metadata.relaunch]\nidentifier = \"a.b\"\nicon = \"missing.png\"\n",
+    );
+    match build::read_metadata(&path, package()) {
+        Err(Error::Io { path: p, .. }) => assert_eq!(p, path.with_file_name("missing.png")),
+        other => panic!("{:?}", other),
+    }
+
+    assert!(matches!(
+        Trampoline::from_cargo_metadata("not a property list"),
+        Err(Error::CargoMetadata { path: None, .. })
+    ));
+}
+
+// End of File
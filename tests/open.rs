@@ -0,0 +1,126 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Register URL schemes, and receive the URLs and files to open.  The
+//! requests are read from the command line, so this test runs without the
+//! standard harness: it runs itself again with arguments, and checks what
+//! the child received.  On macOS, `bundle()` relaunches the process, so the
+//! delivery is only checked against the default backend.
+
+use relaunch::{
+    document::DocumentType, plist::Value, InstallDir, OpenRequest, Trampoline, ValidationError,
+};
+
+fn trampoline() -> Trampoline {
+    let mut trampoline = Trampoline::new("Opener", "org.example.Opener");
+    trampoline.url_scheme("myapp").url_scheme("my-app+x.y");
+    trampoline
+}
+
+/// The trampoline, declaring a document type so that files are delivered.
+fn with_documents() -> Trampoline {
+    let mut document = DocumentType::new("Text");
+    document.extension("txt");
+    let mut trampoline = trampoline();
+    trampoline.document_type(document);
+    trampoline
+}
+
+fn info_plist() {
+    let mut opener = trampoline();
+    opener.url_scheme("MyApp");
+    let info = opener.info_plist("opener").unwrap();
+    let url_types = info["CFBundleURLTypes"].as_array().unwrap();
+    assert_eq!(url_types.len(), 1);
+    let url_type = url_types[0].as_dictionary().unwrap();
+    assert_eq!(
+        url_type["CFBundleURLName"].as_str(),
+        Some("org.example.Opener")
+    );
+    assert_eq!(url_type["CFBundleTypeRole"].as_str(), Some("Viewer"));
+    assert_eq!(
+        url_type["CFBundleURLSchemes"],
+        Value::Array(vec!["myapp".into(), "my-app+x.y".into()])
+    );
+
+    let info = Trampoline::new("Opener", "org.example.Opener")
+        .info_plist("opener")
+        .unwrap();
+    assert!(!info.contains_key("CFBundleURLTypes"));
+
+    for scheme in ["", "1app", "my_app", "myapp:", "my app"] {
+        let mut trampoline = trampoline();
+        trampoline.url_scheme(scheme);
+        assert_eq!(
+            trampoline.validate(),
+            Err(ValidationError::InvalidUrlScheme {
+                scheme: scheme.to_string()
+            })
+        );
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+fn delivery() {
+    let dir = std::env::temp_dir().join(format!("relaunch-test-open-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("document.txt");
+    std::fs::write(&file, "contents").unwrap();
+
+    let received = |mode: &str| {
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .arg(mode)
+            .arg("myapp://settings?tab=1")
+            .arg(&file)
+            .arg(dir.join("missing.txt"))
+            .arg("MYAPP:shouting")
+            .arg("other://not-registered")
+            .arg(&dir)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    // Without document types, paths are left for the application to
+    // interpret as it pleases.
+    assert_eq!(
+        received("--child"),
+        "url myapp://settings?tab=1\nurl MYAPP:shouting\n"
+    );
+    assert_eq!(
+        received("--child-documents"),
+        format!(
+            "url myapp://settings?tab=1\nfile {}\nurl MYAPP:shouting\nfile {}\n",
+            std::fs::canonicalize(&file).unwrap().display(),
+            std::fs::canonicalize(&dir).unwrap().display()
+        )
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Print the requests received, one per line.
+fn child(trampoline: Trampoline) {
+    let app = trampoline.bundle(InstallDir::Temp).unwrap();
+    for request in app.open_requests().try_iter() {
+        match request {
+            OpenRequest::Url(url) => println!("url {}", url),
+            OpenRequest::File(path) => println!("file {}", path.display()),
+        }
+    }
+}
+
+fn main() {
+    match std::env::args().nth(1).as_deref() {
+        Some("--child") => return child(trampoline()),
+        Some("--child-documents") => return child(with_documents()),
+        _ => {}
+    }
+    info_plist();
+    #[cfg(not(target_os = "macos"))]
+    delivery();
+}
+
+// End of File
@@ -0,0 +1,111 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Privacy usage descriptions, and the warnings for missing ones.
+
+use relaunch::{strings, BundleBuilder, Entitlements, Permission, Trampoline, Warning};
+use std::collections::BTreeSet;
+
+#[test]
+fn keys() {
+    let keys: BTreeSet<&str> = Permission::ALL.iter().map(Permission::key).collect();
+    assert_eq!(keys.len(), Permission::ALL.len());
+    assert!(keys
+        .iter()
+        .all(|key| key.starts_with("NS") && key.ends_with("UsageDescription")));
+    assert_eq!(Permission::Camera.to_string(), "NSCameraUsageDescription");
+}
+
+#[test]
+fn info_plist() {
+    let mut trampoline = Trampoline::new("My App", "org.example.MyApp");
+    trampoline
+        .usage_description(Permission::Camera, "To scan documents.")
+        .usage_description(Permission::Microphone, "To record notes.")
+        .usage_description(Permission::Microphone, "To record voice notes.");
+    let info = trampoline.info_plist("my-app").unwrap();
+    assert_eq!(
+        info["NSCameraUsageDescription"].as_str(),
+        Some("To scan documents.")
+    );
+    assert_eq!(
+        info["NSMicrophoneUsageDescription"].as_str(),
+        Some("To record voice notes.")
+    );
+    assert!(!info.contains_key("NSLocationUsageDescription"));
+
+    // A custom key takes precedence, as with the other generated keys.
+    trampoline.plist_key("NSCameraUsageDescription", "Custom.");
+    let info = trampoline.info_plist("my-app").unwrap();
+    assert_eq!(info["NSCameraUsageDescription"].as_str(), Some("Custom."));
+}
+
+#[test]
+fn warnings() {
+    let mut entitlements = Entitlements::new();
+    entitlements
+        .camera(true)
+        .audio_input(false)
+        .location(true)
+        .apple_events(true);
+    let mut trampoline = Trampoline::new("My App", "org.example.MyApp");
+    assert_eq!(trampoline.warnings().unwrap(), []);
+    trampoline
+        .entitlements(entitlements)
+        .usage_description(Permission::LocationWhenInUse, "To show nearby places.")
+        .plist_key("NSAppleEventsUsageDescription", "To control other apps.");
+    let warnings = trampoline.warnings().unwrap();
+    assert_eq!(
+        warnings,
+        [Warning::MissingUsageDescription {
+            permission: Permission::Camera,
+            entitlement: "com.apple.security.device.camera".to_string(),
+        }]
+    );
+    assert_eq!(
+        warnings[0].to_string(),
+        "entitlement com.apple.security.device.camera is enabled, but Info.plist has no NSCameraUsageDescription"
+    );
+    trampoline.usage_description(Permission::Camera, "To scan documents.");
+    assert_eq!(trampoline.warnings().unwrap(), []);
+}
+
+#[test]
+fn localized_bundle() {
+    let dir = std::env::temp_dir().join(format!("relaunch-test-permission-{}", std::process::id()));
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    std::fs::create_dir_all(&dir).unwrap();
+    let exe = dir.join("my-app");
+    std::fs::write(&exe, "not really an executable").unwrap();
+
+    let mut entitlements = Entitlements::new();
+    entitlements.camera(true).audio_input(true);
+    let mut trampoline = Trampoline::new("My App", "org.example.MyApp");
+    trampoline
+        .entitlements(entitlements)
+        .usage_description(Permission::Camera, "To scan documents.")
+        .localized_usage_description("fr", Permission::Camera, "Pour numériser des documents.");
+    let bundle = BundleBuilder::new(&trampoline, &exe, &dir).build().unwrap();
+    let path = bundle
+        .path
+        .join("Contents/Resources/fr.lproj/InfoPlist.strings");
+    let fr = strings::from_bytes(&std::fs::read(path).unwrap()).unwrap();
+    assert_eq!(
+        fr["NSCameraUsageDescription"],
+        "Pour numériser des documents."
+    );
+    assert_eq!(
+        bundle.warnings,
+        [Warning::MissingUsageDescription {
+            permission: Permission::Microphone,
+            entitlement: "com.apple.security.device.audio-input".to_string(),
+        }]
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// End of File
@@ -0,0 +1,519 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Property list serialization tests.
+
+use relaunch::plist::{self, Dictionary, Error, InfoPlist, Value};
+use std::time::{Duration, UNIX_EPOCH};
+
+const HEADER: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">
+<plist version=\"1.0\">
+";
+
+#[test]
+fn xml_scalars() {
+    let mut dict = Dictionary::new();
+    dict.insert("String".into(), "Tom & Jerry <3".into());
+    dict.insert("True".into(), true.into());
+    dict.insert("False".into(), false.into());
+    dict.insert("Integer".into(), (-42i64).into());
+    dict.insert("Real".into(), 1.5.into());
+    dict.insert(
+        "Date".into(),
+        (UNIX_EPOCH + Duration::from_secs(951827696)).into(),
+    );
+    dict.insert("Data".into(), b"relaunch".to_vec().into());
+    let expected = format!(
+        "{}<dict>
+\t<key>Data</key>
+\t<data>
+\tcmVsYXVuY2g=
+\t</data>
+\t<key>Date</key>
+\t<date>2000-02-29T12:34:56Z</date>
+\t<key>False</key>
+\t<false/>
+\t<key>Integer</key>
+\t<integer>-42</integer>
+\t<key>Real</key>
+\t<real>1.5</real>
+\t<key>String</key>
+\t<string>Tom &amp; Jerry &lt;3</string>
+\t<key>True</key>
+\t<true/>
+</dict>
+</plist>
+",
+        HEADER
+    );
+    assert_eq!(
+        plist::xml::to_string(&Value::Dictionary(dict)).unwrap(),
+        expected
+    );
+}
+
+#[test]
+fn xml_containers() {
+    let mut inner = Dictionary::new();
+    inner.insert("Empty".into(), Value::Dictionary(Dictionary::new()));
+    inner.insert("List".into(), vec![1.into(), Value::Array(vec![])].into());
+    let mut info = InfoPlist::new();
+    info.insert("<Nested>".into(), inner.into());
+    let expected = format!(
+        "{}<dict>
+\t<key>&lt;Nested&gt;</key>
+\t<dict>
+\t\t<key>Empty</key>
+\t\t<dict/>
+\t\t<key>List</key>
+\t\t<array>
+\t\t\t<integer>1</integer>
+\t\t\t<array/>
+\t\t</array>
+\t</dict>
+</dict>
+</plist>
+",
+        HEADER
+    );
+    assert_eq!(info.to_xml().unwrap(), expected);
+}
+
+#[test]
+fn trampoline_info_plist() {
+    let info = relaunch::Trampoline::new("Metadata", "org.example.Metadata")
+        .version("1.2.3")
+        .info_plist("metadata")
+        .unwrap();
+    assert_eq!(info["CFBundleName"].as_str(), Some("Metadata"));
+    assert_eq!(
+        info["CFBundleIdentifier"].as_str(),
+        Some("org.example.Metadata")
+    );
+    assert_eq!(info["CFBundleExecutable"].as_str(), Some("metadata"));
+    assert_eq!(info["CFBundleVersion"].as_str(), Some("1.2.39000"));
+    assert_eq!(info["NSHighResolutionCapable"].as_bool(), Some(true));
+}
+
+#[test]
+fn trampoline_custom_keys() {
+    let mut env = Dictionary::new();
+    env.insert("RUST_LOG".into(), "debug".into());
+    let mut ats = Dictionary::new();
+    ats.insert("NSAllowsArbitraryLoads".into(), false.into());
+
+    let mut trampoline = relaunch::Trampoline::new("Custom", "org.example.Custom");
+    trampoline
+        .plist_key("LSEnvironment", env)
+        .plist_key("LSMinimumSystemVersion", "12.0")
+        .plist_key("NSAppTransportSecurity", ats);
+
+    // Bulk merges are recursive into dictionaries.
+    let mut more_env = Dictionary::new();
+    more_env.insert("RUST_BACKTRACE".into(), "1".into());
+    let mut more = Dictionary::new();
+    more.insert("LSEnvironment".into(), more_env.into());
+    more.insert("com.example.Flavor".into(), "vanilla".into());
+    trampoline.merge_plist(more);
+
+    let info = trampoline.info_plist("custom").unwrap();
+    let env = info["LSEnvironment"].as_dictionary().unwrap();
+    assert_eq!(env["RUST_LOG"].as_str(), Some("debug"));
+    assert_eq!(env["RUST_BACKTRACE"].as_str(), Some("1"));
+    assert_eq!(info["LSMinimumSystemVersion"].as_str(), Some("12.0"));
+    assert_eq!(info["com.example.Flavor"].as_str(), Some("vanilla"));
+    assert!(info["NSAppTransportSecurity"].as_dictionary().is_some());
+    assert_eq!(info["CFBundleName"].as_str(), Some("Custom"));
+
+    // Managed keys cannot be overridden.
+    trampoline
+        .plist_key("CFBundleVersion", "9.9.9")
+        .plist_key("CFBundleIdentifier", "org.example.Other");
+    assert!(matches!(
+        trampoline.info_plist("custom"),
+        Err(relaunch::Error::ManagedKeyConflict { keys })
+            if keys == ["CFBundleIdentifier", "CFBundleVersion"]
+    ));
+}
+
+#[test]
+fn trampoline_template() {
+    let template = format!(
+        "{}<dict>
+\t<key>CFBundleIdentifier</key>
+\t<string>org.example.Template</string>
+\t<key>CFBundleDisplayName</key>
+\t<string>Template ✨</string>
+\t<key>LSMinimumSystemVersion</key>
+\t<string>11.0</string>
+\t<key>NSAppTransportSecurity</key>
+\t<dict>
+\t\t<key>NSAllowsArbitraryLoads</key>
+\t\t<true/>
+\t\t<key>NSAllowsLocalNetworking</key>
+\t\t<true/>
+\t</dict>
+</dict>
+</plist>
+",
+        HEADER
+    );
+    let mut ats = Dictionary::new();
+    ats.insert("NSAllowsArbitraryLoads".into(), false.into());
+    let mut trampoline = relaunch::Trampoline::new("Template", "org.example.Template");
+    trampoline
+        .info_plist_template(template.as_bytes())
+        .plist_key("NSAppTransportSecurity", ats);
+
+    // Template over defaults, custom keys over template, and the template
+    // may repeat the identifier as long as it agrees.
+    let info = trampoline.info_plist("template").unwrap();
+    assert_eq!(info["CFBundleDisplayName"].as_str(), Some("Template ✨"));
+    assert_eq!(info["LSMinimumSystemVersion"].as_str(), Some("11.0"));
+    let ats = info["NSAppTransportSecurity"].as_dictionary().unwrap();
+    assert_eq!(ats["NSAllowsArbitraryLoads"].as_bool(), Some(false));
+    assert_eq!(ats["NSAllowsLocalNetworking"].as_bool(), Some(true));
+
+    // The same template in binary form, from a file, conflicting with the
+    // identifier and the version.
+    let mut conflicting = InfoPlist::from_xml(&template).unwrap();
+    conflicting.insert("CFBundleVersion".into(), "0.0.0".into());
+    let path = std::env::temp_dir().join(format!(
+        "relaunch-test-template-{}.plist",
+        std::process::id()
+    ));
+    std::fs::write(&path, conflicting.to_binary()).unwrap();
+    trampoline
+        .ident("org.example.Other")
+        .info_plist_template(path.as_path());
+    assert!(matches!(
+        trampoline.info_plist("template"),
+        Err(relaunch::Error::ManagedKeyConflict { keys })
+            if keys == ["CFBundleIdentifier", "CFBundleVersion"]
+    ));
+    std::fs::remove_file(&path).unwrap();
+
+    // A template which cannot be read.
+    assert!(matches!(
+        trampoline.info_plist("template"),
+        Err(relaunch::Error::InvalidTemplate {
+            path: Some(p),
+            source: plist::Error::Io(_),
+        }) if p == path
+    ));
+}
+
+#[test]
+fn xml_round_trip() {
+    let mut dict = Dictionary::new();
+    dict.insert("Name".into(), "Ünïcødé & <friends>".into());
+    dict.insert("Count".into(), i64::MIN.into());
+    dict.insert("Ratio".into(), (-0.25).into());
+    dict.insert(
+        "Before1970".into(),
+        (UNIX_EPOCH - Duration::from_secs(86400 * 365 + 1)).into(),
+    );
+    dict.insert("Bytes".into(), (0..=255u8).collect::<Vec<_>>().into());
+    dict.insert("Empty".into(), Vec::<u8>::new().into());
+    dict.insert(
+        "Nested".into(),
+        vec![Value::Dictionary(Dictionary::new())].into(),
+    );
+    let value = Value::Dictionary(dict);
+    let text = plist::xml::to_string(&value).unwrap();
+    assert_eq!(plist::xml::from_str(&text).unwrap(), value);
+}
+
+#[test]
+fn xml_control_characters() {
+    // Tab, line feed, and carriage return are allowed in XML.
+    let value = Value::from("Tab\tLF\nCR\r");
+    let text = plist::xml::to_string(&value).unwrap();
+    assert_eq!(plist::xml::from_str(&text).unwrap(), value);
+
+    // Other control characters cannot be represented at all, even as a
+    // character reference, so writing fails rather than producing a
+    // document which no parser accepts.
+    for s in ["Copyright \u{1} 2024", "\u{1f}", "\u{fffe}"] {
+        let err = plist::xml::to_string(&Value::from(s)).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        let mut info = InfoPlist::new();
+        info.insert(s.into(), true.into());
+        assert!(info.to_xml().is_err());
+        // The binary format represents them faithfully.
+        let bytes = plist::binary::to_vec(&Value::from(s));
+        assert_eq!(plist::binary::from_slice(&bytes).unwrap(), Value::from(s));
+    }
+    // Nor are they accepted when parsing.
+    for text in ["\u{1}", "&#1;", "<![CDATA[\u{1}]]>"] {
+        let text = format!("<plist version=\"1.0\"><string>{}</string></plist>", text);
+        assert!(plist::xml::from_str(&text).is_err());
+    }
+}
+
+#[test]
+fn xml_parse_lenient() {
+    // Comments, entity and character references, CDATA sections, odd
+    // whitespace, and the explicit closing forms of empty elements should
+    // all be accepted.
+    let text = "\u{feff}<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<!-- A comment before the doctype. -->
+<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">
+<plist version=\"1.0\"><dict>
+  <key>A&amp;B</key> <string>&lt;&#65;&#x42;&quot;&apos;&gt;</string>
+  <!-- <key>Ignored</key> -->
+  <key>CDATA</key><string><![CDATA[<not a tag>]]></string>
+  <key>Hex</key><integer> 0x10 </integer>
+  <key>Infinity</key><real>+infinity</real>
+  <key>Yes</key><true></true>
+  <key>Blank</key><string/>
+  <key>Data</key><data>
+    cmVs
+    YXVuY2g=
+  </data>
+</dict></plist>
+";
+    let info = InfoPlist::from_xml(text).unwrap();
+    assert_eq!(info["A&B"].as_str(), Some("<AB\"'>"));
+    assert_eq!(info["CDATA"].as_str(), Some("<not a tag>"));
+    assert_eq!(info["Hex"].as_integer(), Some(16));
+    assert_eq!(info["Infinity"].as_real(), Some(f64::INFINITY));
+    assert_eq!(info["Yes"].as_bool(), Some(true));
+    assert_eq!(info["Blank"].as_str(), Some(""));
+    assert_eq!(info["Data"].as_data(), Some(&b"relaunch"[..]));
+    assert!(!info.contains_key("Ignored"));
+}
+
+/// Parse `text` and return the line, column, and message of the error.
+fn xml_error(text: &str) -> (usize, usize, String) {
+    match plist::xml::from_str(text) {
+        Err(Error::Xml {
+            line,
+            column,
+            message,
+        }) => (line, column, message),
+        other => panic!("expected an XML error, got {:?}", other),
+    }
+}
+
+#[test]
+fn xml_parse_errors() {
+    assert_eq!(
+        xml_error("<plist>\n<dict>\n\t<key>A</key>\n\t<string>x</dict>\n</plist>"),
+        (4, 11, "expected `</string>`, found `</dict>`".to_string())
+    );
+    assert_eq!(
+        xml_error("<dict>\n  <string>x</string>\n</dict>"),
+        (2, 3, "expected `<key>`, found `<string>`".to_string())
+    );
+    assert_eq!(
+        xml_error("<array><integer>12a</integer></array>"),
+        (1, 17, "invalid integer `12a`".to_string())
+    );
+    for bad in ["0x-5", "0x+5", "-0x-5", "0x"] {
+        assert_eq!(
+            xml_error(&format!("<integer>{}</integer>", bad)),
+            (1, 10, format!("invalid integer `{}`", bad))
+        );
+    }
+    assert_eq!(
+        xml_error("<dict><key>K</key></dict>"),
+        (1, 19, "missing value for key `K`".to_string())
+    );
+    assert_eq!(
+        xml_error("<dict><key>K</key><true/><key>K</key><false/></dict>"),
+        (1, 26, "duplicate key `K`".to_string())
+    );
+    assert_eq!(
+        xml_error("<string>&bogus;</string>"),
+        (1, 9, "unknown entity reference `&bogus;`".to_string())
+    );
+    assert_eq!(
+        xml_error("<plist><dict>"),
+        (1, 14, "unexpected end of input".to_string())
+    );
+    assert_eq!(
+        xml_error("<date>2023-02-29T00:00:00Z</date>"),
+        (1, 7, "invalid date `2023-02-29T00:00:00Z`".to_string())
+    );
+    assert_eq!(
+        xml_error("<true/><true/>"),
+        (
+            1,
+            8,
+            "unexpected content after end of property list".to_string()
+        )
+    );
+    assert!(matches!(
+        InfoPlist::from_xml("<array/>"),
+        Err(Error::NotADictionary)
+    ));
+}
+
+#[test]
+fn xml_nesting() {
+    let nested = |depth: usize| "<array>".repeat(depth) + &"</array>".repeat(depth);
+    assert!(plist::xml::from_str(&nested(512)).is_ok());
+    assert_eq!(
+        xml_error(&nested(513)),
+        (1, 3585, "containers are nested too deeply".to_string())
+    );
+    // Deep enough to overflow the stack, were it not for the limit.
+    let text = "<dict><key>K</key>".repeat(100_000);
+    assert_eq!(
+        xml_error(&text).2,
+        "containers are nested too deeply".to_string()
+    );
+}
+
+/// A value exercising every type, along with long strings and containers
+/// which need extended length markers.  `count` sets the length of an array,
+/// which can be used to force multi-byte object references.
+fn kitchen_sink(count: i64) -> Value {
+    let mut dict = Dictionary::new();
+    dict.insert("ASCII".into(), "relaunch".into());
+    dict.insert("UTF-16".into(), "Größe 😀".into());
+    dict.insert("Long".into(), "x".repeat(300).into());
+    dict.insert("True".into(), true.into());
+    dict.insert("False".into(), false.into());
+    for (i, n) in [0, 255, 256, 65536, i64::MAX, -1, i64::MIN]
+        .into_iter()
+        .enumerate()
+    {
+        dict.insert(format!("Integer{}", i), n.into());
+    }
+    dict.insert("Real".into(), std::f64::consts::PI.into());
+    dict.insert(
+        "Date".into(),
+        (UNIX_EPOCH + Duration::from_secs(1700000000)).into(),
+    );
+    dict.insert("Data".into(), vec![0xAB; 20].into());
+    dict.insert(
+        "Array".into(),
+        (0..count).map(Value::from).collect::<Vec<_>>().into(),
+    );
+    dict.insert("Empty".into(), Dictionary::new().into());
+    Value::Dictionary(dict)
+}
+
+#[test]
+fn binary_round_trip() {
+    let value = kitchen_sink(1000);
+    let bytes = plist::binary::to_vec(&value);
+    assert!(bytes.starts_with(b"bplist00"));
+    assert_eq!(plist::binary::from_slice(&bytes).unwrap(), value);
+    // Format detection should pick the right parser.
+    assert_eq!(plist::from_slice(&bytes).unwrap(), value);
+    let xml = plist::xml::to_string(&value).unwrap();
+    assert_eq!(plist::from_slice(xml.as_bytes()).unwrap(), value);
+}
+
+#[test]
+fn binary_layout() {
+    // Compare against the exact output of `plutil -convert binary1` for
+    // {"A": "B", "C": 1}.
+    let mut dict = Dictionary::new();
+    dict.insert("A".into(), "B".into());
+    dict.insert("C".into(), 1.into());
+    let expected: &[u8] = &[
+        b'b', b'p', b'l', b'i', b's', b't', b'0', b'0', // header
+        0xD2, 0x01, 0x02, 0x03, 0x04, // dict with 2 entries
+        0x51, b'A', // "A"
+        0x51, b'C', // "C"
+        0x51, b'B', // "B"
+        0x10, 0x01, // 1
+        0x08, 0x0D, 0x0F, 0x11, 0x13, // offset table
+        0, 0, 0, 0, 0, 0, 1, 1, // trailer
+        0, 0, 0, 0, 0, 0, 0, 5, // num_objects
+        0, 0, 0, 0, 0, 0, 0, 0, // top_object
+        0, 0, 0, 0, 0, 0, 0, 0x15, // offset_table_offset
+    ];
+    assert_eq!(plist::binary::to_vec(&Value::Dictionary(dict)), expected);
+}
+
+/// Parse `bytes` and return the offset of the error.
+fn binary_error(bytes: &[u8]) -> usize {
+    match plist::binary::from_slice(bytes) {
+        Err(Error::Binary { offset, .. }) => offset,
+        other => panic!("expected a binary plist error, got {:?}", other),
+    }
+}
+
+/// Assemble a binary plist from encoded objects, with one-byte offsets and
+/// object references, whose first object is the root.
+fn binary_plist(objects: &[&[u8]]) -> Vec<u8> {
+    let mut bytes = b"bplist00".to_vec();
+    let mut offsets = Vec::new();
+    for object in objects {
+        offsets.push(bytes.len() as u8);
+        bytes.extend_from_slice(object);
+    }
+    let table = bytes.len() as u64;
+    bytes.extend_from_slice(&offsets);
+    bytes.extend_from_slice(&[0, 0, 0, 0, 0, 0, 1, 1]);
+    bytes.extend_from_slice(&(objects.len() as u64).to_be_bytes());
+    bytes.extend_from_slice(&0u64.to_be_bytes());
+    bytes.extend_from_slice(&table.to_be_bytes());
+    bytes
+}
+
+#[test]
+fn binary_shared() {
+    // CoreFoundation writes identical values once, so containers may be
+    // referenced more than once: here the root array holds the same
+    // subarray twice.
+    let shared = binary_plist(&[&[0xA2, 0x01, 0x01], &[0xA1, 0x02], &[0x10, 0x07]]);
+    let inner = Value::Array(vec![Value::from(7)]);
+    assert_eq!(
+        plist::binary::from_slice(&shared).unwrap(),
+        Value::Array(vec![inner.clone(), inner])
+    );
+
+    // But sharing must not be used to decode a small file into an enormous
+    // value: each of these arrays holds the next twice, for 2^40 integers.
+    let mut objects: Vec<Vec<u8>> = (1..=40u8).map(|next| vec![0xA2, next, next]).collect();
+    objects.push(vec![0x10, 0x07]);
+    let objects: Vec<&[u8]> = objects.iter().map(Vec::as_slice).collect();
+    binary_error(&binary_plist(&objects));
+}
+
+#[test]
+fn binary_malformed() {
+    let good = plist::binary::to_vec(&vec![Value::from(1)].into());
+    assert_eq!(binary_error(&good[..20]), 0);
+    assert_eq!(
+        binary_error(b"bplist01\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0"),
+        0
+    );
+
+    // An array which contains itself.
+    let mut cycle = good.clone();
+    cycle[9] = 0x00;
+    binary_error(&cycle);
+
+    // An array which contains itself indirectly.
+    let cycle = binary_plist(&[&[0xA1, 0x01], &[0xA1, 0x00]]);
+    binary_error(&cycle);
+
+    // An object reference past the end of the offset table.
+    let mut bad_ref = good.clone();
+    bad_ref[9] = 0x07;
+    assert_eq!(binary_error(&bad_ref), 9);
+
+    // Flipping or truncating any byte must produce an error or a value, but
+    // never a panic.
+    let sink = plist::binary::to_vec(&kitchen_sink(20));
+    for i in 0..sink.len() {
+        for bit in 0..8 {
+            let mut mutated = sink.clone();
+            mutated[i] ^= 1 << bit;
+            let _ = plist::binary::from_slice(&mutated);
+        }
+        let _ = plist::binary::from_slice(&sink[..i]);
+    }
+}
+
+// End of File
@@ -0,0 +1,217 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Copy resource files and directory trees into generated bundles.
+
+mod common;
+
+use relaunch::{BundleBuilder, Error, ResourceFilter, Trampoline};
+use std::path::{Path, PathBuf};
+
+/// Create a scratch directory with [`common::scratch()`], adding a small
+/// resource tree:
+///
+/// ```text
+/// font.ttf
+/// assets/
+///     a.txt
+///     .git/HEAD
+///     shaders/x.wgsl
+///     shaders/y.glsl
+///     empty/
+/// ```
+fn scratch(test: &str) -> (PathBuf, PathBuf) {
+    let (dir, exe) = common::scratch(test);
+    let assets = dir.join("assets");
+    for sub in ["shaders", ".git", "empty"] {
+        std::fs::create_dir_all(assets.join(sub)).unwrap();
+    }
+    for (path, contents) in [
+        ("font.ttf", "font"),
+        ("assets/a.txt", "a"),
+        ("assets/.git/HEAD", "ref: refs/heads/main"),
+        ("assets/shaders/x.wgsl", "x"),
+        ("assets/shaders/y.glsl", "y"),
+    ] {
+        std::fs::write(dir.join(path), contents).unwrap();
+    }
+    (dir, exe)
+}
+
+fn resources(bundle: &Path) -> PathBuf {
+    bundle.join("Contents").join("Resources")
+}
+
+#[test]
+fn copy_resources() {
+    let (dir, exe) = scratch("copy");
+    let mut filter = ResourceFilter::new();
+    filter.include("**/*.wgsl").include("*.txt");
+    let mut trampoline = Trampoline::new("Resources", "org.example.Resources");
+    trampoline
+        .resource(dir.join("font.ttf"), "fonts/font.ttf")
+        .resource_dir(dir.join("assets"), "all")
+        .resource_dir_filtered(dir.join("assets"), "", filter);
+    let bundle = BundleBuilder::new(&trampoline, &exe, &dir).build().unwrap();
+    let resources = resources(&bundle.path);
+
+    let read = |path: &str| std::fs::read_to_string(resources.join(path)).unwrap();
+    assert_eq!(read("fonts/font.ttf"), "font");
+    assert_eq!(read("all/a.txt"), "a");
+    assert_eq!(read("all/.git/HEAD"), "ref: refs/heads/main");
+    assert_eq!(read("all/shaders/y.glsl"), "y");
+    assert!(resources.join("all/empty").is_dir());
+
+    // Only included files were copied to the root, and no empty directories.
+    assert_eq!(read("a.txt"), "a");
+    assert_eq!(read("shaders/x.wgsl"), "x");
+    assert!(!resources.join("shaders/y.glsl").exists());
+    assert!(!resources.join(".git").exists());
+    assert!(!resources.join("empty").exists());
+}
+
+#[test]
+fn exclude_directories() {
+    let (dir, exe) = scratch("exclude");
+    let mut filter = ResourceFilter::new();
+    filter.exclude("**/.git").exclude("**/*.glsl");
+    let mut trampoline = Trampoline::new("Exclude", "org.example.Exclude");
+    trampoline.resource_dir_filtered(dir.join("assets"), "assets", filter);
+    let bundle = BundleBuilder::new(&trampoline, &exe, &dir).build().unwrap();
+    let assets = resources(&bundle.path).join("assets");
+    assert!(assets.join("a.txt").exists());
+    assert!(assets.join("shaders/x.wgsl").exists());
+    assert!(assets.join("empty").is_dir());
+    assert!(!assets.join(".git").exists());
+    assert!(!assets.join("shaders/y.glsl").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn permissions_and_symlinks() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let (dir, exe) = scratch("unix");
+    let assets = dir.join("assets");
+    let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+    std::fs::set_permissions(assets.join("a.txt"), PermissionsExt::from_mode(0o751)).unwrap();
+    std::fs::set_permissions(assets.join("shaders"), PermissionsExt::from_mode(0o705)).unwrap();
+    std::os::unix::fs::symlink("a.txt", assets.join("link")).unwrap();
+    // A link to an ancestor must not be followed.
+    std::os::unix::fs::symlink("..", assets.join("shaders/up")).unwrap();
+
+    let mut trampoline = Trampoline::new("Unix", "org.example.Unix");
+    trampoline
+        .resource_dir(&assets, "assets")
+        .resource(assets.join("link"), "link");
+    let bundle = BundleBuilder::new(&trampoline, &exe, &dir).build().unwrap();
+    let copied = resources(&bundle.path).join("assets");
+    assert_eq!(mode(&copied.join("a.txt")), 0o751);
+    assert_eq!(mode(&copied.join("shaders")), 0o705);
+    assert_eq!(
+        std::fs::read_link(copied.join("link")).unwrap(),
+        Path::new("a.txt")
+    );
+    assert_eq!(
+        std::fs::read_link(copied.join("shaders/up")).unwrap(),
+        Path::new("..")
+    );
+    // A file resource which is a link is replaced by what it points to, as
+    // the link would dangle within the bundle.
+    let file = resources(&bundle.path).join("link");
+    assert!(file.symlink_metadata().unwrap().is_file());
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), "a");
+    assert_eq!(mode(&file), 0o751);
+
+    // A resource directory may itself be a link, which is followed.
+    std::os::unix::fs::symlink("assets", dir.join("linked")).unwrap();
+    let mut trampoline = Trampoline::new("Linked", "org.example.Linked");
+    trampoline.resource_dir(dir.join("linked"), "linked");
+    let bundle = BundleBuilder::new(&trampoline, &exe, &dir).build().unwrap();
+    let copied = resources(&bundle.path).join("linked");
+    assert!(copied.symlink_metadata().unwrap().is_dir());
+    assert_eq!(std::fs::read_to_string(copied.join("a.txt")).unwrap(), "a");
+
+    // Changes behind the link are picked up by the next build.
+    std::fs::write(assets.join("b.txt"), "b").unwrap();
+    let bundle = BundleBuilder::new(&trampoline, &exe, &dir).build().unwrap();
+    let copied = resources(&bundle.path).join("linked");
+    assert_eq!(std::fs::read_to_string(copied.join("b.txt")).unwrap(), "b");
+}
+
+#[cfg(unix)]
+#[test]
+fn read_only_directories() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let (dir, exe) = scratch("readonly");
+    let assets = dir.join("assets");
+    let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+    for path in [assets.join("shaders"), assets.clone()] {
+        std::fs::set_permissions(path, PermissionsExt::from_mode(0o555)).unwrap();
+    }
+
+    // The copies stay writable by their owner, so that the bundle can be
+    // replaced by the next build.
+    let mut trampoline = Trampoline::new("ReadOnly", "org.example.ReadOnly");
+    trampoline.resource_dir(&assets, "assets");
+    let bundle = BundleBuilder::new(&trampoline, &exe, &dir).build().unwrap();
+    let copied = resources(&bundle.path).join("assets");
+    assert_eq!(mode(&copied), 0o755);
+    assert_eq!(mode(&copied.join("shaders")), 0o755);
+    trampoline.force_rebuild(true);
+    BundleBuilder::new(&trampoline, &exe, &dir).build().unwrap();
+    assert_eq!(std::fs::read_to_string(copied.join("a.txt")).unwrap(), "a");
+
+    for path in [assets.clone(), assets.join("shaders")] {
+        std::fs::set_permissions(path, PermissionsExt::from_mode(0o755)).unwrap();
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn invalid_resources() {
+    let (dir, exe) = scratch("invalid");
+    let build = |trampoline: &Trampoline| {
+        let result = BundleBuilder::new(trampoline, &exe, &dir).build();
+        assert!(!dir.join("Invalid.app").exists());
+        result.unwrap_err()
+    };
+
+    let missing = dir.join("missing.png");
+    let mut trampoline = Trampoline::new("Invalid", "org.example.Invalid");
+    trampoline.resource(&missing, "missing.png");
+    match build(&trampoline) {
+        Error::Resource { path, .. } => assert_eq!(path, missing),
+        other => panic!("{:?}", other),
+    }
+
+    let mut trampoline = Trampoline::new("Invalid", "org.example.Invalid");
+    trampoline.resource(dir.join("assets"), "assets");
+    match build(&trampoline) {
+        Error::Resource { path, .. } => assert_eq!(path, dir.join("assets")),
+        other => panic!("{:?}", other),
+    }
+
+    for dest in ["../escape", "/absolute", ""] {
+        let mut trampoline = Trampoline::new("Invalid", "org.example.Invalid");
+        trampoline.resource(dir.join("font.ttf"), dest);
+        match build(&trampoline) {
+            Error::ResourceDestination { path } => assert_eq!(path, Path::new(dest)),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    let mut filter = ResourceFilter::new();
+    filter.include("[*.png");
+    let mut trampoline = Trampoline::new("Invalid", "org.example.Invalid");
+    trampoline.resource_dir_filtered(dir.join("assets"), "assets", filter);
+    match build(&trampoline) {
+        Error::ResourcePattern { pattern, .. } => assert_eq!(pattern, "[*.png"),
+        other => panic!("{:?}", other),
+    }
+}
+
+// End of File
@@ -3,13 +3,10 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::io::Error as IOError;
-
-fn main() -> Result<(), IOError> {
+fn main() -> Result<(), relaunch::Error> {
     // Bundle and relaunch the application in a temporary directory.
-    let _app =
-        relaunch::Trampoline::new("re-Test-Simple", "com.github.maaku.relauncher.tests.Simple")
-            .bundle(relaunch::InstallDir::Temp)?;
+    let _app = relaunch::trampoline!("re-Test-Simple", "com.github.maaku.relauncher.tests.Simple")
+        .bundle(relaunch::InstallDir::Temp)?;
     // Check that we are bundled.
     assert!(relaunch::Trampoline::is_bundled());
     Ok(())
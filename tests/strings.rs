@@ -0,0 +1,190 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Read and write `.strings` files, and localize generated bundles.
+
+mod common;
+
+use common::scratch;
+use relaunch::{
+    strings::{self, Strings},
+    BundleBuilder, Error, InfoPlist, Trampoline, ValidationError,
+};
+
+fn table(entries: &[(&str, &str)]) -> Strings {
+    entries
+        .iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+#[test]
+fn round_trip() {
+    let entries = table(&[
+        ("CFBundleDisplayName", "Mon \"App\" \\ é"),
+        ("NSHumanReadableCopyright", "Line 1\nLine 2\tTab\u{1}"),
+        ("Emoji", "😀"),
+    ]);
+    assert_eq!(
+        strings::to_string(&entries),
+        concat!(
+            "\"CFBundleDisplayName\" = \"Mon \\\"App\\\" \\\\ é\";\n",
+            "\"Emoji\" = \"😀\";\n",
+            "\"NSHumanReadableCopyright\" = \"Line 1\\nLine 2\\tTab\\U0001\";\n",
+        )
+    );
+    let bytes = strings::to_bytes(&entries);
+    assert_eq!(&bytes[..4], &[0xff, 0xfe, b'"', 0]);
+    assert_eq!(strings::from_bytes(&bytes).unwrap(), entries);
+}
+
+#[test]
+fn legacy_syntax() {
+    let text = r#"
+        /* A block comment,
+           over two lines. */
+        "quoted" = "value"; // A line comment.
+        unquoted = value.with/punctuation;
+        "same";
+        "escapes" = "\U00A9 \UD83D\UDE00 \101\"\'\\";
+        "quoted" = "last wins";
+    "#;
+    let expected = table(&[
+        ("quoted", "last wins"),
+        ("unquoted", "value.with/punctuation"),
+        ("same", "same"),
+        ("escapes", "© 😀 A\"'\\"),
+    ]);
+    assert_eq!(strings::from_str(text).unwrap(), expected);
+
+    // UTF-16 in either byte order, and UTF-8 with a byte order mark.
+    let be: Vec<u8> = "\u{feff}\"k\" = \"v\";"
+        .encode_utf16()
+        .flat_map(u16::to_be_bytes)
+        .collect();
+    assert_eq!(strings::from_bytes(&be).unwrap(), table(&[("k", "v")]));
+    let utf8 = b"\xef\xbb\xbf\"k\" = \"v\";";
+    assert_eq!(strings::from_bytes(utf8).unwrap(), table(&[("k", "v")]));
+}
+
+#[test]
+fn malformed() {
+    let syntax = |text: &str| match strings::from_str(text) {
+        Err(strings::Error::Syntax { line, column, .. }) => (line, column),
+        other => panic!("unexpected result {:?}", other),
+    };
+    assert_eq!(syntax("\"a\" = \"b\";\n\"c\" = \"d"), (2, 7));
+    assert_eq!(syntax("\"a\" = \"b\"\n\"c\" = \"d\";"), (2, 1));
+    assert_eq!(syntax("\"a\" = ;"), (1, 7));
+    assert_eq!(syntax("/* unterminated"), (1, 1));
+    assert_eq!(syntax("\"a\" = \"\\UD83D\";"), (1, 14));
+    assert_eq!(
+        strings::from_bytes(&[0xff, 0xfe, b'"']),
+        Err(strings::Error::Encoding)
+    );
+    assert_eq!(
+        strings::from_bytes(b"\"caf\xe9\";"),
+        Err(strings::Error::Encoding)
+    );
+}
+
+#[test]
+fn localized_bundle() {
+    let (dir, exe) = scratch("bundle");
+    let es = dir.join("es.strings");
+    std::fs::write(
+        &es,
+        "\"CFBundleDisplayName\" = \"Mi aplicación\";\n\"NSHumanReadableCopyright\" = \"© Ejemplo\";",
+    )
+    .unwrap();
+
+    let mut trampoline = Trampoline::new("My App", "org.example.MyApp");
+    trampoline
+        .localized_name("fr", "Mon application")
+        .localized_name("de", "Meine App")
+        .localized_strings_file("es", &es)
+        .localized_string("es", "CFBundleDisplayName", "Mi app");
+    let bundle = BundleBuilder::new(&trampoline, &exe, &dir).build().unwrap();
+    assert!(bundle.path.ends_with("My App.app"));
+    let resources = bundle.path.join("Contents/Resources");
+    let read = |locale: &str| {
+        let path = resources.join(format!("{}.lproj/InfoPlist.strings", locale));
+        strings::from_bytes(&std::fs::read(path).unwrap()).unwrap()
+    };
+    assert_eq!(
+        read("fr"),
+        table(&[
+            ("CFBundleDisplayName", "Mon application"),
+            ("CFBundleName", "Mon application"),
+        ])
+    );
+    assert_eq!(
+        read("es"),
+        table(&[
+            ("CFBundleDisplayName", "Mi app"),
+            ("NSHumanReadableCopyright", "© Ejemplo"),
+        ])
+    );
+    assert!(!resources.join("en.lproj").exists());
+
+    let info =
+        InfoPlist::from_bytes(&std::fs::read(bundle.path.join("Contents/Info.plist")).unwrap())
+            .unwrap();
+    assert_eq!(info["CFBundleDevelopmentRegion"].as_str(), Some("en"));
+    let locales: Vec<_> = info["CFBundleLocalizations"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|locale| locale.as_str().unwrap())
+        .collect();
+    assert_eq!(locales, ["de", "en", "es", "fr"]);
+
+    trampoline.development_region("fr");
+    let info = trampoline.info_plist("my-app").unwrap();
+    assert_eq!(info["CFBundleDevelopmentRegion"].as_str(), Some("fr"));
+    assert_eq!(info["CFBundleLocalizations"].as_array().unwrap().len(), 3);
+
+    // Neither key is written without localizations.
+    let plain = Trampoline::new("My App", "org.example.MyApp");
+    let info = plain.info_plist("my-app").unwrap();
+    assert!(!info.contains_key("CFBundleDevelopmentRegion"));
+    assert!(!info.contains_key("CFBundleLocalizations"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn invalid_localizations() {
+    let (dir, exe) = scratch("invalid");
+    let mut trampoline = Trampoline::new("Invalid", "org.example.Invalid");
+    trampoline.localized_name("../fr", "Invalide");
+    assert!(matches!(
+        trampoline.validate(),
+        Err(ValidationError::InvalidLocale { locale }) if locale == "../fr"
+    ));
+
+    let bad = dir.join("bad.strings");
+    std::fs::write(&bad, "\"unterminated").unwrap();
+    let mut trampoline = Trampoline::new("Invalid", "org.example.Invalid");
+    trampoline.localized_strings_file("fr", &bad);
+    let builder = BundleBuilder::new(&trampoline, &exe, &dir);
+    match builder.build() {
+        Err(Error::Strings { path, source }) => {
+            assert_eq!(path, bad);
+            assert!(matches!(
+                source,
+                strings::Error::Syntax {
+                    line: 1,
+                    column: 1,
+                    ..
+                }
+            ));
+        }
+        other => panic!("unexpected result {:?}", other.map(|bundle| bundle.path)),
+    }
+    assert!(!builder.bundle_path().exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// End of File
@@ -0,0 +1,188 @@
+// Copyright (c) 2023-2024 by Mark Friedenbach <mark@friedenbach.org>
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Derive `CFBundleShortVersionString` and `CFBundleVersion` from semantic
+//! versions.
+
+use relaunch::{
+    BundleVersion, Error, PackageInfo, Stage, Trampoline, ValidationError, VersionError,
+};
+
+fn parse(version: &str) -> BundleVersion {
+    version.parse().unwrap()
+}
+
+#[test]
+fn apple_strings() {
+    for (semver, short, build) in [
+        ("1.2.3", "1.2.3", "1.2.39000"),
+        ("0.3.0", "0.3.0", "0.3.9000"),
+        ("1.0.0-dev", "1.0.0", "1.0.1000"),
+        ("1.0.0-alpha.1", "1.0.0", "1.0.2001"),
+        ("2.10.0-beta.12", "2.10.0", "2.10.3012"),
+        (
+            "99999.99.99-rc.255+build.7",
+            "99999.99.99",
+            "99999.99.994255",
+        ),
+    ] {
+        let version = parse(semver);
+        assert_eq!(version.short_version_string(), short, "{}", semver);
+        assert_eq!(version.bundle_version(), build, "{}", semver);
+        assert_eq!(version.to_string(), semver);
+    }
+    assert_eq!(parse("1.0.0+abc").build(), Some("abc"));
+    let version = parse("2.10.3-rc.7");
+    assert_eq!(
+        (
+            version.major(),
+            version.minor(),
+            version.patch(),
+            version.stage()
+        ),
+        (2, 10, 3, Stage::ReleaseCandidate(Some(7)))
+    );
+
+    // A stage number of 0 would be encoded like no number, so it is not
+    // used, and equality agrees with the build version.
+    let beta = parse("1.0.0-beta");
+    let zero = BundleVersion::new(1, 0, 0)
+        .unwrap()
+        .with_stage(Stage::Beta(Some(0)));
+    assert_eq!(zero.stage(), Stage::Beta(None));
+    assert_eq!(zero, beta);
+    assert_eq!(zero.bundle_version(), beta.bundle_version());
+    assert_eq!(
+        BundleVersion::new(1, 2, 3)
+            .unwrap()
+            .with_stage(Stage::Beta(Some(4)))
+            .bundle_version(),
+        "1.2.33004"
+    );
+}
+
+#[test]
+fn ordering() {
+    let versions = [
+        "0.9.9",
+        "1.0.0-dev.1",
+        "1.0.0-alpha",
+        "1.0.0-alpha.1",
+        "1.0.0-alpha.2",
+        "1.0.0-beta.1",
+        "1.0.0-rc.1",
+        "1.0.0",
+        "1.0.1-alpha.1",
+        "1.1.0",
+        "10.0.0",
+    ];
+    for pair in versions.windows(2) {
+        assert!(parse(pair[0]) < parse(pair[1]), "{} < {}", pair[0], pair[1]);
+        // The build versions are ordered the same way, component-wise.
+        let build = |version: &str| -> Vec<u32> {
+            let build = parse(version).bundle_version();
+            build.split('.').map(|c| c.parse().unwrap()).collect()
+        };
+        assert!(build(pair[0]) < build(pair[1]), "{} < {}", pair[0], pair[1]);
+    }
+    assert_eq!(parse("1.0.0+a"), parse("1.0.0+b"));
+}
+
+#[test]
+fn unrepresentable() {
+    for bad in [
+        "", "1", "1.2", "1.2.3.4", "01.2.3", "1.x.3", "1.2.3-", "1.2.3+",
+    ] {
+        assert!(
+            matches!(
+                bad.parse::<BundleVersion>(),
+                Err(VersionError::Syntax { .. })
+            ),
+            "{:?}",
+            bad
+        );
+    }
+    for (bad, component) in [
+        ("100000.0.0", "major"),
+        ("1.100.0", "minor"),
+        ("1.0.100", "patch"),
+        ("1.0.0-beta.256", "pre-release"),
+    ] {
+        match bad.parse::<BundleVersion>() {
+            Err(VersionError::OutOfRange { component: c, .. }) => assert_eq!(c, component),
+            other => panic!("{:?} parsed as {:?}", bad, other),
+        }
+    }
+    assert!(BundleVersion::new(1, 100, 0).is_err());
+    for bad in [
+        "1.0.0-preview",
+        "1.0.0-beta.x",
+        "1.0.0-beta.1.2",
+        "1.0.0-beta.0",
+        "1.0.0-dev.0",
+    ] {
+        assert!(
+            matches!(
+                bad.parse::<BundleVersion>(),
+                Err(VersionError::UnsupportedPrerelease { .. })
+            ),
+            "{:?}",
+            bad
+        );
+    }
+}
+
+#[test]
+fn trampoline_version() {
+    let info = Trampoline::new("Versioned", "org.example.Versioned")
+        .version(parse("1.4.0-rc.2"))
+        .info_plist("versioned")
+        .unwrap();
+    assert_eq!(info["CFBundleShortVersionString"].as_str(), Some("1.4.0"));
+    assert_eq!(info["CFBundleVersion"].as_str(), Some("1.4.4002"));
+
+    let mut trampoline = Trampoline::new("Versioned", "org.example.Versioned");
+    trampoline.version("1.4");
+    assert!(matches!(
+        trampoline.validate(),
+        Err(ValidationError::InvalidVersion(VersionError::Syntax { .. }))
+    ));
+    assert!(matches!(
+        trampoline.info_plist("versioned"),
+        Err(Error::InvalidMetadata(ValidationError::InvalidVersion(_)))
+    ));
+}
+
+#[test]
+fn trampoline_macro() {
+    let trampoline = relaunch::trampoline!("Macro", "org.example.Macro");
+    let package = trampoline.package_info().unwrap();
+    assert_eq!(package.name, env!("CARGO_PKG_NAME"));
+    assert_eq!(package.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(package.authors.len(), 1);
+    assert!(package.description.is_some());
+
+    let info = trampoline.info_plist("macro").unwrap();
+    assert_eq!(
+        info["CFBundleShortVersionString"].as_str(),
+        Some(env!("CARGO_PKG_VERSION"))
+    );
+
+    // An explicit version still takes precedence.
+    let mut trampoline = relaunch::trampoline!("Macro", "org.example.Macro");
+    trampoline.version("7.0.0-beta.1");
+    let info = trampoline.info_plist("macro").unwrap();
+    assert_eq!(info["CFBundleVersion"].as_str(), Some("7.0.3001"));
+}
+
+#[test]
+fn package_info() {
+    let package = PackageInfo::new("app", "1.0.0", "A <a@example.org>:B", "");
+    assert_eq!(package.authors, ["A <a@example.org>", "B"]);
+    assert_eq!(package.description, None);
+    assert!(PackageInfo::new("app", "1.0.0", "", "").authors.is_empty());
+}
+
+// End of File